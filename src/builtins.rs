@@ -0,0 +1,256 @@
+use crate::{
+    conslist::ConsList,
+    evaluator::{Evaluator, EvaluatorError},
+    lisptype::LispType,
+    type_enums::Builtin,
+};
+
+mod chars;
+mod format;
+mod hash;
+mod list;
+mod regex;
+mod set;
+mod string;
+mod vector;
+
+/// Applies a native function to its already evaluated arguments. The
+/// evaluator is needed by builtins that call back into Lisp functions.
+pub fn apply(
+    evaluator: &mut Evaluator,
+    builtin: &Builtin,
+    args: Vec<LispType>,
+) -> Result<LispType, EvaluatorError> {
+    match builtin {
+        Builtin::CONS => {
+            let [head, tail] = take_args(builtin, args)?;
+            Ok(LispType::Cons(ConsList::cons(head, tail)))
+        }
+        Builtin::CAR => {
+            let [pair] = take_args(builtin, args)?;
+            match pair {
+                LispType::Cons(list) => list.car().cloned().ok_or_else(|| {
+                    EvaluatorError::InvalidArguments("Cannot take car of an empty list".to_string())
+                }),
+                _ => Err(EvaluatorError::TypeMismatch(
+                    "car expects a pair".to_string(),
+                )),
+            }
+        }
+        Builtin::CDR => {
+            let [pair] = take_args(builtin, args)?;
+            match pair {
+                LispType::Cons(list) => list.cdr().ok_or_else(|| {
+                    EvaluatorError::InvalidArguments("Cannot take cdr of an empty list".to_string())
+                }),
+                _ => Err(EvaluatorError::TypeMismatch(
+                    "cdr expects a pair".to_string(),
+                )),
+            }
+        }
+        Builtin::LIST => Ok(LispType::list(args)),
+        Builtin::LISTP => {
+            let [value] = take_args(builtin, args)?;
+            Ok(LispType::Bool(
+                matches!(value, LispType::Cons(list) if list.is_proper()),
+            ))
+        }
+        Builtin::PAIRP => {
+            let [value] = take_args(builtin, args)?;
+            Ok(LispType::Bool(
+                matches!(value, LispType::Cons(list) if !matches!(*list, ConsList::Nil)),
+            ))
+        }
+        // Atoms are stored by value, so identity and eqv? coincide.
+        Builtin::EQP | Builtin::EQVP => {
+            let [a, b] = take_args(builtin, args)?;
+            Ok(LispType::Bool(a.is_eqv(&b)))
+        }
+        // Structural, like hashing, even for records whose type implements
+        // `==` with extend-type.
+        Builtin::EQUALP => {
+            let [a, b] = take_args(builtin, args)?;
+            Ok(LispType::Bool(a == b))
+        }
+        Builtin::NOT => {
+            let [value] = take_args(builtin, args)?;
+            Ok(LispType::Bool(!value.is_truthy()))
+        }
+        // `(gensym)` or `(gensym "prefix")`.
+        Builtin::GENSYM => {
+            let prefix = match &args[..] {
+                [] => "g".to_string(),
+                [LispType::String(prefix)] => prefix.clone(),
+                [other] => {
+                    return Err(EvaluatorError::TypeMismatch(format!(
+                        "gensym expects a string prefix, got {}",
+                        other.show()
+                    )))
+                }
+                _ => {
+                    return Err(EvaluatorError::InvalidArguments(
+                        "gensym takes at most one argument".to_string(),
+                    ))
+                }
+            };
+            Ok(LispType::Symbol(evaluator.gensym(&prefix)))
+        }
+        Builtin::HASHMAP
+        | Builtin::HASHGET
+        | Builtin::HASHSET
+        | Builtin::HASHREMOVE
+        | Builtin::HASHCONTAINSP
+        | Builtin::HASHCOUNT
+        | Builtin::HASHKEYS
+        | Builtin::HASHVALUES
+        | Builtin::HASHMERGE
+        | Builtin::HASHTOLIST => hash::apply(builtin, args),
+        Builtin::VECTOR
+        | Builtin::MAKEVECTOR
+        | Builtin::VECTORP
+        | Builtin::VECTORREF
+        | Builtin::VECTORSET
+        | Builtin::VECTORLENGTH
+        | Builtin::VECTORSLICE
+        | Builtin::VECTORTOLIST => vector::apply(builtin, args),
+        Builtin::LISTTOVECTOR => vector::apply(builtin, seq_arg(evaluator, args, 0)?),
+        Builtin::SET
+        | Builtin::SETP
+        | Builtin::SETADD
+        | Builtin::SETREMOVE
+        | Builtin::UNION
+        | Builtin::INTERSECTION
+        | Builtin::DIFFERENCE
+        | Builtin::SUBSETP
+        | Builtin::SETTOLIST => set::apply(builtin, args),
+        Builtin::MEMBERP => set::apply(builtin, seq_arg(evaluator, args, 1)?),
+        Builtin::CHARP
+        | Builtin::CHARTOINTEGER
+        | Builtin::INTEGERTOCHAR
+        | Builtin::CHARUPCASE
+        | Builtin::CHARDOWNCASE
+        | Builtin::CHARALPHABETICP
+        | Builtin::CHARNUMERICP
+        | Builtin::CHARWHITESPACEP
+        | Builtin::CHARUPPERCASEP
+        | Builtin::CHARLOWERCASEP => chars::apply(builtin, args),
+        Builtin::STRINGREF
+        | Builtin::STRINGLENGTH
+        | Builtin::SUBSTRING
+        | Builtin::STRINGINDEX
+        | Builtin::STRINGSPLIT
+        | Builtin::STRINGTRIM
+        | Builtin::STRINGUPCASE
+        | Builtin::STRINGDOWNCASE
+        | Builtin::STRINGREPLACE
+        | Builtin::STRINGCONTAINSP
+        | Builtin::STRINGSTARTSWITHP
+        | Builtin::STRINGENDSWITHP
+        | Builtin::STRINGTONUMBER
+        | Builtin::NUMBERTOSTRING
+        | Builtin::STRINGTOSYMBOL
+        | Builtin::SYMBOLTOSTRING
+        | Builtin::STRINGTOLIST
+        | Builtin::LISTTOSTRING => string::apply(builtin, args),
+        Builtin::STRINGJOIN => string::apply(builtin, seq_arg(evaluator, args, 0)?),
+        Builtin::FORMAT => {
+            let views = evaluator.record_views(&args, true)?;
+            format::apply(args, &views)
+        }
+        Builtin::REGEX
+        | Builtin::REGEXP
+        | Builtin::REGEXMATCH
+        | Builtin::REGEXFINDALL
+        | Builtin::REGEXREPLACE
+        | Builtin::REGEXSPLIT => regex::apply(builtin, args),
+        Builtin::MAP
+        | Builtin::FOREACH
+        | Builtin::FILTER
+        | Builtin::REMOVE
+        | Builtin::REDUCE
+        | Builtin::FOLDLEFT
+        | Builtin::FOLDRIGHT
+        | Builtin::APPLY
+        | Builtin::APPEND
+        | Builtin::REVERSE
+        | Builtin::NTH
+        | Builtin::LAST
+        | Builtin::TAKE
+        | Builtin::DROP
+        | Builtin::ZIP
+        | Builtin::RANGE
+        | Builtin::ASSOC
+        | Builtin::MEMBER
+        | Builtin::SORT => list::apply(evaluator, builtin, args),
+        Builtin::LENGTH => {
+            let [seq] = take_args(builtin, args)?;
+            let len = match &seq {
+                LispType::Vector(vector) => vector.items.borrow().len(),
+                LispType::HashMap(map) => map.len(),
+                LispType::Set(set) => set.len(),
+                LispType::String(s) => s.chars().count(),
+                LispType::Cons(list) if list.is_proper() => list.len(),
+                _ => seq_items(evaluator, builtin, seq)?.len(),
+            };
+            Ok(LispType::Integer(len as i64))
+        }
+    }
+}
+
+/// Checks that exactly `N` arguments were passed and moves them out.
+fn take_args<const N: usize>(
+    builtin: &Builtin,
+    args: Vec<LispType>,
+) -> Result<[LispType; N], EvaluatorError> {
+    args.try_into().map_err(|_| {
+        EvaluatorError::InvalidArguments(format!(
+            "{} requires exactly {} argument{}",
+            builtin.to_string(),
+            N,
+            if N == 1 { "" } else { "s" }
+        ))
+    })
+}
+
+/// Replaces the argument at `index`, if any, by its elements when it is a
+/// record whose type implements `seq`, for builtins that take a sequence
+/// but no evaluator.
+fn seq_arg(
+    evaluator: &mut Evaluator,
+    mut args: Vec<LispType>,
+    index: usize,
+) -> Result<Vec<LispType>, EvaluatorError> {
+    if let Some(arg) = args.get_mut(index) {
+        *arg = evaluator.as_sequence(std::mem::replace(arg, LispType::nil()))?;
+    }
+    Ok(args)
+}
+
+/// Collects the elements of a sequence like `seq_to_vec`, which may also be
+/// a record whose type implements `seq`.
+fn seq_items(
+    evaluator: &mut Evaluator,
+    builtin: &Builtin,
+    seq: LispType,
+) -> Result<Vec<LispType>, EvaluatorError> {
+    let seq = evaluator.as_sequence(seq)?;
+    seq_to_vec(builtin, seq)
+}
+
+/// Collects the elements of a sequence: a proper list or a vector.
+fn seq_to_vec(builtin: &Builtin, seq: LispType) -> Result<Vec<LispType>, EvaluatorError> {
+    match seq {
+        LispType::Vector(vector) => Ok(vector.items.borrow().clone()),
+        LispType::Cons(list) if list.is_proper() => Ok(list.iter().cloned().collect()),
+        LispType::Cons(list) => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects a proper list, got {}",
+            builtin.to_string(),
+            list.show()
+        ))),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects a sequence, got {}",
+            builtin.to_string(),
+            other.show()
+        ))),
+    }
+}
@@ -0,0 +1,61 @@
+use super::take_args;
+use crate::{evaluator::EvaluatorError, lisptype::LispType, type_enums::Builtin};
+
+/// Applies a character builtin.
+pub fn apply(builtin: &Builtin, args: Vec<LispType>) -> Result<LispType, EvaluatorError> {
+    let [arg] = take_args(builtin, args)?;
+
+    match builtin {
+        Builtin::CHARP => Ok(LispType::Bool(matches!(arg, LispType::Char(_)))),
+        Builtin::INTEGERTOCHAR => match arg {
+            LispType::Integer(code) => u32::try_from(code)
+                .ok()
+                .and_then(char::from_u32)
+                .map(LispType::Char)
+                .ok_or_else(|| {
+                    EvaluatorError::InvalidArguments(format!(
+                        "integer->char: {} is not a Unicode scalar value",
+                        code
+                    ))
+                }),
+            other => Err(EvaluatorError::TypeMismatch(format!(
+                "integer->char expects an integer, got {}",
+                other.show()
+            ))),
+        },
+        _ => {
+            let ch = as_char(builtin, arg)?;
+            Ok(match builtin {
+                Builtin::CHARTOINTEGER => LispType::Integer(ch as i64),
+                Builtin::CHARUPCASE => LispType::Char(single_or(ch.to_uppercase(), ch)),
+                Builtin::CHARDOWNCASE => LispType::Char(single_or(ch.to_lowercase(), ch)),
+                Builtin::CHARALPHABETICP => LispType::Bool(ch.is_alphabetic()),
+                Builtin::CHARNUMERICP => LispType::Bool(ch.is_numeric()),
+                Builtin::CHARWHITESPACEP => LispType::Bool(ch.is_whitespace()),
+                Builtin::CHARUPPERCASEP => LispType::Bool(ch.is_uppercase()),
+                Builtin::CHARLOWERCASEP => LispType::Bool(ch.is_lowercase()),
+                _ => unreachable!("{} is not a character builtin", builtin.to_string()),
+            })
+        }
+    }
+}
+
+/// Case mappings can expand to several characters (`ß` upcases to `SS`);
+/// a character result is only possible when the mapping is one-to-one.
+fn single_or(mut mapped: impl Iterator<Item = char>, ch: char) -> char {
+    match (mapped.next(), mapped.next()) {
+        (Some(single), None) => single,
+        _ => ch,
+    }
+}
+
+fn as_char(builtin: &Builtin, value: LispType) -> Result<char, EvaluatorError> {
+    match value {
+        LispType::Char(ch) => Ok(ch),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects a character, got {}",
+            builtin.to_string(),
+            other.show()
+        ))),
+    }
+}
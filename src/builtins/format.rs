@@ -0,0 +1,395 @@
+use super::seq_to_vec;
+use crate::{
+    evaluator::EvaluatorError,
+    lisptype::{LispType, Repr},
+    protocol::{RecordViews, ShownRecords},
+    record::Record,
+    type_enums::Builtin,
+};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// `(format dest control args...)`, after Common Lisp. When `dest` is `#f`
+/// or `nil` the output is returned as a string; when it is `#t` it is written
+/// to standard output and `nil` is returned.
+///
+/// Directives, each optionally preceded by comma-separated parameters
+/// (numbers or `'c` characters) and the `@` modifier:
+///
+/// - `~a` / `~s`: display / write the argument, with records written by
+///   their `show` implementation as given in `views`; `~8a` pads it on
+///   the right to 8 columns, `~8@a` on the left, and `~8,'.a` pads with dots
+/// - `~d` / `~x`: decimal / hexadecimal integer; `~5,'0d` pads on the left
+///   with zeros and `~@d` always prints the sign
+/// - `~f`: number as a float; `~8,2f` is 8 columns wide with 2 decimals
+///   and `~8,2,,,'*f` pads with stars. The scale and overflow parameters
+///   in between are not supported
+/// - `~%`: newline (`~3%` for three of them), `~~`: a tilde
+/// - `~{...~}`: repeat the body over the elements of a list, a vector or
+///   a record whose `seq` implementation is given in `views`
+/// - `~^`: stop the enclosing iteration (or the whole format) when no
+///   arguments are left
+pub fn apply(args: Vec<LispType>, views: &RecordViews) -> Result<LispType, EvaluatorError> {
+    let mut args = args.into_iter();
+    let (dest, control) = match (args.next(), args.next()) {
+        (Some(dest), Some(LispType::String(control))) => (dest, control),
+        _ => {
+            return Err(EvaluatorError::InvalidArguments(
+                "format requires a destination and a control string".to_string(),
+            ))
+        }
+    };
+
+    let formatter = Formatter {
+        control: &control,
+        chars: control.chars().collect(),
+        records: &views.shown,
+        items: &views.items,
+    };
+    let mut out = String::new();
+    let mut args = Args {
+        items: args.collect(),
+        next: 0,
+    };
+    formatter.run(0, formatter.chars.len(), &mut args, &mut out)?;
+
+    match dest {
+        LispType::Bool(true) => {
+            print!("{}", out);
+            io::stdout().flush().ok();
+            Ok(LispType::nil())
+        }
+        dest if !dest.is_truthy() => Ok(LispType::String(out)),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "format destination must be #t, #f or nil, got {}",
+            other.show()
+        ))),
+    }
+}
+
+struct Args {
+    items: Vec<LispType>,
+    next: usize,
+}
+
+impl Args {
+    fn remaining(&self) -> usize {
+        self.items.len() - self.next
+    }
+}
+
+/// Whether a `~^` asked the enclosing construct to stop.
+enum Flow {
+    Done,
+    Escape,
+}
+
+/// A parsed directive: `~params@X`.
+struct Directive {
+    position: usize,
+    params: Vec<Option<Param>>,
+    at_sign: bool,
+    kind: char,
+    /// Index just past the directive character.
+    end: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Param {
+    Number(i64),
+    Char(char),
+}
+
+struct Formatter<'a> {
+    control: &'a str,
+    chars: Vec<char>,
+    records: &'a ShownRecords,
+    items: &'a HashMap<*const Record, LispType>,
+}
+
+impl Formatter<'_> {
+    fn error(&self, position: usize, message: impl Into<String>) -> EvaluatorError {
+        EvaluatorError::Format {
+            control: self.control.to_string(),
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// Interprets `chars[start..end]`, consuming arguments from `args`.
+    fn run(
+        &self,
+        start: usize,
+        end: usize,
+        args: &mut Args,
+        out: &mut String,
+    ) -> Result<Flow, EvaluatorError> {
+        let mut i = start;
+        while i < end {
+            if self.chars[i] != '~' {
+                out.push(self.chars[i]);
+                i += 1;
+                continue;
+            }
+
+            let directive = self.parse_directive(i, end)?;
+            i = directive.end;
+            match directive.kind {
+                'a' | 's' => {
+                    let arg = self.next_arg(&directive, args)?;
+                    let text = arg.repr_string(Repr {
+                        readable: directive.kind == 's',
+                        records: Some(self.records),
+                    });
+                    self.pad(&directive, text, directive.at_sign, out)?;
+                }
+                'd' | 'x' => {
+                    let arg = self.next_arg(&directive, args)?;
+                    let value = match arg {
+                        LispType::Integer(value) => value,
+                        other => {
+                            return Err(self.error(
+                                directive.position,
+                                format!(
+                                    "~{} expects an integer, got {}",
+                                    directive.kind,
+                                    other.show()
+                                ),
+                            ))
+                        }
+                    };
+                    let digits = if directive.kind == 'd' {
+                        value.unsigned_abs().to_string()
+                    } else {
+                        format!("{:x}", value.unsigned_abs())
+                    };
+                    let sign = if value < 0 {
+                        "-"
+                    } else if directive.at_sign {
+                        "+"
+                    } else {
+                        ""
+                    };
+                    self.pad(&directive, format!("{}{}", sign, digits), true, out)?;
+                }
+                'f' => {
+                    let arg = self.next_arg(&directive, args)?;
+                    let value = arg.as_float().ok_or_else(|| {
+                        self.error(
+                            directive.position,
+                            format!("~f expects a number, got {}", arg.show()),
+                        )
+                    })?;
+                    let text = match self.number_param(&directive, 1)? {
+                        Some(digits) if digits >= 0 => format!("{:.*}", digits as usize, value),
+                        Some(_) => {
+                            return Err(
+                                self.error(directive.position, "~f digits must not be negative")
+                            )
+                        }
+                        None => LispType::Float(value).show(),
+                    };
+                    if directive.params.iter().skip(2).take(2).any(Option::is_some) {
+                        return Err(self.error(
+                            directive.position,
+                            "~f does not support the scale and overflow parameters",
+                        ));
+                    }
+                    self.pad(&directive, text, true, out)?;
+                }
+                '%' => {
+                    let count = self.number_param(&directive, 0)?.unwrap_or(1).max(0);
+                    out.extend(std::iter::repeat_n('\n', count as usize));
+                }
+                '~' => out.push('~'),
+                '^' => {
+                    if args.remaining() == 0 {
+                        return Ok(Flow::Escape);
+                    }
+                }
+                '{' => {
+                    let (close, after_close) = self.find_close(&directive, end)?;
+                    let mut arg = self.next_arg(&directive, args)?;
+                    if let LispType::Record(record) = &arg {
+                        if let Some(items) = self.items.get(&Rc::as_ptr(record)) {
+                            arg = items.clone();
+                        }
+                    }
+                    let items = seq_to_vec(&Builtin::FORMAT, arg)
+                        .map_err(|e| self.error(directive.position, format!("~{{: {}", e)))?;
+                    let mut inner = Args { items, next: 0 };
+                    while inner.remaining() > 0 {
+                        let before = inner.next;
+                        let flow = self.run(directive.end, close, &mut inner, out)?;
+                        // A body that consumes nothing would loop forever.
+                        if matches!(flow, Flow::Escape) || inner.next == before {
+                            break;
+                        }
+                    }
+                    i = after_close;
+                }
+                '}' => {
+                    return Err(self.error(directive.position, "~} without matching ~{"));
+                }
+                other => {
+                    return Err(
+                        self.error(directive.position, format!("unknown directive ~{}", other))
+                    )
+                }
+            }
+        }
+        Ok(Flow::Done)
+    }
+
+    fn parse_directive(&self, position: usize, end: usize) -> Result<Directive, EvaluatorError> {
+        let at = |i: usize| self.chars[..end].get(i).copied();
+        let mut i = position + 1;
+        let mut params = Vec::new();
+
+        loop {
+            let param = match at(i) {
+                Some('\'') => {
+                    let ch = at(i + 1)
+                        .ok_or_else(|| self.error(position, "missing character after '"))?;
+                    i += 2;
+                    Some(Param::Char(ch))
+                }
+                Some(ch) if ch.is_ascii_digit() || ch == '-' => {
+                    let start = i;
+                    i += 1;
+                    while at(i).is_some_and(|ch| ch.is_ascii_digit()) {
+                        i += 1;
+                    }
+                    let text: String = self.chars[start..i].iter().collect();
+                    let number = text
+                        .parse()
+                        .map_err(|_| self.error(position, format!("bad parameter {}", text)))?;
+                    Some(Param::Number(number))
+                }
+                _ => None,
+            };
+
+            if at(i) == Some(',') {
+                params.push(param);
+                i += 1;
+            } else {
+                if param.is_some() {
+                    params.push(param);
+                }
+                break;
+            }
+        }
+
+        let at_sign = at(i) == Some('@');
+        if at_sign {
+            i += 1;
+        }
+
+        match at(i) {
+            Some(ch) => Ok(Directive {
+                position,
+                params,
+                at_sign,
+                kind: ch.to_ascii_lowercase(),
+                end: i + 1,
+            }),
+            None => Err(self.error(position, "unterminated directive")),
+        }
+    }
+
+    /// Finds the `~}` closing the `~{` at `directive`, skipping nested pairs.
+    /// Returns the positions where the closing directive starts and ends.
+    fn find_close(
+        &self,
+        directive: &Directive,
+        end: usize,
+    ) -> Result<(usize, usize), EvaluatorError> {
+        let mut depth = 0;
+        let mut i = directive.end;
+        while i < end {
+            if self.chars[i] == '~' {
+                let inner = self.parse_directive(i, end)?;
+                match inner.kind {
+                    '{' => depth += 1,
+                    '}' if depth == 0 => return Ok((i, inner.end)),
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                i = inner.end;
+            } else {
+                i += 1;
+            }
+        }
+        Err(self.error(directive.position, "~{ without matching ~}"))
+    }
+
+    fn next_arg(&self, directive: &Directive, args: &mut Args) -> Result<LispType, EvaluatorError> {
+        let arg = args.items.get(args.next).cloned().ok_or_else(|| {
+            self.error(
+                directive.position,
+                format!("not enough arguments for ~{}", directive.kind),
+            )
+        })?;
+        args.next += 1;
+        Ok(arg)
+    }
+
+    fn number_param(
+        &self,
+        directive: &Directive,
+        index: usize,
+    ) -> Result<Option<i64>, EvaluatorError> {
+        match directive.params.get(index).copied().flatten() {
+            Some(Param::Number(n)) => Ok(Some(n)),
+            Some(Param::Char(_)) => Err(self.error(
+                directive.position,
+                format!(
+                    "parameter {} of ~{} must be a number",
+                    index + 1,
+                    directive.kind
+                ),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Pads `text` to the `mincol` parameter with the `padchar` parameter,
+    /// which is the fifth one for `~f` as in Common Lisp and otherwise the
+    /// second.
+    fn pad(
+        &self,
+        directive: &Directive,
+        text: String,
+        pad_left: bool,
+        out: &mut String,
+    ) -> Result<(), EvaluatorError> {
+        let mincol = self.number_param(directive, 0)?.unwrap_or(0).max(0) as usize;
+        let index = if directive.kind == 'f' { 4 } else { 1 };
+        let padchar = match directive.params.get(index).copied().flatten() {
+            Some(Param::Char(ch)) => ch,
+            Some(Param::Number(_)) => {
+                return Err(self.error(
+                    directive.position,
+                    format!(
+                        "parameter {} of ~{} must be a character",
+                        index + 1,
+                        directive.kind
+                    ),
+                ))
+            }
+            None => ' ',
+        };
+        let padding: String =
+            std::iter::repeat_n(padchar, mincol.saturating_sub(text.chars().count())).collect();
+
+        if pad_left {
+            out.push_str(&padding);
+            out.push_str(&text);
+        } else {
+            out.push_str(&text);
+            out.push_str(&padding);
+        }
+        Ok(())
+    }
+}
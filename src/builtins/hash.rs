@@ -0,0 +1,107 @@
+use super::take_args;
+use crate::{
+    conslist::ConsList,
+    evaluator::EvaluatorError,
+    lisptype::{LispMap, LispType},
+    type_enums::Builtin,
+};
+use std::rc::Rc;
+
+/// Applies a hash map builtin. Maps are immutable: every update returns a
+/// new map and leaves its argument untouched.
+pub fn apply(builtin: &Builtin, args: Vec<LispType>) -> Result<LispType, EvaluatorError> {
+    match builtin {
+        Builtin::HASHMAP => {
+            if !args.len().is_multiple_of(2) {
+                return Err(EvaluatorError::InvalidArguments(
+                    "hash-map requires an even number of arguments".to_string(),
+                ));
+            }
+            let mut map = LispMap::default();
+            let mut args = args.into_iter();
+            while let (Some(key), Some(value)) = (args.next(), args.next()) {
+                map.insert(key, value);
+            }
+            Ok(LispType::HashMap(Rc::new(map)))
+        }
+        Builtin::HASHGET => {
+            if args.len() != 2 && args.len() != 3 {
+                return Err(EvaluatorError::InvalidArguments(
+                    "hash-get requires a map, a key and an optional default".to_string(),
+                ));
+            }
+            let mut args = args.into_iter();
+            let map = as_map(builtin, args.next().unwrap())?;
+            let key = args.next().unwrap();
+            let default = args.next().unwrap_or_else(LispType::nil);
+            Ok(map.get(&key).cloned().unwrap_or(default))
+        }
+        Builtin::HASHSET => {
+            let [map, key, value] = take_args(builtin, args)?;
+            let mut map = as_map(builtin, map)?;
+            Rc::make_mut(&mut map).insert(key, value);
+            Ok(LispType::HashMap(map))
+        }
+        Builtin::HASHREMOVE => {
+            let [map, key] = take_args(builtin, args)?;
+            let mut map = as_map(builtin, map)?;
+            if map.contains_key(&key) {
+                Rc::make_mut(&mut map).remove(&key);
+            }
+            Ok(LispType::HashMap(map))
+        }
+        Builtin::HASHCONTAINSP => {
+            let [map, key] = take_args(builtin, args)?;
+            Ok(LispType::Bool(as_map(builtin, map)?.contains_key(&key)))
+        }
+        Builtin::HASHCOUNT => {
+            let [map] = take_args(builtin, args)?;
+            Ok(LispType::Integer(as_map(builtin, map)?.len() as i64))
+        }
+        Builtin::HASHKEYS => {
+            let [map] = take_args(builtin, args)?;
+            Ok(LispType::list(
+                as_map(builtin, map)?.keys().cloned().collect(),
+            ))
+        }
+        Builtin::HASHVALUES => {
+            let [map] = take_args(builtin, args)?;
+            Ok(LispType::list(
+                as_map(builtin, map)?.values().cloned().collect(),
+            ))
+        }
+        Builtin::HASHMERGE => {
+            let mut merged = Rc::new(LispMap::default());
+            for (i, arg) in args.into_iter().enumerate() {
+                let map = as_map(builtin, arg)?;
+                if i == 0 {
+                    merged = map;
+                } else {
+                    let target = Rc::make_mut(&mut merged);
+                    target.extend(map.iter().map(|(k, v)| (k.clone(), v.clone())));
+                }
+            }
+            Ok(LispType::HashMap(merged))
+        }
+        Builtin::HASHTOLIST => {
+            let [map] = take_args(builtin, args)?;
+            let entries = as_map(builtin, map)?
+                .iter()
+                .map(|(k, v)| LispType::Cons(ConsList::cons(k.clone(), v.clone())))
+                .collect();
+            Ok(LispType::list(entries))
+        }
+        _ => unreachable!("{} is not a hash map builtin", builtin.to_string()),
+    }
+}
+
+fn as_map(builtin: &Builtin, value: LispType) -> Result<Rc<LispMap>, EvaluatorError> {
+    match value {
+        LispType::HashMap(map) => Ok(map),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects a hash map, got {}",
+            builtin.to_string(),
+            other.show()
+        ))),
+    }
+}
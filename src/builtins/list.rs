@@ -0,0 +1,350 @@
+use super::{seq_items, take_args};
+use crate::{
+    conslist::ConsList,
+    evaluator::{Evaluator, EvaluatorError},
+    lisptype::LispType,
+    type_enums::Builtin,
+};
+
+/// Applies a list builtin. Sequences may be proper lists, vectors or records
+/// whose type implements `seq`; results are always lists. Every operation
+/// loops over the elements, so long lists never deepen the Rust stack.
+///
+/// Functions come first (`(map f xs)`, `(fold-left f init xs)`), while
+/// positional operations take the sequence first like `vector-ref`
+/// (`(nth xs 2)`, `(take xs 3)`, `(sort xs <)`).
+pub fn apply(
+    evaluator: &mut Evaluator,
+    builtin: &Builtin,
+    args: Vec<LispType>,
+) -> Result<LispType, EvaluatorError> {
+    match builtin {
+        // With several sequences, stops at the end of the shortest one.
+        Builtin::MAP | Builtin::FOREACH => {
+            let (function, seqs) = function_and_seqs(evaluator, builtin, args)?;
+            let len = seqs.iter().map(Vec::len).min().unwrap_or(0);
+            let mut results = Vec::with_capacity(len);
+            for i in 0..len {
+                let call_args = seqs.iter().map(|seq| seq[i].clone()).collect();
+                results.push(evaluator.apply_function(&function, call_args)?);
+            }
+            Ok(match builtin {
+                Builtin::MAP => LispType::list(results),
+                _ => LispType::nil(),
+            })
+        }
+        Builtin::FILTER | Builtin::REMOVE => {
+            let [pred, seq] = take_args(builtin, args)?;
+            let keep = matches!(builtin, Builtin::FILTER);
+            let mut results = Vec::new();
+            for item in seq_items(evaluator, builtin, seq)? {
+                if evaluator
+                    .apply_function(&pred, vec![item.clone()])?
+                    .is_truthy()
+                    == keep
+                {
+                    results.push(item);
+                }
+            }
+            Ok(LispType::list(results))
+        }
+        // Like fold-left, seeded with the first element.
+        Builtin::REDUCE => {
+            let [function, seq] = take_args(builtin, args)?;
+            let mut items = seq_items(evaluator, builtin, seq)?.into_iter();
+            let mut acc = items.next().ok_or_else(|| {
+                EvaluatorError::InvalidArguments("reduce of an empty sequence".to_string())
+            })?;
+            for item in items {
+                acc = evaluator.apply_function(&function, vec![acc, item])?;
+            }
+            Ok(acc)
+        }
+        // `(fold-left f init xs)` computes `(f (f init x1) x2)`, while
+        // `(fold-right f init xs)` computes `(f x1 (f x2 init))`.
+        Builtin::FOLDLEFT | Builtin::FOLDRIGHT => {
+            let [function, init, seq] = take_args(builtin, args)?;
+            let items = seq_items(evaluator, builtin, seq)?;
+            let mut acc = init;
+            if matches!(builtin, Builtin::FOLDLEFT) {
+                for item in items {
+                    acc = evaluator.apply_function(&function, vec![acc, item])?;
+                }
+            } else {
+                for item in items.into_iter().rev() {
+                    acc = evaluator.apply_function(&function, vec![item, acc])?;
+                }
+            }
+            Ok(acc)
+        }
+        // `(apply f a b '(c d))` calls `(f a b c d)`.
+        Builtin::APPLY => {
+            if args.len() < 2 {
+                return Err(EvaluatorError::InvalidArguments(
+                    "apply requires a function and an argument list".to_string(),
+                ));
+            }
+            let mut args = args;
+            let spread = seq_items(evaluator, builtin, args.pop().unwrap())?;
+            let function = args.remove(0);
+            args.extend(spread);
+            evaluator.apply_function(&function, args)
+        }
+        // Copies every argument but the last, which becomes the shared tail
+        // of the result and need not be a list: `(append '(1) 2)` is `(1 . 2)`.
+        Builtin::APPEND => {
+            let mut args = args;
+            let mut result = args.pop().unwrap_or_else(LispType::nil);
+            for seq in args.into_iter().rev() {
+                for item in seq_items(evaluator, builtin, seq)?.into_iter().rev() {
+                    result = LispType::Cons(ConsList::cons(item, result));
+                }
+            }
+            Ok(result)
+        }
+        Builtin::REVERSE => {
+            let [seq] = take_args(builtin, args)?;
+            let mut items = seq_items(evaluator, builtin, seq)?;
+            items.reverse();
+            Ok(LispType::list(items))
+        }
+        Builtin::NTH => {
+            let [seq, index] = take_args(builtin, args)?;
+            let index = as_count(builtin, &index)?;
+            let item = match seq {
+                LispType::Cons(list) if list.is_proper() => list.nth(index).cloned(),
+                seq => seq_items(evaluator, builtin, seq)?.into_iter().nth(index),
+            };
+            item.ok_or_else(|| {
+                EvaluatorError::InvalidArguments(format!("nth: index {} out of range", index))
+            })
+        }
+        Builtin::LAST => {
+            let [seq] = take_args(builtin, args)?;
+            seq_items(evaluator, builtin, seq)?.pop().ok_or_else(|| {
+                EvaluatorError::InvalidArguments("last of an empty sequence".to_string())
+            })
+        }
+        Builtin::TAKE => {
+            let [seq, count] = take_args(builtin, args)?;
+            let count = as_count(builtin, &count)?;
+            let mut items = seq_items(evaluator, builtin, seq)?;
+            check_count(builtin, count, items.len())?;
+            items.truncate(count);
+            Ok(LispType::list(items))
+        }
+        // The result of dropping from a list shares its tail.
+        Builtin::DROP => {
+            let [seq, count] = take_args(builtin, args)?;
+            let count = as_count(builtin, &count)?;
+            match seq {
+                LispType::Cons(list) if list.is_proper() => {
+                    let mut current = list;
+                    for dropped in 0..count {
+                        current = match &*current {
+                            ConsList::Cons(_, tail) => tail.clone(),
+                            _ => return Err(count_error(builtin, count, dropped)),
+                        };
+                    }
+                    Ok(LispType::Cons(current))
+                }
+                seq => {
+                    let items = seq_items(evaluator, builtin, seq)?;
+                    check_count(builtin, count, items.len())?;
+                    Ok(LispType::list(items[count..].to_vec()))
+                }
+            }
+        }
+        Builtin::ZIP => {
+            if args.is_empty() {
+                return Err(EvaluatorError::InvalidArguments(
+                    "zip requires at least one sequence".to_string(),
+                ));
+            }
+            let seqs = args
+                .into_iter()
+                .map(|seq| seq_items(evaluator, builtin, seq))
+                .collect::<Result<Vec<_>, _>>()?;
+            let len = seqs.iter().map(Vec::len).min().unwrap_or(0);
+            let tuples = (0..len)
+                .map(|i| LispType::list(seqs.iter().map(|seq| seq[i].clone()).collect()))
+                .collect();
+            Ok(LispType::list(tuples))
+        }
+        // `(range end)`, `(range start end)` or `(range start end step)`;
+        // `end` is exclusive and `step` may be negative.
+        Builtin::RANGE => {
+            let bounds = args
+                .iter()
+                .map(|arg| match arg {
+                    LispType::Integer(i) => Ok(*i),
+                    other => Err(EvaluatorError::TypeMismatch(format!(
+                        "range expects integers, got {}",
+                        other.show()
+                    ))),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let (start, end, step) = match bounds[..] {
+                [end] => (0, end, 1),
+                [start, end] => (start, end, 1),
+                [start, end, step] => (start, end, step),
+                _ => {
+                    return Err(EvaluatorError::InvalidArguments(
+                        "range requires one to three integers".to_string(),
+                    ))
+                }
+            };
+            if step == 0 {
+                return Err(EvaluatorError::InvalidArguments(
+                    "range: step must not be zero".to_string(),
+                ));
+            }
+            let mut items = Vec::new();
+            let mut i = start;
+            while (step > 0 && i < end) || (step < 0 && i > end) {
+                items.push(LispType::Integer(i));
+                i = match i.checked_add(step) {
+                    Some(next) => next,
+                    None => break,
+                };
+            }
+            Ok(LispType::list(items))
+        }
+        // The first pair of an association list whose car is `equal?` to the
+        // key, or #f.
+        Builtin::ASSOC => {
+            let [key, alist] = take_args(builtin, args)?;
+            for entry in seq_items(evaluator, builtin, alist)? {
+                match &entry {
+                    LispType::Cons(pair) => {
+                        if pair.car() == Some(&key) {
+                            return Ok(entry);
+                        }
+                    }
+                    other => {
+                        return Err(EvaluatorError::TypeMismatch(format!(
+                            "assoc expects a list of pairs, got element {}",
+                            other.show()
+                        )))
+                    }
+                }
+            }
+            Ok(LispType::Bool(false))
+        }
+        // The tail of the list starting at the first element `equal?` to the
+        // item, or #f.
+        Builtin::MEMBER => {
+            let [item, list] = take_args(builtin, args)?;
+            let mut current = match evaluator.as_sequence(list)? {
+                LispType::Cons(list) if list.is_proper() => list,
+                other => {
+                    return Err(EvaluatorError::TypeMismatch(format!(
+                        "member expects a proper list, got {}",
+                        other.show()
+                    )))
+                }
+            };
+            loop {
+                let next = match &*current {
+                    ConsList::Cons(head, _) if *head == item => return Ok(LispType::Cons(current)),
+                    ConsList::Cons(_, tail) => tail.clone(),
+                    _ => return Ok(LispType::Bool(false)),
+                };
+                current = next;
+            }
+        }
+        // A stable merge sort; `less?` is called as `(less? a b)`.
+        Builtin::SORT => {
+            let [seq, less] = take_args(builtin, args)?;
+            let items = seq_items(evaluator, builtin, seq)?;
+            let sorted = merge_sort(items, |a, b| {
+                Ok(evaluator
+                    .apply_function(&less, vec![a.clone(), b.clone()])?
+                    .is_truthy())
+            })?;
+            Ok(LispType::list(sorted))
+        }
+        _ => unreachable!("{} is not a list builtin", builtin.to_string()),
+    }
+}
+
+/// Splits `(f seq...)` into the function and the collected sequences.
+fn function_and_seqs(
+    evaluator: &mut Evaluator,
+    builtin: &Builtin,
+    args: Vec<LispType>,
+) -> Result<(LispType, Vec<Vec<LispType>>), EvaluatorError> {
+    if args.len() < 2 {
+        return Err(EvaluatorError::InvalidArguments(format!(
+            "{} requires a function and at least one sequence",
+            builtin.to_string()
+        )));
+    }
+    let mut args = args.into_iter();
+    let function = args.next().unwrap();
+    let seqs = args
+        .map(|seq| seq_items(evaluator, builtin, seq))
+        .collect::<Result<_, _>>()?;
+    Ok((function, seqs))
+}
+
+fn as_count(builtin: &Builtin, value: &LispType) -> Result<usize, EvaluatorError> {
+    match value {
+        LispType::Integer(i) if *i >= 0 => Ok(*i as usize),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects a non-negative integer, got {}",
+            builtin.to_string(),
+            other.show()
+        ))),
+    }
+}
+
+fn check_count(builtin: &Builtin, count: usize, len: usize) -> Result<(), EvaluatorError> {
+    if count > len {
+        return Err(count_error(builtin, count, len));
+    }
+    Ok(())
+}
+
+fn count_error(builtin: &Builtin, count: usize, len: usize) -> EvaluatorError {
+    EvaluatorError::InvalidArguments(format!(
+        "{}: count {} out of range for length {}",
+        builtin.to_string(),
+        count,
+        len
+    ))
+}
+
+/// Bottom-up merge sort with a fallible comparator. Unlike `slice::sort_by`
+/// it tolerates comparators that are not a total order.
+fn merge_sort(
+    mut items: Vec<LispType>,
+    mut less: impl FnMut(&LispType, &LispType) -> Result<bool, EvaluatorError>,
+) -> Result<Vec<LispType>, EvaluatorError> {
+    let len = items.len();
+    let mut width = 1;
+    while width < len {
+        let mut merged = Vec::with_capacity(len);
+        for start in (0..len).step_by(2 * width) {
+            let mid = (start + width).min(len);
+            let end = (start + 2 * width).min(len);
+            let (mut i, mut j) = (start, mid);
+            while i < mid && j < end {
+                // Take from the right run only when strictly less, which
+                // keeps equal elements in their original order.
+                if less(&items[j], &items[i])? {
+                    merged.push(items[j].clone());
+                    j += 1;
+                } else {
+                    merged.push(items[i].clone());
+                    i += 1;
+                }
+            }
+            merged.extend_from_slice(&items[i..mid]);
+            merged.extend_from_slice(&items[j..end]);
+        }
+        items = merged;
+        width *= 2;
+    }
+    Ok(items)
+}
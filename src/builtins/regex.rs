@@ -0,0 +1,179 @@
+use super::take_args;
+use crate::{
+    evaluator::EvaluatorError,
+    lisptype::LispType,
+    regex::{Captures, Regex},
+    type_enums::Builtin,
+};
+use std::rc::Rc;
+
+/// Applies a regex builtin. Wherever a regex is expected, a pattern string is
+/// accepted too and compiled on the spot; `(regex "...")` compiles it once.
+///
+/// Matches are described by capture lists: the whole match followed by one
+/// string per group, or `nil` for a group that did not take part.
+pub fn apply(builtin: &Builtin, args: Vec<LispType>) -> Result<LispType, EvaluatorError> {
+    match builtin {
+        Builtin::REGEX => {
+            let [pattern] = take_args(builtin, args)?;
+            Ok(LispType::Regex(as_regex(builtin, pattern)?))
+        }
+        Builtin::REGEXP => {
+            let [value] = take_args(builtin, args)?;
+            Ok(LispType::Bool(matches!(value, LispType::Regex(_))))
+        }
+        // The first match anywhere in the string, or #f.
+        Builtin::REGEXMATCH => {
+            let [regex, string] = take_args(builtin, args)?;
+            let regex = as_regex(builtin, regex)?;
+            let string = as_string(builtin, string)?;
+            Ok(match regex.captures_at(&string, 0) {
+                Some(captures) => captures_to_list(&string, &captures),
+                None => LispType::Bool(false),
+            })
+        }
+        // Like Python's `findall`: the matched strings when the pattern has no
+        // groups, capture lists otherwise.
+        Builtin::REGEXFINDALL => {
+            let [regex, string] = take_args(builtin, args)?;
+            let regex = as_regex(builtin, regex)?;
+            let string = as_string(builtin, string)?;
+            let matches = regex
+                .captures_all(&string)
+                .iter()
+                .map(|captures| {
+                    if regex.groups() == 0 {
+                        capture_to_string(&string, captures[0])
+                    } else {
+                        captures_to_list(&string, captures)
+                    }
+                })
+                .collect();
+            Ok(LispType::list(matches))
+        }
+        // Replaces every match. In the replacement, `$1` inserts a group, `$0`
+        // the whole match and `$$` a dollar sign.
+        Builtin::REGEXREPLACE => {
+            let [regex, string, replacement] = take_args(builtin, args)?;
+            let regex = as_regex(builtin, regex)?;
+            let string = as_string(builtin, string)?;
+            let replacement = as_string(builtin, replacement)?;
+
+            let mut out = String::new();
+            let mut last = 0;
+            for captures in regex.captures_all(&string) {
+                let (start, end) = captures[0].expect("group 0 always matches");
+                out.push_str(&string[last..start]);
+                expand_replacement(&replacement, &string, &captures, &mut out)?;
+                last = end;
+            }
+            out.push_str(&string[last..]);
+            Ok(LispType::String(out))
+        }
+        Builtin::REGEXSPLIT => {
+            let [regex, string] = take_args(builtin, args)?;
+            let regex = as_regex(builtin, regex)?;
+            let string = as_string(builtin, string)?;
+
+            let mut parts = Vec::new();
+            let mut last = 0;
+            for captures in regex.captures_all(&string) {
+                let (start, end) = captures[0].expect("group 0 always matches");
+                // An empty match at the edges would only add empty fields.
+                if start == end && (start == 0 || start == string.len()) {
+                    continue;
+                }
+                parts.push(LispType::String(string[last..start].to_string()));
+                last = end;
+            }
+            parts.push(LispType::String(string[last..].to_string()));
+            Ok(LispType::list(parts))
+        }
+        _ => unreachable!("{} is not a regex builtin", builtin.to_string()),
+    }
+}
+
+fn as_regex(builtin: &Builtin, value: LispType) -> Result<Rc<Regex>, EvaluatorError> {
+    match value {
+        LispType::Regex(regex) => Ok(regex),
+        LispType::String(pattern) => Regex::new(&pattern).map(Rc::new).map_err(|e| {
+            EvaluatorError::InvalidArguments(format!(
+                "{}: invalid regex {}: {}",
+                builtin.to_string(),
+                LispType::String(pattern).show(),
+                e
+            ))
+        }),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects a regex or a pattern string, got {}",
+            builtin.to_string(),
+            other.show()
+        ))),
+    }
+}
+
+fn as_string(builtin: &Builtin, value: LispType) -> Result<String, EvaluatorError> {
+    match value {
+        LispType::String(s) => Ok(s),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects a string, got {}",
+            builtin.to_string(),
+            other.show()
+        ))),
+    }
+}
+
+fn capture_to_string(string: &str, capture: Option<(usize, usize)>) -> LispType {
+    match capture {
+        Some((start, end)) => LispType::String(string[start..end].to_string()),
+        None => LispType::nil(),
+    }
+}
+
+fn captures_to_list(string: &str, captures: &Captures) -> LispType {
+    LispType::list(
+        captures
+            .iter()
+            .map(|&capture| capture_to_string(string, capture))
+            .collect(),
+    )
+}
+
+fn expand_replacement(
+    replacement: &str,
+    string: &str,
+    captures: &Captures,
+    out: &mut String,
+) -> Result<(), EvaluatorError> {
+    let mut chars = replacement.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '$' {
+            out.push(ch);
+            continue;
+        }
+        if chars.next_if_eq(&'$').is_some() {
+            out.push('$');
+            continue;
+        }
+
+        let mut digits = String::new();
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            digits.push(digit);
+        }
+        let group = digits.parse::<usize>().ok().filter(|&g| g < captures.len());
+        match group {
+            Some(group) => {
+                if let Some((start, end)) = captures[group] {
+                    out.push_str(&string[start..end]);
+                }
+            }
+            None => {
+                return Err(EvaluatorError::InvalidArguments(format!(
+                    "regex-replace: replacement refers to a missing group ${}",
+                    digits
+                )))
+            }
+        }
+    }
+    Ok(())
+}
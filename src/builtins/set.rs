@@ -0,0 +1,88 @@
+use super::{seq_to_vec, take_args};
+use crate::{
+    evaluator::EvaluatorError,
+    lisptype::{LispSet, LispType},
+    type_enums::Builtin,
+};
+use std::rc::Rc;
+
+/// Applies a set builtin. Sets are immutable and compare their elements with
+/// structural equality, so `'(1 2)` and `(list 1 2)` are the same element.
+pub fn apply(builtin: &Builtin, args: Vec<LispType>) -> Result<LispType, EvaluatorError> {
+    match builtin {
+        Builtin::SET => Ok(LispType::Set(Rc::new(args.into_iter().collect()))),
+        Builtin::SETP => {
+            let [value] = take_args(builtin, args)?;
+            Ok(LispType::Bool(matches!(value, LispType::Set(_))))
+        }
+        Builtin::SETADD => {
+            let [set, item] = take_args(builtin, args)?;
+            let mut set = as_set(builtin, set)?;
+            if !set.contains(&item) {
+                Rc::make_mut(&mut set).insert(item);
+            }
+            Ok(LispType::Set(set))
+        }
+        Builtin::SETREMOVE => {
+            let [set, item] = take_args(builtin, args)?;
+            let mut set = as_set(builtin, set)?;
+            if set.contains(&item) {
+                Rc::make_mut(&mut set).remove(&item);
+            }
+            Ok(LispType::Set(set))
+        }
+        Builtin::MEMBERP => {
+            let [item, collection] = take_args(builtin, args)?;
+            let found = match collection {
+                LispType::Set(set) => set.contains(&item),
+                seq => seq_to_vec(builtin, seq)?.contains(&item),
+            };
+            Ok(LispType::Bool(found))
+        }
+        Builtin::UNION | Builtin::INTERSECTION | Builtin::DIFFERENCE => {
+            let mut sets = args.into_iter().map(|arg| as_set(builtin, arg));
+            let mut result = match sets.next() {
+                Some(first) => first?,
+                None => {
+                    return Err(EvaluatorError::InvalidArguments(format!(
+                        "{} requires at least one set",
+                        builtin.to_string()
+                    )))
+                }
+            };
+            for other in sets {
+                let other = other?;
+                let target = Rc::make_mut(&mut result);
+                match builtin {
+                    Builtin::UNION => target.extend(other.iter().cloned()),
+                    Builtin::INTERSECTION => target.retain(|item| other.contains(item)),
+                    _ => target.retain(|item| !other.contains(item)),
+                }
+            }
+            Ok(LispType::Set(result))
+        }
+        Builtin::SUBSETP => {
+            let [a, b] = take_args(builtin, args)?;
+            let (a, b) = (as_set(builtin, a)?, as_set(builtin, b)?);
+            Ok(LispType::Bool(a.is_subset(&b)))
+        }
+        Builtin::SETTOLIST => {
+            let [set] = take_args(builtin, args)?;
+            Ok(LispType::list(
+                as_set(builtin, set)?.iter().cloned().collect(),
+            ))
+        }
+        _ => unreachable!("{} is not a set builtin", builtin.to_string()),
+    }
+}
+
+fn as_set(builtin: &Builtin, value: LispType) -> Result<Rc<LispSet>, EvaluatorError> {
+    match value {
+        LispType::Set(set) => Ok(set),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects a set, got {}",
+            builtin.to_string(),
+            other.show()
+        ))),
+    }
+}
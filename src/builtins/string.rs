@@ -0,0 +1,233 @@
+use super::{seq_to_vec, take_args};
+use crate::{evaluator::EvaluatorError, lisptype::LispType, parser::Parser, type_enums::Builtin};
+
+/// Applies a string builtin. Indices count Unicode scalar values, not bytes.
+/// Searches that find nothing return `#f`.
+pub fn apply(builtin: &Builtin, args: Vec<LispType>) -> Result<LispType, EvaluatorError> {
+    match builtin {
+        Builtin::STRINGREF => {
+            let [string, index] = take_args(builtin, args)?;
+            let string = as_string(builtin, string)?;
+            let index = as_index(builtin, &index)?;
+            string
+                .chars()
+                .nth(index)
+                .map(LispType::Char)
+                .ok_or_else(|| {
+                    EvaluatorError::InvalidArguments(format!(
+                        "string-ref: index {} out of range",
+                        index
+                    ))
+                })
+        }
+        Builtin::STRINGLENGTH => {
+            let [string] = take_args(builtin, args)?;
+            let len = as_string(builtin, string)?.chars().count();
+            Ok(LispType::Integer(len as i64))
+        }
+        Builtin::SUBSTRING => {
+            if args.len() != 2 && args.len() != 3 {
+                return Err(EvaluatorError::InvalidArguments(
+                    "substring requires a string, a start and an optional end".to_string(),
+                ));
+            }
+            let mut args = args.into_iter();
+            let chars: Vec<char> = as_string(builtin, args.next().unwrap())?.chars().collect();
+            let start = as_index(builtin, &args.next().unwrap())?;
+            let end = match args.next() {
+                Some(end) => as_index(builtin, &end)?,
+                None => chars.len(),
+            };
+            if start > end || end > chars.len() {
+                return Err(EvaluatorError::InvalidArguments(format!(
+                    "substring: range {}..{} out of bounds for length {}",
+                    start,
+                    end,
+                    chars.len()
+                )));
+            }
+            Ok(LispType::String(chars[start..end].iter().collect()))
+        }
+        Builtin::STRINGINDEX => {
+            let [string, needle] = take_args(builtin, args)?;
+            let string = as_string(builtin, string)?;
+            let needle = as_pattern(builtin, needle)?;
+            Ok(match string.find(needle.as_str()) {
+                Some(byte_pos) => LispType::Integer(string[..byte_pos].chars().count() as i64),
+                None => LispType::Bool(false),
+            })
+        }
+        Builtin::STRINGSPLIT => {
+            if args.is_empty() || args.len() > 2 {
+                return Err(EvaluatorError::InvalidArguments(
+                    "string-split requires a string and an optional separator".to_string(),
+                ));
+            }
+            let mut args = args.into_iter();
+            let string = as_string(builtin, args.next().unwrap())?;
+            let parts: Vec<LispType> = match args.next() {
+                Some(separator) => {
+                    let separator = as_pattern(builtin, separator)?;
+                    if separator.is_empty() {
+                        return Err(EvaluatorError::InvalidArguments(
+                            "string-split: separator must not be empty".to_string(),
+                        ));
+                    }
+                    string
+                        .split(separator.as_str())
+                        .map(|part| LispType::String(part.to_string()))
+                        .collect()
+                }
+                None => string
+                    .split_whitespace()
+                    .map(|part| LispType::String(part.to_string()))
+                    .collect(),
+            };
+            Ok(LispType::list(parts))
+        }
+        Builtin::STRINGJOIN => {
+            if args.is_empty() || args.len() > 2 {
+                return Err(EvaluatorError::InvalidArguments(
+                    "string-join requires a list of strings and an optional separator".to_string(),
+                ));
+            }
+            let mut args = args.into_iter();
+            let parts = seq_to_vec(builtin, args.next().unwrap())?
+                .into_iter()
+                .map(|part| as_string(builtin, part))
+                .collect::<Result<Vec<_>, _>>()?;
+            let separator = match args.next() {
+                Some(separator) => as_pattern(builtin, separator)?,
+                None => String::new(),
+            };
+            Ok(LispType::String(parts.join(&separator)))
+        }
+        Builtin::STRINGTRIM | Builtin::STRINGUPCASE | Builtin::STRINGDOWNCASE => {
+            let [string] = take_args(builtin, args)?;
+            let string = as_string(builtin, string)?;
+            Ok(LispType::String(match builtin {
+                Builtin::STRINGTRIM => string.trim().to_string(),
+                Builtin::STRINGUPCASE => string.to_uppercase(),
+                _ => string.to_lowercase(),
+            }))
+        }
+        Builtin::STRINGREPLACE => {
+            let [string, from, to] = take_args(builtin, args)?;
+            let string = as_string(builtin, string)?;
+            let from = as_pattern(builtin, from)?;
+            if from.is_empty() {
+                return Err(EvaluatorError::InvalidArguments(
+                    "string-replace: pattern must not be empty".to_string(),
+                ));
+            }
+            let to = as_pattern(builtin, to)?;
+            Ok(LispType::String(string.replace(&from, &to)))
+        }
+        Builtin::STRINGCONTAINSP | Builtin::STRINGSTARTSWITHP | Builtin::STRINGENDSWITHP => {
+            let [string, needle] = take_args(builtin, args)?;
+            let string = as_string(builtin, string)?;
+            let needle = as_pattern(builtin, needle)?;
+            Ok(LispType::Bool(match builtin {
+                Builtin::STRINGCONTAINSP => string.contains(&needle),
+                Builtin::STRINGSTARTSWITHP => string.starts_with(&needle),
+                _ => string.ends_with(&needle),
+            }))
+        }
+        Builtin::STRINGTONUMBER => {
+            let [string] = take_args(builtin, args)?;
+            let string = as_string(builtin, string)?;
+            Ok(match string.parse::<i64>() {
+                Ok(i) => LispType::Integer(i),
+                Err(_) => match string.parse::<f64>() {
+                    Ok(f) if f.is_finite() => LispType::Float(f),
+                    _ => LispType::Bool(false),
+                },
+            })
+        }
+        Builtin::NUMBERTOSTRING => {
+            let [number] = take_args(builtin, args)?;
+            match number {
+                LispType::Integer(_) | LispType::Float(_) => Ok(LispType::String(number.show())),
+                other => Err(EvaluatorError::TypeMismatch(format!(
+                    "number->string expects a number, got {}",
+                    other.show()
+                ))),
+            }
+        }
+        Builtin::STRINGTOSYMBOL => {
+            let [string] = take_args(builtin, args)?;
+            // As when reading, reserved names such as `car` become their own
+            // variants, so that they compare equal to the quoted name.
+            Ok(Parser::parse_symbol(&as_string(builtin, string)?))
+        }
+        Builtin::SYMBOLTOSTRING => {
+            let [symbol] = take_args(builtin, args)?;
+            match symbol {
+                LispType::Symbol(s) => Ok(LispType::String(s)),
+                // Reserved names are parsed to their own variants but are
+                // still symbols as far as the language is concerned.
+                LispType::SpecForm(_)
+                | LispType::Builtin(_)
+                | LispType::BinOp(_)
+                | LispType::BinPred(_) => Ok(LispType::String(symbol.show())),
+                other => Err(EvaluatorError::TypeMismatch(format!(
+                    "symbol->string expects a symbol, got {}",
+                    other.show()
+                ))),
+            }
+        }
+        Builtin::STRINGTOLIST => {
+            let [string] = take_args(builtin, args)?;
+            let chars = as_string(builtin, string)?
+                .chars()
+                .map(LispType::Char)
+                .collect();
+            Ok(LispType::list(chars))
+        }
+        Builtin::LISTTOSTRING => {
+            let [list] = take_args(builtin, args)?;
+            let string = seq_to_vec(builtin, list)?
+                .into_iter()
+                .map(|item| match item {
+                    LispType::Char(ch) => Ok(ch),
+                    other => Err(EvaluatorError::TypeMismatch(format!(
+                        "list->string expects characters, got {}",
+                        other.show()
+                    ))),
+                })
+                .collect::<Result<String, _>>()?;
+            Ok(LispType::String(string))
+        }
+        _ => unreachable!("{} is not a string builtin", builtin.to_string()),
+    }
+}
+
+fn as_string(builtin: &Builtin, value: LispType) -> Result<String, EvaluatorError> {
+    match value {
+        LispType::String(s) => Ok(s),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects a string, got {}",
+            builtin.to_string(),
+            other.show()
+        ))),
+    }
+}
+
+/// Accepts either a string or a single character where a substring is expected.
+fn as_pattern(builtin: &Builtin, value: LispType) -> Result<String, EvaluatorError> {
+    match value {
+        LispType::Char(ch) => Ok(ch.to_string()),
+        other => as_string(builtin, other),
+    }
+}
+
+fn as_index(builtin: &Builtin, value: &LispType) -> Result<usize, EvaluatorError> {
+    match value {
+        LispType::Integer(i) if *i >= 0 => Ok(*i as usize),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects a non-negative integer index, got {}",
+            builtin.to_string(),
+            other.show()
+        ))),
+    }
+}
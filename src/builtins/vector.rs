@@ -0,0 +1,127 @@
+use super::{seq_to_vec, take_args};
+use crate::{
+    evaluator::EvaluatorError,
+    lisptype::{LispType, LispVector},
+    type_enums::Builtin,
+};
+use std::rc::Rc;
+
+/// Applies a vector builtin. Vectors built here are mutable; literal vectors
+/// are not and reject `vector-set!`.
+pub fn apply(builtin: &Builtin, args: Vec<LispType>) -> Result<LispType, EvaluatorError> {
+    match builtin {
+        Builtin::VECTOR => Ok(new_vector(args)),
+        Builtin::MAKEVECTOR => {
+            if args.is_empty() || args.len() > 2 {
+                return Err(EvaluatorError::InvalidArguments(
+                    "make-vector requires a length and an optional fill value".to_string(),
+                ));
+            }
+            let mut args = args.into_iter();
+            let len = match args.next().unwrap() {
+                LispType::Integer(len) if len >= 0 => len as usize,
+                other => {
+                    return Err(EvaluatorError::TypeMismatch(format!(
+                        "make-vector expects a non-negative length, got {}",
+                        other.show()
+                    )))
+                }
+            };
+            let fill = args.next().unwrap_or_else(LispType::nil);
+            Ok(new_vector(vec![fill; len]))
+        }
+        Builtin::VECTORP => {
+            let [value] = take_args(builtin, args)?;
+            Ok(LispType::Bool(matches!(value, LispType::Vector(_))))
+        }
+        Builtin::VECTORREF => {
+            let [vector, index] = take_args(builtin, args)?;
+            let vector = as_vector(builtin, vector)?;
+            let items = vector.items.borrow();
+            let index = as_index(builtin, &index, items.len())?;
+            Ok(items[index].clone())
+        }
+        Builtin::VECTORSET => {
+            let [vector, index, value] = take_args(builtin, args)?;
+            let vector = as_vector(builtin, vector)?;
+            if !vector.mutable {
+                return Err(EvaluatorError::Other(
+                    "vector-set! cannot modify a literal vector".to_string(),
+                ));
+            }
+            let mut items = vector.items.borrow_mut();
+            let index = as_index(builtin, &index, items.len())?;
+            items[index] = value.clone();
+            Ok(value)
+        }
+        Builtin::VECTORLENGTH => {
+            let [vector] = take_args(builtin, args)?;
+            let len = as_vector(builtin, vector)?.items.borrow().len();
+            Ok(LispType::Integer(len as i64))
+        }
+        Builtin::VECTORSLICE => {
+            if args.len() != 2 && args.len() != 3 {
+                return Err(EvaluatorError::InvalidArguments(
+                    "vector-slice requires a vector, a start and an optional end".to_string(),
+                ));
+            }
+            let mut args = args.into_iter();
+            let vector = as_vector(builtin, args.next().unwrap())?;
+            let items = vector.items.borrow();
+            let start = as_index(builtin, &args.next().unwrap(), items.len() + 1)?;
+            let end = match args.next() {
+                Some(end) => as_index(builtin, &end, items.len() + 1)?,
+                None => items.len(),
+            };
+            if start > end {
+                return Err(EvaluatorError::InvalidArguments(format!(
+                    "vector-slice start {} is after end {}",
+                    start, end
+                )));
+            }
+            Ok(new_vector(items[start..end].to_vec()))
+        }
+        Builtin::VECTORTOLIST => {
+            let [vector] = take_args(builtin, args)?;
+            let items = as_vector(builtin, vector)?.items.borrow().clone();
+            Ok(LispType::list(items))
+        }
+        Builtin::LISTTOVECTOR => {
+            let [list] = take_args(builtin, args)?;
+            Ok(new_vector(seq_to_vec(builtin, list)?))
+        }
+        _ => unreachable!("{} is not a vector builtin", builtin.to_string()),
+    }
+}
+
+fn new_vector(items: Vec<LispType>) -> LispType {
+    LispType::Vector(Rc::new(LispVector::new(items, true)))
+}
+
+fn as_vector(builtin: &Builtin, value: LispType) -> Result<Rc<LispVector>, EvaluatorError> {
+    match value {
+        LispType::Vector(vector) => Ok(vector),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects a vector, got {}",
+            builtin.to_string(),
+            other.show()
+        ))),
+    }
+}
+
+/// Converts `index` to a position below `len`.
+fn as_index(builtin: &Builtin, index: &LispType, len: usize) -> Result<usize, EvaluatorError> {
+    match index {
+        LispType::Integer(i) if *i >= 0 && (*i as usize) < len => Ok(*i as usize),
+        LispType::Integer(i) => Err(EvaluatorError::InvalidArguments(format!(
+            "{}: index {} out of range",
+            builtin.to_string(),
+            i
+        ))),
+        other => Err(EvaluatorError::TypeMismatch(format!(
+            "{} expects an integer index, got {}",
+            builtin.to_string(),
+            other.show()
+        ))),
+    }
+}
@@ -1,49 +1,277 @@
-use super::lisptype::LispType;
+use super::lisptype::{LispType, Repr};
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub enum ConsList {
     Cons(LispType, Rc<ConsList>),
+    /// A pair whose cdr is not a list, i.e. the last cell of an improper list
+    /// such as `(1 2 . 3)`. A pair whose cdr is a list is always a `Cons`.
+    Pair(LispType, LispType),
     Nil,
 }
 
 impl ConsList {
+    /// Builds the pair `(head . tail)`, choosing `Cons` when `tail` is a list.
+    pub fn cons(head: LispType, tail: LispType) -> Rc<ConsList> {
+        match tail {
+            LispType::Cons(list) => Rc::new(ConsList::Cons(head, list)),
+            tail => Rc::new(ConsList::Pair(head, tail)),
+        }
+    }
+
     pub fn car(&self) -> Option<&LispType> {
         match self {
-            ConsList::Cons(ref head, _) => Some(head),
+            ConsList::Cons(ref head, _) | ConsList::Pair(ref head, _) => Some(head),
             ConsList::Nil => None,
         }
     }
 
-    pub fn cdr(&self) -> Option<&ConsList> {
+    pub fn cdr(&self) -> Option<LispType> {
         match self {
-            ConsList::Cons(_, ref tail) => Some(tail),
+            ConsList::Cons(_, ref tail) => Some(LispType::Cons(tail.clone())),
+            ConsList::Pair(_, ref tail) => Some(tail.clone()),
             ConsList::Nil => None,
         }
     }
 
+    /// Returns true for `nil` and for chains of `Cons` ending in `nil`.
+    pub fn is_proper(&self) -> bool {
+        let mut current = self;
+        loop {
+            match current {
+                ConsList::Cons(_, tail) => current = tail,
+                ConsList::Pair(_, _) => return false,
+                ConsList::Nil => return true,
+            }
+        }
+    }
+
+    /// Iterates over the elements. For an improper list such as `(1 2 . 3)`
+    /// this yields the cars `1` and `2`; the final cdr is not an element.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            current: Some(self),
+        }
+    }
+
+    /// Number of elements, counted like `iter`.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self, ConsList::Nil)
+    }
+
+    pub fn nth(&self, n: usize) -> Option<&LispType> {
+        self.iter().nth(n)
+    }
+
+    /// Copies the elements of this list in front of `tail`, which is shared
+    /// rather than copied, as with `append` in Lisp. The result is improper
+    /// when `tail` is not a list; any improper tail of `self` is dropped.
+    pub fn append(&self, tail: LispType) -> LispType {
+        let items: Vec<&LispType> = self.iter().collect();
+        items.into_iter().rev().fold(tail, |tail, head| {
+            LispType::Cons(ConsList::cons(head.clone(), tail))
+        })
+    }
+
     pub fn show(&self) -> String {
-        return format!("({})", self.show_());
+        self.to_string()
     }
 
-    pub fn show_(&self) -> String {
-        match self {
+    pub(crate) fn write_repr(&self, f: &mut impl fmt::Write, repr: Repr) -> fmt::Result {
+        f.write_char('(')?;
+        let mut current = self;
+        let mut first = true;
+        loop {
+            match current {
+                ConsList::Cons(head, tail) => {
+                    if !first {
+                        f.write_char(' ')?;
+                    }
+                    head.write_repr(f, repr)?;
+                    current = tail;
+                }
+                ConsList::Pair(head, tail) => {
+                    if !first {
+                        f.write_char(' ')?;
+                    }
+                    head.write_repr(f, repr)?;
+                    f.write_str(" . ")?;
+                    tail.write_repr(f, repr)?;
+                    break;
+                }
+                ConsList::Nil => break,
+            }
+            first = false;
+        }
+        f.write_char(')')
+    }
+}
+
+pub struct Iter<'a> {
+    current: Option<&'a ConsList>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a LispType;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.current? {
+            ConsList::Cons(head, tail) => {
+                self.current = Some(tail);
+                Some(head)
+            }
+            ConsList::Pair(head, _) => {
+                self.current = None;
+                Some(head)
+            }
+            ConsList::Nil => None,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a ConsList {
+    type Item = &'a LispType;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Owning iterator over the elements of a list. Cells may be shared with
+/// other lists, so elements are cloned out rather than moved.
+pub struct IntoIter {
+    current: Option<Rc<ConsList>>,
+}
+
+impl Iterator for IntoIter {
+    type Item = LispType;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.current.take()?;
+        match &*current {
             ConsList::Cons(head, tail) => {
-                let head_str = if let LispType::Cons(ref cons) = head {
-                    format!("({})", cons.show_())
-                } else {
-                    head.show()
-                };
-
-                let tail_str = tail.show_();
-
-                if tail_str.is_empty() {
-                    head_str
-                } else {
-                    format!("{} {}", head_str, tail_str)
+                self.current = Some(tail.clone());
+                Some(head.clone())
+            }
+            ConsList::Pair(head, _) => Some(head.clone()),
+            ConsList::Nil => None,
+        }
+    }
+}
+
+impl IntoIterator for ConsList {
+    type Item = LispType;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            current: Some(Rc::new(self)),
+        }
+    }
+}
+
+/// Builds a proper list.
+impl FromIterator<LispType> for ConsList {
+    fn from_iter<I: IntoIterator<Item = LispType>>(iter: I) -> Self {
+        let mut items: Vec<LispType> = iter.into_iter().collect();
+        if items.is_empty() {
+            return ConsList::Nil;
+        }
+        let rest = items
+            .drain(1..)
+            .rev()
+            .fold(Rc::new(ConsList::Nil), |tail, head| {
+                Rc::new(ConsList::Cons(head, tail))
+            });
+        let first = items.pop().expect("checked non-empty above");
+        ConsList::Cons(first, rest)
+    }
+}
+
+impl From<Vec<LispType>> for ConsList {
+    fn from(items: Vec<LispType>) -> Self {
+        items.into_iter().collect()
+    }
+}
+
+thread_local! {
+    static NIL: Rc<ConsList> = Rc::new(ConsList::Nil);
+}
+
+/// Unlinks the spine iteratively. The derived drop would recurse once per
+/// cell and overflow the stack on long lists.
+impl Drop for ConsList {
+    fn drop(&mut self) {
+        let ConsList::Cons(_, tail) = self else {
+            return;
+        };
+        let mut next = std::mem::replace(tail, NIL.with(Rc::clone));
+        // Stop at the first cell still shared with another list.
+        while let Ok(mut cell) = Rc::try_unwrap(next) {
+            match &mut cell {
+                ConsList::Cons(_, tail) => next = std::mem::replace(tail, NIL.with(Rc::clone)),
+                _ => break,
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConsList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_repr(f, Repr::READABLE)
+    }
+}
+
+/// Element-wise structural equality. Walks the spine iteratively so long
+/// lists do not exhaust the stack.
+impl PartialEq for ConsList {
+    fn eq(&self, other: &Self) -> bool {
+        let (mut a, mut b) = (self, other);
+        loop {
+            match (a, b) {
+                (ConsList::Cons(x, xs), ConsList::Cons(y, ys)) => {
+                    if x != y {
+                        return false;
+                    }
+                    if Rc::ptr_eq(xs, ys) {
+                        return true;
+                    }
+                    (a, b) = (xs, ys);
+                }
+                (ConsList::Pair(x, xt), ConsList::Pair(y, yt)) => return x == y && xt == yt,
+                (ConsList::Nil, ConsList::Nil) => return true,
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl Eq for ConsList {}
+
+impl Hash for ConsList {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut current = self;
+        loop {
+            std::mem::discriminant(current).hash(state);
+            match current {
+                ConsList::Cons(head, tail) => {
+                    head.hash(state);
+                    current = tail;
+                }
+                ConsList::Pair(head, tail) => {
+                    head.hash(state);
+                    tail.hash(state);
+                    break;
                 }
+                ConsList::Nil => break,
             }
-            ConsList::Nil => "".to_string(),
         }
     }
 }
@@ -0,0 +1,73 @@
+use crate::lisptype::LispType;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A lexical scope: its own bindings plus the scope it was created in.
+/// Closures keep their defining `Env` alive, so frames are reference counted.
+#[derive(Default)]
+pub struct Env {
+    vars: RefCell<HashMap<String, LispType>>,
+    parent: Option<Rc<Env>>,
+}
+
+impl Env {
+    /// Creates an empty frame whose lookups fall back to `parent`.
+    pub fn with_parent(parent: Rc<Env>) -> Self {
+        Env {
+            vars: RefCell::new(HashMap::new()),
+            parent: Some(parent),
+        }
+    }
+
+    /// Looks `name` up in this frame, then in the enclosing ones.
+    pub fn get(&self, name: &str) -> Option<LispType> {
+        let mut env = self;
+        loop {
+            if let Some(value) = env.vars.borrow().get(name) {
+                return Some(value.clone());
+            }
+            env = env.parent.as_deref()?;
+        }
+    }
+
+    /// Looks `name` up in this frame only.
+    pub fn get_local(&self, name: &str) -> Option<LispType> {
+        self.vars.borrow().get(name).cloned()
+    }
+
+    /// Binds `name` in this frame, shadowing any outer binding.
+    pub fn define(&self, name: String, value: LispType) {
+        self.vars.borrow_mut().insert(name, value);
+    }
+
+    /// Updates the innermost existing binding of `name`. Returns false when
+    /// the name is not bound anywhere.
+    pub fn set(&self, name: &str, value: LispType) -> bool {
+        let mut env = self;
+        loop {
+            if let Some(slot) = env.vars.borrow_mut().get_mut(name) {
+                *slot = value;
+                return true;
+            }
+            match env.parent.as_deref() {
+                Some(parent) => env = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Frames usually contain closures that point back at them, so the derived
+/// output would never end; only the names bound in each frame are shown.
+impl fmt::Debug for Env {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<String> = self.vars.borrow().keys().cloned().collect();
+        names.sort();
+        f.debug_struct("Env")
+            .field("names", &names)
+            .field("parent", &self.parent)
+            .finish()
+    }
+}
@@ -1,6 +1,13 @@
 use crate::{
+    builtins,
     conslist::ConsList,
-    lisptype::LispType,
+    env::Env,
+    expander::ExpansionCache,
+    generic::{Generic, Method, NextMethod, ANY},
+    lisptype::{Lambda, LispMap, LispSet, LispType, LispVector, Macro},
+    module::{ModuleState, PendingModule},
+    record::{RecordOp, RecordProc, RecordType, SumType},
+    syntax_rules::{base_name, macro_scope, SyntaxRules},
     type_enums::{BinOp, BinPred, SpecForms},
 };
 use std::collections::HashMap;
@@ -15,6 +22,19 @@ pub enum EvaluatorError {
     TypeMismatch(String),
     DivisionByZero,
     UnmatchedBrace,
+    /// A bad `format` directive; `position` is the char index of its `~`.
+    Format {
+        control: String,
+        position: usize,
+        message: String,
+    },
+    /// No clause of a `match` accepted the value.
+    NoMatch(LispType),
+    /// An error in code produced by the macro call `call`.
+    InExpansion {
+        call: LispType,
+        error: Box<EvaluatorError>,
+    },
     Other(String),
 }
 
@@ -26,6 +46,23 @@ impl fmt::Display for EvaluatorError {
             EvaluatorError::TypeMismatch(msg) => write!(f, "Type mismatch: {}", msg),
             EvaluatorError::DivisionByZero => write!(f, "Error: Division by zero"),
             EvaluatorError::UnmatchedBrace => write!(f, "Error: Unmatched brace detected"),
+            EvaluatorError::Format {
+                control,
+                position,
+                message,
+            } => write!(
+                f,
+                "Format error: {}\n  {:?}\n   {}^",
+                message,
+                control,
+                " ".repeat(control.chars().take(*position).map(escaped_width).sum())
+            ),
+            EvaluatorError::NoMatch(value) => {
+                write!(f, "No match: no clause matches {}", value.show())
+            }
+            EvaluatorError::InExpansion { call, error } => {
+                write!(f, "{}\n  in expansion of {}", error, call.show())
+            }
             EvaluatorError::Other(msg) => write!(f, "Error: {}", msg),
         }
     }
@@ -33,50 +70,212 @@ impl fmt::Display for EvaluatorError {
 
 impl std::error::Error for EvaluatorError {}
 
+/// Width of `ch` inside a `{:?}`-quoted string, so the caret of a format
+/// error lines up with its directive.
+fn escaped_width(ch: char) -> usize {
+    ch.escape_debug().count()
+}
+
+/// A `(pattern init)` pair of a let form.
+type Binding = (LispType, LispType);
+
+/// The pattern, optional guard and body of a `match` clause.
+type MatchClause = (LispType, Option<LispType>, Vec<LispType>);
+
+/// Rust stack, in bytes, that nested function calls may use before a call
+/// fails instead of overflowing the stack. It leaves room within the 2 MiB
+/// stack of a spawned thread; see `set_stack_limit`.
+const DEFAULT_STACK_LIMIT: usize = 1 << 20;
+
 /// The main evaluator structure.
 pub struct Evaluator {
-    env: HashMap<String, LispType>,
+    /// The innermost scope; the global one when no function is running.
+    pub(crate) env: Rc<Env>,
+    /// Whether a `loop` body is running, outside of any function call made
+    /// from it. Only then may `recur` be evaluated.
+    in_loop: bool,
+    /// Arguments of an evaluated `recur`, waiting for its `loop` to start the
+    /// next iteration.
+    pending_recur: Option<Vec<LispType>>,
+    /// A lambda call made in tail position of a function body, waiting for
+    /// `call_lambda` to make it in place of the returning body. Recursive
+    /// loops therefore run in constant Rust stack space.
+    pending_call: Option<(Rc<Lambda>, Vec<LispType>)>,
+    /// How many function calls and macro expansions are running, each
+    /// nested in the previous.
+    call_depth: usize,
+    /// The stack address where the outermost of them started.
+    stack_base: usize,
+    /// See `set_stack_limit`.
+    stack_limit: usize,
+    /// Numbers the symbols made by `gensym` and by hygienic renaming.
+    symbol_counter: usize,
+    /// The scopes `syntax-rules` macros were defined in, numbered for the
+    /// symbols they rename, see `macro_scope`.
+    macro_scopes: Vec<Rc<Env>>,
+    pub(crate) expansions: ExpansionCache,
+    /// Problems found while expanding, such as a `match` that misses a
+    /// variant of a sum type, waiting for `take_warnings`.
+    pub(crate) warnings: Vec<String>,
+    /// The `extend-type` implementations of built-in operations, as generic
+    /// functions keyed by operation name.
+    pub(crate) protocols: HashMap<String, Rc<Generic>>,
+    /// The latest record type defined under each name, for the records
+    /// read from `#S(...)` literals.
+    pub(crate) record_types: HashMap<String, Rc<RecordType>>,
+    pub(crate) modules: HashMap<String, ModuleState>,
+    /// The modules whose bodies are running, innermost last.
+    pub(crate) pending_modules: Vec<PendingModule>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The parts of `(dotimes (var count [result]) body...)` and `dolist`.
+struct Iteration<'a> {
+    var: String,
+    source: &'a LispType,
+    result: Option<&'a LispType>,
+    body: &'a [LispType],
 }
 
 impl Evaluator {
     /// Creates a new evaluator with an empty environment.
     pub fn new() -> Self {
         Evaluator {
-            env: HashMap::new(),
+            env: Rc::new(Env::default()),
+            in_loop: false,
+            pending_recur: None,
+            pending_call: None,
+            call_depth: 0,
+            stack_base: 0,
+            stack_limit: DEFAULT_STACK_LIMIT,
+            symbol_counter: 0,
+            macro_scopes: Vec::new(),
+            expansions: ExpansionCache::default(),
+            warnings: Vec::new(),
+            protocols: HashMap::new(),
+            record_types: HashMap::new(),
+            modules: HashMap::new(),
+            pending_modules: Vec::new(),
         }
     }
 
-    /// Evaluates a Lisp expression.
+    /// Sets how much Rust stack, in bytes, nested function calls and macro
+    /// expansions may use. Deeper recursion fails with an error rather than
+    /// overflowing the stack, so the limit must be somewhat below the size
+    /// of the stack of the thread that evaluates.
+    pub fn set_stack_limit(&mut self, bytes: usize) {
+        self.stack_limit = bytes;
+    }
+
+    /// Returns the warnings produced since the last call and forgets them.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// A fresh uninterned symbol name such as `#:g12`. The reader never
+    /// produces a `#:` symbol, so it cannot clash with one written in code.
+    pub fn gensym(&mut self, prefix: &str) -> String {
+        self.symbol_counter += 1;
+        format!("#:{}{}", prefix, self.symbol_counter)
+    }
+
+    /// Evaluates a Lisp expression: its macros are expanded first, see
+    /// `expand`, then the expanded form is evaluated.
     pub fn eval(&mut self, expr: LispType) -> Result<LispType, EvaluatorError> {
+        let expanded = self.expand(&expr)?;
+        self.eval_core(expanded)
+    }
+
+    /// Evaluates an expression whose macros have been expanded.
+    fn eval_core(&mut self, expr: LispType) -> Result<LispType, EvaluatorError> {
+        self.eval_form(expr, false)
+    }
+
+    /// Evaluates an expanded expression. `tail` is true when its value is
+    /// the value of the running function body, so that a lambda call can
+    /// be left to `call_lambda`, see `pending_call`.
+    fn eval_form(&mut self, expr: LispType, tail: bool) -> Result<LispType, EvaluatorError> {
         match expr {
-            LispType::Cons(list) => self.eval_list(list),
+            LispType::Cons(list) => self.eval_list(list, tail),
             LispType::Symbol(s) => self.eval_symbol(s),
+            LispType::HashMap(map) => self.eval_map(&map),
+            LispType::Vector(vector) => self.eval_vector(&vector),
+            LispType::Set(set) => self.eval_set_literal(&set),
             _ => Ok(expr),
         }
     }
 
-    /// Evaluates a symbol by looking it up in the environment.
+    /// Evaluates the keys and values of a map literal such as `{"a" (+ 1 2)}`.
+    fn eval_map(&mut self, map: &LispMap) -> Result<LispType, EvaluatorError> {
+        let mut result = LispMap::default();
+        for (key, value) in map {
+            result.insert(self.eval_core(key.clone())?, self.eval_core(value.clone())?);
+        }
+        Ok(LispType::HashMap(Rc::new(result)))
+    }
+
+    /// Evaluates the elements of a vector literal such as `[x (+ 1 2)]`.
+    /// The resulting vector is immutable, like the literal itself.
+    fn eval_vector(&mut self, vector: &LispVector) -> Result<LispType, EvaluatorError> {
+        let items = vector.items.borrow().clone();
+        let items = items
+            .into_iter()
+            .map(|item| self.eval_core(item))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(LispType::Vector(Rc::new(LispVector::new(items, false))))
+    }
+
+    /// Evaluates the elements of a set literal such as `#{x (+ 1 2)}`.
+    fn eval_set_literal(&mut self, set: &LispSet) -> Result<LispType, EvaluatorError> {
+        let items = set
+            .iter()
+            .map(|item| self.eval_core(item.clone()))
+            .collect::<Result<LispSet, _>>()?;
+        Ok(LispType::Set(Rc::new(items)))
+    }
+
+    /// Evaluates a symbol by looking it up in the environment, see `lookup`.
     fn eval_symbol(&mut self, sym: String) -> Result<LispType, EvaluatorError> {
-        self.env
-            .get(&sym)
-            .cloned()
-            .ok_or(EvaluatorError::UndefinedSymbol(sym))
+        if let Some(value) = self.lookup(&sym) {
+            return Ok(value);
+        }
+        match self.qualified_value(&sym) {
+            Some(result) => result,
+            None => Err(EvaluatorError::UndefinedSymbol(sym)),
+        }
     }
 
-    /// Evaluates a Lisp list.
-    fn eval_list(&mut self, list: Rc<ConsList>) -> Result<LispType, EvaluatorError> {
+    /// Evaluates a Lisp list. Errors in the expansion of a macro call name
+    /// that call.
+    fn eval_list(&mut self, list: Rc<ConsList>, tail: bool) -> Result<LispType, EvaluatorError> {
+        self.eval_call(list.clone(), tail)
+            .map_err(|error| match self.expansions.origin(&list) {
+                Some(call) => EvaluatorError::InExpansion {
+                    call: call.clone(),
+                    error: Box::new(error),
+                },
+                None => error,
+            })
+    }
+
+    fn eval_call(&mut self, list: Rc<ConsList>, tail: bool) -> Result<LispType, EvaluatorError> {
         let elements = self.list_to_vec(list.clone())?;
 
         if elements.is_empty() {
-            return Err(EvaluatorError::InvalidArguments(
-                "Cannot evaluate an empty list".to_string(),
-            ));
+            return Ok(LispType::nil());
         }
 
         let first_elem = &elements[0];
 
         match first_elem {
-            LispType::SpecForm(spec_form) => self.eval_spec_form(spec_form.clone(), &elements[1..]),
+            LispType::SpecForm(spec_form) => {
+                self.eval_spec_form(spec_form.clone(), &elements[1..], tail)
+            }
 
             LispType::BinOp(bin_op) => {
                 if elements.len() != 3 {
@@ -85,13 +284,9 @@ impl Evaluator {
                         bin_op
                     )));
                 }
-                let arg1 = self.eval(elements[1].clone())?;
-                let arg2 = self.eval(elements[2].clone())?;
-                Evaluator::apply_bo(bin_op.clone(), arg1, arg2).and_then(|opt| {
-                    opt.ok_or(EvaluatorError::Other(
-                        "BinOp application failed".to_string(),
-                    ))
-                })
+                let arg1 = self.eval_core(elements[1].clone())?;
+                let arg2 = self.eval_core(elements[2].clone())?;
+                self.apply_binop(bin_op, arg1, arg2)
             }
 
             LispType::BinPred(bin_pred) => {
@@ -101,48 +296,336 @@ impl Evaluator {
                         bin_pred
                     )));
                 }
-                let arg1 = self.eval(elements[1].clone())?;
-                let arg2 = self.eval(elements[2].clone())?;
-                Evaluator::apply_bp(bin_pred.clone(), &arg1, &arg2).and_then(|opt| {
-                    opt.map(LispType::Bool).ok_or(EvaluatorError::Other(
-                        "BinPred application failed".to_string(),
-                    ))
-                })
+                let arg1 = self.eval_core(elements[1].clone())?;
+                let arg2 = self.eval_core(elements[2].clone())?;
+                self.apply_binpred(bin_pred, arg1, arg2)
+            }
+
+            LispType::Builtin(builtin) => {
+                let args = self.eval_args(&elements[1..])?;
+                builtins::apply(self, builtin, args)
             }
 
-            LispType::Symbol(s) => Err(EvaluatorError::TypeMismatch(format!(
-                "Cannot apply operator to symbol: {}",
-                s
+            // Any other head is an expression that must produce a function,
+            // such as a variable bound to a lambda or a lambda form itself.
+            head => {
+                let function = self.eval_core(head.clone())?;
+                // A macro defined after this form was expanded.
+                if let LispType::Macro(mac) = &function {
+                    let expansion =
+                        self.expand_call(mac, &LispType::Cons(list), &mut Vec::new())?;
+                    return self.eval_form(expansion, tail);
+                }
+                let args = self.eval_args(&elements[1..])?;
+                match function {
+                    LispType::Lambda(lambda) if tail => {
+                        self.pending_call = Some((lambda, args));
+                        Ok(LispType::nil())
+                    }
+                    function => self.apply_function(&function, args),
+                }
+            }
+        }
+    }
+
+    fn eval_args(&mut self, args: &[LispType]) -> Result<Vec<LispType>, EvaluatorError> {
+        args.iter().map(|arg| self.eval_core(arg.clone())).collect()
+    }
+
+    /// Calls a function value with already evaluated arguments. Lambdas,
+    /// builtins and the binary operators and predicates are all callable,
+    /// so `(map + xs ys)` works as well as `(map (lambda (x) ...) xs)`.
+    pub fn apply_function(
+        &mut self,
+        function: &LispType,
+        args: Vec<LispType>,
+    ) -> Result<LispType, EvaluatorError> {
+        match function {
+            LispType::Lambda(lambda) => self.call_lambda(lambda, args),
+            LispType::RecordProc(proc) => proc.apply(args),
+            LispType::Generic(generic) => self.call_generic(generic, args),
+            LispType::NextMethod(next) => {
+                if next.methods.is_empty() {
+                    return Err(EvaluatorError::Other(format!(
+                        "{}: no next method",
+                        next.generic.name
+                    )));
+                }
+                // Without arguments, the next method gets those of the call.
+                let args = if args.is_empty() {
+                    next.args.clone()
+                } else {
+                    args
+                };
+                self.call_method(&next.generic, next.methods.clone(), args)
+            }
+            LispType::Builtin(builtin) => builtins::apply(self, builtin, args),
+            LispType::BinOp(op) => {
+                let [a, b] = Self::binary_args(op.to_string(), args)?;
+                self.apply_binop(op, a, b)
+            }
+            LispType::BinPred(pred) => {
+                let [a, b] = Self::binary_args(pred.to_string(), args)?;
+                self.apply_binpred(pred, a, b)
+            }
+            other => Err(EvaluatorError::TypeMismatch(format!(
+                "{} is not a function",
+                other.show()
             ))),
+        }
+    }
+
+    fn binary_args(name: &str, args: Vec<LispType>) -> Result<[LispType; 2], EvaluatorError> {
+        args.try_into().map_err(|_| {
+            EvaluatorError::InvalidArguments(format!("{} requires exactly two arguments", name))
+        })
+    }
+
+    /// Binds the arguments in a new frame on top of the lambda's captured
+    /// environment and evaluates the body there. Calls the body makes in
+    /// tail position are made here in turn, once the body has returned.
+    fn call_lambda(
+        &mut self,
+        lambda: &Lambda,
+        args: Vec<LispType>,
+    ) -> Result<LispType, EvaluatorError> {
+        // Calls that are not tail calls nest on the Rust stack.
+        let overflow = |depth| format!("stack overflow: {} nested calls", depth);
+        self.nested(overflow, |this| {
+            // A recur in the function body cannot target the caller's loop.
+            let caller_in_loop = std::mem::replace(&mut this.in_loop, false);
+            let mut result = this.eval_lambda_body(lambda, args);
+            while result.is_ok() {
+                let Some((lambda, args)) = this.pending_call.take() else {
+                    break;
+                };
+                result = this.eval_lambda_body(&lambda, args);
+            }
+            this.pending_call = None;
+            this.in_loop = caller_in_loop;
+            result
+        })
+    }
+
+    /// Runs `run` as a function call or macro expansion nested in the
+    /// running ones. Fails with the message `overflow` makes of the nesting
+    /// depth instead when the Rust stack has grown past the limit.
+    pub(crate) fn nested<T>(
+        &mut self,
+        overflow: impl FnOnce(usize) -> String,
+        run: impl FnOnce(&mut Self) -> Result<T, EvaluatorError>,
+    ) -> Result<T, EvaluatorError> {
+        let marker = 0u8;
+        let here = std::ptr::addr_of!(marker) as usize;
+        if self.call_depth == 0 {
+            self.stack_base = here;
+        } else if self.stack_base.saturating_sub(here) > self.stack_limit {
+            return Err(EvaluatorError::Other(overflow(self.call_depth)));
+        }
+
+        self.call_depth += 1;
+        let result = run(self);
+        self.call_depth -= 1;
+        result
+    }
+
+    /// Checks the arity, binds the arguments and evaluates the body, whose
+    /// last form is in tail position.
+    fn eval_lambda_body(
+        &mut self,
+        lambda: &Lambda,
+        mut args: Vec<LispType>,
+    ) -> Result<LispType, EvaluatorError> {
+        let arity_ok = match lambda.rest {
+            Some(_) => args.len() >= lambda.params.len(),
+            None => args.len() == lambda.params.len(),
+        };
+        if !arity_ok {
+            return Err(EvaluatorError::InvalidArguments(format!(
+                "lambda expects {}{} argument{}, got {}",
+                if lambda.rest.is_some() {
+                    "at least "
+                } else {
+                    ""
+                },
+                lambda.params.len(),
+                if lambda.params.len() == 1 { "" } else { "s" },
+                args.len()
+            )));
+        }
+
+        let frame = Env::with_parent(lambda.env.clone());
+        let rest = args.split_off(lambda.params.len());
+        for (param, arg) in lambda.params.iter().zip(args) {
+            Self::bind_pattern(&frame, param, arg)?;
+        }
+        if let Some(name) = &lambda.rest {
+            frame.define(name.clone(), LispType::list(rest));
+        }
+
+        self.eval_in_env(Rc::new(frame), &lambda.body, true)
+    }
+
+    /// Evaluates `body` with `env` as the current scope, restoring the
+    /// caller's scope afterwards even when evaluation fails. `tail` is as
+    /// for `eval_form` and applies to the last form.
+    pub(crate) fn eval_in_env(
+        &mut self,
+        env: Rc<Env>,
+        body: &[LispType],
+        tail: bool,
+    ) -> Result<LispType, EvaluatorError> {
+        let caller_env = std::mem::replace(&mut self.env, env);
+        let result = self.eval_body(body, tail);
+        self.env = caller_env;
+        result
+    }
+
+    /// Binds the symbols of `pattern` in `env`. A pattern is a symbol or a
+    /// list of patterns, possibly dotted: `(a (b c) . rest)` destructures
+    /// `(1 (2 3) 4 5)` into `a` = 1, `b` = 2, `c` = 3 and `rest` = `(4 5)`.
+    fn bind_pattern(env: &Env, pattern: &LispType, value: LispType) -> Result<(), EvaluatorError> {
+        let mismatch = |value: &LispType| {
+            EvaluatorError::InvalidArguments(format!(
+                "cannot destructure {} with pattern {}",
+                value.show(),
+                pattern.show()
+            ))
+        };
+
+        let mut pattern_cell: &ConsList = match pattern {
+            LispType::Symbol(name) => {
+                env.define(name.clone(), value);
+                return Ok(());
+            }
+            LispType::Cons(list) => list,
+            other => {
+                return Err(EvaluatorError::TypeMismatch(format!(
+                    "binding patterns must be symbols or lists, got {}",
+                    other.show()
+                )))
+            }
+        };
+
+        let mut current = value.clone();
+        loop {
+            if let ConsList::Nil = pattern_cell {
+                return match current.is_nil() {
+                    true => Ok(()),
+                    false => Err(mismatch(&value)),
+                };
+            }
+            let (item, tail) = match &current {
+                LispType::Cons(list) => match (list.car(), list.cdr()) {
+                    (Some(item), Some(tail)) => (item.clone(), tail),
+                    _ => return Err(mismatch(&value)),
+                },
+                _ => return Err(mismatch(&value)),
+            };
+            match pattern_cell {
+                ConsList::Cons(head, rest) => {
+                    Self::bind_pattern(env, head, item)?;
+                    pattern_cell = rest;
+                    current = tail;
+                }
+                ConsList::Pair(head, rest) => {
+                    Self::bind_pattern(env, head, item)?;
+                    return Self::bind_pattern(env, rest, tail);
+                }
+                ConsList::Nil => unreachable!("handled above"),
+            }
+        }
+    }
 
-            LispType::Cons(inner_list) => {
-                let inner_eval_result = self.eval_list(inner_list.clone())?;
-                return Ok(inner_eval_result);
+    /// Every symbol a pattern binds, in order.
+    pub(crate) fn pattern_symbols(pattern: &LispType, symbols: &mut Vec<String>) {
+        match pattern {
+            LispType::Symbol(name) => symbols.push(name.clone()),
+            LispType::Cons(list) => {
+                let mut current: &ConsList = list;
+                loop {
+                    match current {
+                        ConsList::Cons(head, rest) => {
+                            Self::pattern_symbols(head, symbols);
+                            current = rest;
+                        }
+                        ConsList::Pair(head, rest) => {
+                            Self::pattern_symbols(head, symbols);
+                            Self::pattern_symbols(rest, symbols);
+                            break;
+                        }
+                        ConsList::Nil => break,
+                    }
+                }
             }
+            _ => {}
+        }
+    }
 
-            _ => Err(EvaluatorError::TypeMismatch(
-                "The first element of the list must be a SpecForm, BinOp, or BinPred".to_string(),
-            )),
+    /// Evaluates forms in order and returns the value of the last one, which
+    /// is in tail position when `tail` is.
+    fn eval_body(&mut self, body: &[LispType], tail: bool) -> Result<LispType, EvaluatorError> {
+        let Some((last, init)) = body.split_last() else {
+            return Ok(LispType::nil());
+        };
+        for form in init {
+            self.eval_core(form.clone())?;
         }
+        self.eval_form(last.clone(), tail)
     }
 
     /// Evaluates special forms like def, set, get, quote, etc.
+    /// `tail` is as for `eval_form`; forms that end in one of their
+    /// subforms, such as `if`, pass it on.
     fn eval_spec_form(
         &mut self,
         spec_form: SpecForms,
         args: &[LispType],
+        tail: bool,
     ) -> Result<LispType, EvaluatorError> {
         match spec_form {
             SpecForms::DEF => self.eval_def(args),
             SpecForms::SET => self.eval_set(args),
             SpecForms::GET => self.eval_get(args),
             SpecForms::QUOTE => self.eval_quote(args),
+            SpecForms::QUASIQUOTE => self.eval_quasiquote(args),
+            SpecForms::UNQUOTE | SpecForms::UNQUOTESPLICING => Err(EvaluatorError::Other(format!(
+                "{} used outside of quasiquote",
+                spec_form.to_string()
+            ))),
             SpecForms::EVAL => self.eval_eval(args),
             SpecForms::PRINT => self.eval_print(args),
-            SpecForms::CAR => self.eval_car(args),
-            SpecForms::CDR => self.eval_cdr(args),
-            SpecForms::CONS => self.eval_cons(args),
-            SpecForms::DO => self.eval_do(args),
+            SpecForms::DO => self.eval_do(args, tail),
+            SpecForms::COND => self.eval_cond(args, tail),
+            SpecForms::LAMBDA => self.eval_lambda(args),
+            SpecForms::LET => self.eval_let(args, tail),
+            SpecForms::LETSTAR => self.eval_let_star(args, tail),
+            SpecForms::LETREC => self.eval_letrec(args, tail),
+            SpecForms::WHILE => self.eval_while(args),
+            SpecForms::LOOP => self.eval_loop(args),
+            SpecForms::RECUR => self.eval_recur(args),
+            SpecForms::DOTIMES => self.eval_dotimes(args),
+            SpecForms::DOLIST => self.eval_dolist(args),
+            SpecForms::IF => self.eval_if(args, tail),
+            SpecForms::WHEN | SpecForms::UNLESS => self.eval_when(spec_form, args, tail),
+            SpecForms::AND => self.eval_and(args, tail),
+            SpecForms::OR => self.eval_or(args, tail),
+            SpecForms::CASE => self.eval_case(args, tail),
+            SpecForms::MATCH => self.eval_match(args, tail),
+            SpecForms::MACRO => self.eval_macro(args),
+            SpecForms::MACROEXPAND => self.eval_macroexpand(args),
+            SpecForms::DEFINESYNTAX => self.eval_define_syntax(args),
+            SpecForms::SYNTAXRULES => self.eval_syntax_rules(args),
+            SpecForms::TYPEOF => self.eval_typeof(args),
+            SpecForms::DEFSTRUCT => self.eval_defstruct(args),
+            SpecForms::DEFINERECORDTYPE => self.eval_define_record_type(args),
+            SpecForms::DEFTYPE => self.eval_deftype(args),
+            SpecForms::DEFGENERIC => self.eval_defgeneric(args),
+            SpecForms::DEFMETHOD => self.eval_defmethod(args),
+            SpecForms::EXTENDTYPE => self.eval_extend_type(args),
+            SpecForms::MODULE => self.eval_module(args),
+            SpecForms::EXPORT => self.eval_export(args),
+            SpecForms::IMPORT => self.eval_import(args),
             _ => Err(EvaluatorError::UndefinedSymbol("undef symb".to_string())),
         }
     }
@@ -163,8 +646,9 @@ impl Evaluator {
                 ))
             }
         };
-        let value = self.eval(args[1].clone())?;
-        self.env.insert(symbol.clone(), value.clone());
+        let value = self.eval_core(args[1].clone())?;
+        self.note_definition(&symbol, &value);
+        self.env.define(symbol, value.clone());
         Ok(value)
     }
 
@@ -183,9 +667,13 @@ impl Evaluator {
                 ))
             }
         };
-        let value = self.eval(args[1].clone())?;
-        if self.env.contains_key(&symbol) {
-            self.env.insert(symbol.clone(), value.clone());
+        let value = self.eval_core(args[1].clone())?;
+        self.note_definition(&symbol, &value);
+        let set = self.env.set(&symbol, value.clone())
+            || self
+                .macro_env(&symbol)
+                .is_some_and(|env| env.set(base_name(&symbol), value.clone()));
+        if set {
             Ok(value)
         } else {
             Err(EvaluatorError::UndefinedSymbol(symbol))
@@ -220,6 +708,113 @@ impl Evaluator {
         Ok(args[0].clone())
     }
 
+    /// Handles the quasiquote special form.
+    fn eval_quasiquote(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        if args.len() != 1 {
+            return Err(EvaluatorError::InvalidArguments(
+                "quasiquote requires exactly one argument".to_string(),
+            ));
+        }
+        self.quasiquote(&args[0], 1)
+    }
+
+    /// Expands a quasiquote template. `depth` counts the enclosing quasiquotes
+    /// not yet cancelled by an unquote; only forms at depth 1 are evaluated.
+    fn quasiquote(
+        &mut self,
+        template: &LispType,
+        depth: usize,
+    ) -> Result<LispType, EvaluatorError> {
+        let list = match template {
+            LispType::Cons(list) => list.clone(),
+            _ => return Ok(template.clone()),
+        };
+
+        if let Some((form, arg)) = Self::as_quasi_form(&list) {
+            return match form {
+                SpecForms::UNQUOTE if depth == 1 => self.eval_core(arg),
+                SpecForms::UNQUOTESPLICING if depth == 1 => Err(EvaluatorError::Other(
+                    "unquote-splicing must appear inside a list".to_string(),
+                )),
+                SpecForms::UNQUOTE | SpecForms::UNQUOTESPLICING => {
+                    let inner = self.quasiquote(&arg, depth - 1)?;
+                    Ok(LispType::list(vec![LispType::SpecForm(form), inner]))
+                }
+                _ => {
+                    let inner = self.quasiquote(&arg, depth + 1)?;
+                    Ok(LispType::list(vec![LispType::SpecForm(form), inner]))
+                }
+            };
+        }
+
+        // Walk the cells rather than the elements, so that a dotted tail is
+        // kept. The reader turns `(a . ,x)` into `(a unquote x)`, so a rest
+        // of the list that is itself a quasi form is the tail.
+        let mut items = Vec::new();
+        let mut current: &ConsList = &list;
+        let tail = loop {
+            match current {
+                ConsList::Cons(item, rest) => {
+                    self.quasiquote_item(item, depth, &mut items)?;
+                    if Self::as_quasi_form(rest).is_some() {
+                        break self.quasiquote(&LispType::Cons(rest.clone()), depth)?;
+                    }
+                    current = rest;
+                }
+                ConsList::Pair(item, tail) => {
+                    self.quasiquote_item(item, depth, &mut items)?;
+                    break self.quasiquote(tail, depth)?;
+                }
+                ConsList::Nil => break LispType::nil(),
+            }
+        };
+        Ok(ConsList::from(items).append(tail))
+    }
+
+    /// Quasiquotes one element of a list template onto `items`, splicing it
+    /// in when it is `(unquote-splicing x)` at depth 1.
+    fn quasiquote_item(
+        &mut self,
+        item: &LispType,
+        depth: usize,
+        items: &mut Vec<LispType>,
+    ) -> Result<(), EvaluatorError> {
+        let splice = match item {
+            LispType::Cons(inner) => match Self::as_quasi_form(inner) {
+                Some((SpecForms::UNQUOTESPLICING, arg)) if depth == 1 => Some(arg),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match splice {
+            Some(arg) => match self.eval_core(arg)? {
+                LispType::Cons(spliced) => items.extend(self.list_to_vec(spliced)?),
+                _ => {
+                    return Err(EvaluatorError::TypeMismatch(
+                        "unquote-splicing expects a list".to_string(),
+                    ))
+                }
+            },
+            None => items.push(self.quasiquote(item, depth)?),
+        }
+        Ok(())
+    }
+
+    /// Recognizes `(quasiquote x)`, `(unquote x)` and `(unquote-splicing x)`.
+    pub(crate) fn as_quasi_form(list: &ConsList) -> Option<(SpecForms, LispType)> {
+        match list {
+            ConsList::Cons(LispType::SpecForm(form), tail) => match (form, &**tail) {
+                (
+                    SpecForms::QUASIQUOTE | SpecForms::UNQUOTE | SpecForms::UNQUOTESPLICING,
+                    ConsList::Cons(arg, rest),
+                ) if matches!(**rest, ConsList::Nil) => Some((form.clone(), arg.clone())),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     /// Handles the eval special form.
     fn eval_eval(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
         if args.len() != 1 {
@@ -227,96 +822,1360 @@ impl Evaluator {
                 "eval requires exactly one argument".to_string(),
             ));
         }
-        let expr = self.eval(args[0].clone())?;
+        let expr = self.eval_core(args[0].clone())?;
         self.eval(expr)
     }
 
     /// Handles the print special form.
     fn eval_print(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
         for arg in args {
-            let val = self.eval(arg.clone())?;
-            println!("{}", val.show());
+            let val = self.eval_core(arg.clone())?;
+            println!("{}", self.show_value(&val, false)?);
         }
         Ok(LispType::Bool(true))
     }
 
-    /// Handles the car special form.
-    fn eval_car(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
-        if args.len() != 1 {
+    /// Handles the cond special form: `(cond test1 expr1 test2 expr2 ... [default])`.
+    /// Tests are checked in order with `LispType::is_truthy`; when none passes
+    /// the optional default is evaluated, otherwise the result is `nil`.
+    fn eval_cond(&mut self, args: &[LispType], tail: bool) -> Result<LispType, EvaluatorError> {
+        for clause in args.chunks(2) {
+            match clause {
+                [test, expr] => {
+                    if self.eval_core(test.clone())?.is_truthy() {
+                        return self.eval_form(expr.clone(), tail);
+                    }
+                }
+                [default] => return self.eval_form(default.clone(), tail),
+                _ => unreachable!(),
+            }
+        }
+        Ok(LispType::nil())
+    }
+
+    /// Handles `(lambda (params...) body...)`. The parameter list may end in
+    /// `. rest`, or be a single symbol that receives all arguments as a list.
+    fn eval_lambda(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        if args.len() < 2 {
             return Err(EvaluatorError::InvalidArguments(
-                "car requires exactly one argument".to_string(),
+                "lambda requires a parameter list and a body".to_string(),
             ));
         }
-        let lst = self.eval(args[0].clone())?;
-        match lst {
-            LispType::Cons(cons_list) => match &*cons_list {
-                ConsList::Cons(head, _) => Ok(head.clone()),
-                ConsList::Nil => Err(EvaluatorError::InvalidArguments(
-                    "Cannot take car of an empty list".to_string(),
-                )),
+
+        let rest_name = |param: &LispType| match param {
+            LispType::Symbol(s) => Ok(s.clone()),
+            other => Err(EvaluatorError::TypeMismatch(format!(
+                "the rest parameter must be a symbol, got {}",
+                other.show()
+            ))),
+        };
+
+        let mut params = Vec::new();
+        let mut rest = None;
+        match &args[0] {
+            LispType::Cons(list) => {
+                let mut current: &ConsList = list;
+                loop {
+                    match current {
+                        ConsList::Cons(param, tail) => {
+                            Self::check_pattern(param)?;
+                            params.push(param.clone());
+                            current = tail;
+                        }
+                        ConsList::Pair(param, tail) => {
+                            Self::check_pattern(param)?;
+                            params.push(param.clone());
+                            rest = Some(rest_name(tail)?);
+                            break;
+                        }
+                        ConsList::Nil => break,
+                    }
+                }
+            }
+            other => rest = Some(rest_name(other)?),
+        }
+
+        Ok(LispType::Lambda(Rc::new(Lambda {
+            params,
+            rest,
+            body: args[1..].to_vec(),
+            env: self.env.clone(),
+        })))
+    }
+
+    /// Handles `(let ((pattern init) ...) body...)`: every init is evaluated
+    /// in the current scope, then all patterns are bound in one new frame.
+    /// With a name before the bindings, `(let name ((var init) ...) body...)`
+    /// binds `name` to a function of the variables and calls it, so the body
+    /// can loop by calling `name` again.
+    fn eval_let(&mut self, args: &[LispType], tail: bool) -> Result<LispType, EvaluatorError> {
+        match args.first() {
+            Some(LispType::Symbol(name)) => return self.eval_named_let(name, &args[1..], tail),
+            Some(LispType::SpecForm(form)) => {
+                return Err(EvaluatorError::InvalidArguments(format!(
+                    "let: {} is a reserved form and cannot name a let",
+                    form.to_string()
+                )))
+            }
+            _ => {}
+        }
+
+        let (bindings, body) = Self::let_parts(SpecForms::LET, args)?;
+        let frame = Env::with_parent(self.env.clone());
+        for (pattern, init) in bindings {
+            let value = self.eval_core(init)?;
+            Self::bind_pattern(&frame, &pattern, value)?;
+        }
+        self.eval_in_env(Rc::new(frame), body, tail)
+    }
+
+    fn eval_named_let(
+        &mut self,
+        name: &str,
+        args: &[LispType],
+        tail: bool,
+    ) -> Result<LispType, EvaluatorError> {
+        let (bindings, body) = Self::let_parts(SpecForms::LET, args)?;
+        let mut params = Vec::new();
+        let mut values = Vec::new();
+        for (pattern, init) in bindings {
+            params.push(pattern);
+            values.push(self.eval_core(init)?);
+        }
+
+        let frame = Rc::new(Env::with_parent(self.env.clone()));
+        let lambda = Rc::new(Lambda {
+            params,
+            rest: None,
+            body: body.to_vec(),
+            env: frame.clone(),
+        });
+        frame.define(name.to_string(), LispType::Lambda(lambda.clone()));
+        if tail {
+            self.pending_call = Some((lambda, values));
+            return Ok(LispType::nil());
+        }
+        self.call_lambda(&lambda, values)
+    }
+
+    /// Handles `let*`: like `let`, but each init sees the bindings before it.
+    fn eval_let_star(&mut self, args: &[LispType], tail: bool) -> Result<LispType, EvaluatorError> {
+        let (bindings, body) = Self::let_parts(SpecForms::LETSTAR, args)?;
+        let mut env = self.env.clone();
+        for (pattern, init) in bindings {
+            let value = self.eval_in_env(env.clone(), &[init], false)?;
+            let frame = Env::with_parent(env);
+            Self::bind_pattern(&frame, &pattern, value)?;
+            env = Rc::new(frame);
+        }
+        self.eval_in_env(Rc::new(Env::with_parent(env)), body, tail)
+    }
+
+    /// Handles `letrec`: the inits are evaluated in the new frame, so
+    /// lambdas among them can refer to each other. A variable read before
+    /// its init has run is `nil`.
+    fn eval_letrec(&mut self, args: &[LispType], tail: bool) -> Result<LispType, EvaluatorError> {
+        let (bindings, body) = Self::let_parts(SpecForms::LETREC, args)?;
+        let frame = Rc::new(Env::with_parent(self.env.clone()));
+        let mut symbols = Vec::new();
+        for (pattern, _) in &bindings {
+            Self::pattern_symbols(pattern, &mut symbols);
+        }
+        for symbol in symbols {
+            frame.define(symbol, LispType::nil());
+        }
+        for (pattern, init) in bindings {
+            let value = self.eval_in_env(frame.clone(), &[init], false)?;
+            Self::bind_pattern(&frame, &pattern, value)?;
+        }
+        self.eval_in_env(frame, body, tail)
+    }
+
+    /// Splits the arguments of a let form into its `(pattern init)` pairs
+    /// and its body.
+    fn let_parts(
+        form: SpecForms,
+        args: &[LispType],
+    ) -> Result<(Vec<Binding>, &[LispType]), EvaluatorError> {
+        let malformed = || {
+            EvaluatorError::InvalidArguments(format!(
+                "{} requires a list of (pattern value) bindings and a body",
+                form.to_string()
+            ))
+        };
+
+        let (bindings, body) = match args {
+            [LispType::Cons(bindings), body @ ..] if !body.is_empty() && bindings.is_proper() => {
+                (bindings, body)
+            }
+            _ => return Err(malformed()),
+        };
+        let bindings = bindings
+            .iter()
+            .map(|binding| match binding {
+                LispType::Cons(pair) if pair.is_proper() && pair.len() == 2 => {
+                    let pattern = pair.nth(0).unwrap().clone();
+                    Self::check_pattern(&pattern)?;
+                    Ok((pattern, pair.nth(1).unwrap().clone()))
+                }
+                _ => Err(malformed()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((bindings, body))
+    }
+
+    /// Checks that `pattern` is a symbol or a (possibly dotted) list of
+    /// patterns, so that mistakes are reported where the form is written.
+    pub(crate) fn check_pattern(pattern: &LispType) -> Result<(), EvaluatorError> {
+        match pattern {
+            LispType::Symbol(_) => Ok(()),
+            LispType::Cons(list) => {
+                let mut current: &ConsList = list;
+                loop {
+                    match current {
+                        ConsList::Cons(head, rest) => {
+                            Self::check_pattern(head)?;
+                            current = rest;
+                        }
+                        ConsList::Pair(head, rest) => {
+                            Self::check_pattern(head)?;
+                            return Self::check_pattern(rest);
+                        }
+                        ConsList::Nil => return Ok(()),
+                    }
+                }
+            }
+            other => Err(EvaluatorError::TypeMismatch(format!(
+                "binding patterns must be symbols or lists, got {}",
+                other.show()
+            ))),
+        }
+    }
+
+    /// Handles `(if test then [else])`. Without an else branch a false test
+    /// gives `nil`.
+    fn eval_if(&mut self, args: &[LispType], tail: bool) -> Result<LispType, EvaluatorError> {
+        let (test, then, otherwise) = match args {
+            [test, then] => (test, then, None),
+            [test, then, otherwise] => (test, then, Some(otherwise)),
+            _ => {
+                return Err(EvaluatorError::InvalidArguments(
+                    "if requires a test, a then branch and an optional else branch".to_string(),
+                ))
+            }
+        };
+        if self.eval_core(test.clone())?.is_truthy() {
+            self.eval_form(then.clone(), tail)
+        } else {
+            otherwise.map_or(Ok(LispType::nil()), |form| {
+                self.eval_form(form.clone(), tail)
+            })
+        }
+    }
+
+    /// Handles `(when test body...)` and `(unless test body...)`, which run
+    /// the body when the test is true or false respectively, and otherwise
+    /// return `nil`.
+    fn eval_when(
+        &mut self,
+        form: SpecForms,
+        args: &[LispType],
+        tail: bool,
+    ) -> Result<LispType, EvaluatorError> {
+        let (test, body) = args.split_first().ok_or_else(|| {
+            EvaluatorError::InvalidArguments(format!("{} requires a test", form.to_string()))
+        })?;
+        let expected = matches!(form, SpecForms::WHEN);
+        if self.eval_core(test.clone())?.is_truthy() == expected {
+            self.eval_body(body, tail)
+        } else {
+            Ok(LispType::nil())
+        }
+    }
+
+    /// Handles `(and forms...)`: the first false value, or the last value
+    /// when all are true. `(and)` is `#t`.
+    fn eval_and(&mut self, args: &[LispType], tail: bool) -> Result<LispType, EvaluatorError> {
+        let Some((last, init)) = args.split_last() else {
+            return Ok(LispType::Bool(true));
+        };
+        for form in init {
+            let result = self.eval_core(form.clone())?;
+            if !result.is_truthy() {
+                return Ok(result);
+            }
+        }
+        self.eval_form(last.clone(), tail)
+    }
+
+    /// Handles `(or forms...)`: the first true value, or the last value when
+    /// all are false. `(or)` is `#f`.
+    fn eval_or(&mut self, args: &[LispType], tail: bool) -> Result<LispType, EvaluatorError> {
+        let Some((last, init)) = args.split_last() else {
+            return Ok(LispType::Bool(false));
+        };
+        for form in init {
+            let result = self.eval_core(form.clone())?;
+            if result.is_truthy() {
+                return Ok(result);
+            }
+        }
+        self.eval_form(last.clone(), tail)
+    }
+
+    /// Handles `(case key ((datum...) body...) ... [(else body...)])`. The key
+    /// is evaluated and compared with the unevaluated data using structural
+    /// equality, as `equal?` does; a single datum need not be in a list.
+    /// Returns `nil` when no clause matches.
+    fn eval_case(&mut self, args: &[LispType], tail: bool) -> Result<LispType, EvaluatorError> {
+        let (key, clauses) = args
+            .split_first()
+            .ok_or_else(|| EvaluatorError::InvalidArguments("case requires a key".to_string()))?;
+        let key = self.eval_core(key.clone())?;
+
+        for clause in clauses {
+            let (data, body) = match clause {
+                LispType::Cons(clause) if clause.is_proper() && !clause.is_empty() => {
+                    let items = self.list_to_vec(clause.clone())?;
+                    let (data, body) = items.split_first().expect("checked non-empty above");
+                    (data.clone(), body.to_vec())
+                }
+                other => {
+                    return Err(EvaluatorError::InvalidArguments(format!(
+                        "case clauses must be (data body...), got {}",
+                        other.show()
+                    )))
+                }
+            };
+            let matches = match &data {
+                LispType::Symbol(s) if base_name(s) == "else" => true,
+                LispType::Cons(data) => data.iter().any(|datum| *datum == key),
+                datum => *datum == key,
+            };
+            if matches {
+                return self.eval_body(&body, tail);
+            }
+        }
+        Ok(LispType::nil())
+    }
+
+    /// Handles `(match expr (pattern [when guard] body...) ...)`. The clauses
+    /// are tried in order; the first whose pattern matches the value and
+    /// whose guard, if any, is true has its body evaluated with the pattern's
+    /// variables bound. When no clause matches, the error shows the value.
+    fn eval_match(&mut self, args: &[LispType], tail: bool) -> Result<LispType, EvaluatorError> {
+        let (subject, clauses) = args.split_first().ok_or_else(|| {
+            EvaluatorError::InvalidArguments("match requires a value to match".to_string())
+        })?;
+        let value = self.eval_core(subject.clone())?;
+
+        for clause in clauses {
+            let (pattern, guard, body) = Self::match_clause(clause)?;
+            let frame = Env::with_parent(self.env.clone());
+            if !Self::match_pattern(&frame, &pattern, &value)? {
+                continue;
+            }
+            let frame = Rc::new(frame);
+            if let Some(guard) = guard {
+                if !self
+                    .eval_in_env(frame.clone(), &[guard], false)?
+                    .is_truthy()
+                {
+                    continue;
+                }
+            }
+            return self.eval_in_env(frame, &body, tail);
+        }
+        Err(EvaluatorError::NoMatch(value))
+    }
+
+    fn match_clause(clause: &LispType) -> Result<MatchClause, EvaluatorError> {
+        let malformed = || {
+            EvaluatorError::InvalidArguments(format!(
+                "match clauses must be (pattern [when guard] body...), got {}",
+                clause.show()
+            ))
+        };
+        let items: Vec<LispType> = match clause {
+            LispType::Cons(list) if list.is_proper() => list.iter().cloned().collect(),
+            _ => return Err(malformed()),
+        };
+        match &items[..] {
+            [pattern, LispType::SpecForm(SpecForms::WHEN), guard, body @ ..] => {
+                Ok((pattern.clone(), Some(guard.clone()), body.to_vec()))
+            }
+            [_, LispType::SpecForm(SpecForms::WHEN)] | [] => Err(malformed()),
+            [pattern, body @ ..] => Ok((pattern.clone(), None, body.to_vec())),
+        }
+    }
+
+    /// Matches `value` against a `match` pattern, binding the pattern's
+    /// variables in `env`, and returns whether it matched. Patterns are:
+    /// `_`, which matches anything; a symbol, which binds the value, or
+    /// when it occurs again in the pattern must be `equal?` to the value it
+    /// bound first; a string, number, boolean or character, or any quoted
+    /// datum, which must
+    /// be `equal?` to the value; a possibly dotted list of patterns; and a map
+    /// `{key pattern ...}`, which matches a map holding every key (written as
+    /// data, not evaluated) with a value matching its pattern. A list headed
+    /// by a record constructor, such as `(circle r)`, matches a record of
+    /// the constructor's type whose fields match the remaining patterns.
+    fn match_pattern(
+        env: &Env,
+        pattern: &LispType,
+        value: &LispType,
+    ) -> Result<bool, EvaluatorError> {
+        match pattern {
+            LispType::Symbol(name) if base_name(name) == "_" => Ok(true),
+            LispType::Symbol(name) => match env.get_local(name) {
+                Some(bound) => Ok(bound == *value),
+                None => {
+                    env.define(name.clone(), value.clone());
+                    Ok(true)
+                }
             },
-            _ => Err(EvaluatorError::TypeMismatch(
-                "car expects a list".to_string(),
-            )),
+            LispType::String(_)
+            | LispType::Integer(_)
+            | LispType::Float(_)
+            | LispType::Bool(_)
+            | LispType::Char(_) => Ok(pattern == value),
+            LispType::Cons(list) => {
+                if let Some(datum) = Self::quoted_datum(list) {
+                    return Ok(datum == *value);
+                }
+                if let Some(LispType::Symbol(head)) = list.car() {
+                    let head = env.get(head).or_else(|| env.get(base_name(head)));
+                    if let Some(LispType::RecordProc(proc)) = head {
+                        if let RecordOp::Construct(rtype, indices) = &proc.op {
+                            return Self::match_record(
+                                env, list, &proc.name, rtype, indices, value,
+                            );
+                        }
+                    }
+                }
+                let mut pattern_cell: &ConsList = list;
+                let mut current = value.clone();
+                loop {
+                    if let ConsList::Nil = pattern_cell {
+                        return Ok(current.is_nil());
+                    }
+                    let (item, tail) = match &current {
+                        LispType::Cons(cell) => match (cell.car(), cell.cdr()) {
+                            (Some(item), Some(tail)) => (item.clone(), tail),
+                            _ => return Ok(false),
+                        },
+                        _ => return Ok(false),
+                    };
+                    match pattern_cell {
+                        ConsList::Cons(head, rest) => {
+                            if !Self::match_pattern(env, head, &item)? {
+                                return Ok(false);
+                            }
+                            pattern_cell = rest;
+                            current = tail;
+                        }
+                        ConsList::Pair(head, rest) => {
+                            return Ok(Self::match_pattern(env, head, &item)?
+                                && Self::match_pattern(env, rest, &tail)?);
+                        }
+                        ConsList::Nil => unreachable!("handled above"),
+                    }
+                }
+            }
+            LispType::HashMap(entries) => {
+                let LispType::HashMap(map) = value else {
+                    return Ok(false);
+                };
+                for (key, pattern) in entries.iter() {
+                    let key = match key {
+                        LispType::Cons(list) => Self::quoted_datum(list).unwrap_or(key.clone()),
+                        _ => key.clone(),
+                    };
+                    match map.get(&key) {
+                        Some(item) if Self::match_pattern(env, pattern, item)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+            other => Err(EvaluatorError::TypeMismatch(format!(
+                "{} cannot be used as a match pattern",
+                other.show()
+            ))),
         }
     }
 
-    /// Handles the cdr special form.
-    fn eval_cdr(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+    /// Matches the constructor pattern `(constructor pattern...)`, which
+    /// has one pattern per constructor argument, against a record.
+    fn match_record(
+        env: &Env,
+        pattern: &Rc<ConsList>,
+        constructor: &str,
+        rtype: &Rc<RecordType>,
+        indices: &[usize],
+        value: &LispType,
+    ) -> Result<bool, EvaluatorError> {
+        let patterns: Vec<&LispType> = pattern.iter().skip(1).collect();
+        if !pattern.is_proper() || patterns.len() != indices.len() {
+            return Err(EvaluatorError::InvalidArguments(format!(
+                "match: {} takes {} field pattern{}, got {}",
+                constructor,
+                indices.len(),
+                if indices.len() == 1 { "" } else { "s" },
+                LispType::Cons(pattern.clone()).show()
+            )));
+        }
+        let LispType::Record(record) = value else {
+            return Ok(false);
+        };
+        if !Rc::ptr_eq(&record.rtype, rtype) {
+            return Ok(false);
+        }
+        for (pattern, &index) in patterns.into_iter().zip(indices) {
+            if !Self::match_pattern(env, pattern, &record.values[index])? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The datum of a `(quote datum)` form.
+    fn quoted_datum(list: &ConsList) -> Option<LispType> {
+        match list {
+            ConsList::Cons(LispType::SpecForm(SpecForms::QUOTE), tail) => match &**tail {
+                ConsList::Cons(datum, rest) if matches!(**rest, ConsList::Nil) => {
+                    Some(datum.clone())
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Handles `(macro (params...) body...)`, which creates a macro from a
+    /// function of the unevaluated arguments of its calls.
+    fn eval_macro(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        match self.eval_lambda(args)? {
+            LispType::Lambda(lambda) => Ok(LispType::Macro(Rc::new(Macro::Procedural(lambda)))),
+            _ => unreachable!("eval_lambda always returns a lambda"),
+        }
+    }
+
+    /// Handles `(syntax-rules (literals...) ((_ pattern...) template)...)`.
+    fn eval_syntax_rules(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let (literals, rules) = args.split_first().ok_or_else(|| {
+            EvaluatorError::InvalidArguments("syntax-rules requires a list of literals".to_string())
+        })?;
+        let rules = SyntaxRules::new(literals, rules, self.env.clone())?;
+        Ok(LispType::Macro(Rc::new(Macro::SyntaxRules(rules))))
+    }
+
+    /// Handles `(define-syntax name transformer)`, which binds `name` to the
+    /// macro `transformer` evaluates to.
+    fn eval_define_syntax(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let (name, transformer) = match args {
+            [LispType::Symbol(name), transformer] => (name, transformer),
+            _ => {
+                return Err(EvaluatorError::InvalidArguments(
+                    "define-syntax requires a name and a transformer".to_string(),
+                ))
+            }
+        };
+        let value = self.eval_core(transformer.clone())?;
+        if !matches!(value, LispType::Macro(_)) {
+            return Err(EvaluatorError::TypeMismatch(format!(
+                "define-syntax expects a macro, got {}",
+                value.show()
+            )));
+        }
+        self.note_definition(name, &value);
+        self.env.define(name.clone(), value.clone());
+        Ok(value)
+    }
+
+    /// Cached expansions may call a macro's previous definition, so they
+    /// are dropped whenever a macro is bound, or a name that currently
+    /// holds a macro is bound to something else.
+    fn note_definition(&mut self, name: &str, value: &LispType) {
+        let replaces_macro = matches!(self.lookup(name), Some(LispType::Macro(_)));
+        if replaces_macro || matches!(value, LispType::Macro(_)) {
+            self.expansions.clear();
+        }
+    }
+
+    /// Handles `(macroexpand form)`: evaluates `form` and, as long as the
+    /// result is a call to a macro, replaces it by its expansion. Subforms
+    /// are not expanded.
+    fn eval_macroexpand(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
         if args.len() != 1 {
             return Err(EvaluatorError::InvalidArguments(
-                "cdr requires exactly one argument".to_string(),
+                "macroexpand requires exactly one argument".to_string(),
             ));
         }
-        let lst = self.eval(args[0].clone())?;
-        match lst {
-            LispType::Cons(cons_list) => match &*cons_list {
-                ConsList::Cons(_, tail) => Ok(LispType::Cons(tail.clone())),
-                ConsList::Nil => Err(EvaluatorError::InvalidArguments(
-                    "Cannot take cdr of an empty list".to_string(),
-                )),
-            },
-            _ => Err(EvaluatorError::TypeMismatch(
-                "cdr expects a list".to_string(),
-            )),
+        let mut form = self.eval_core(args[0].clone())?;
+        while let Some(mac) = self.macro_of(&form) {
+            form = self.expand_macro(&mac, &form)?;
         }
+        Ok(form)
     }
 
-    /// Handles the cons special form.
-    fn eval_cons(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
-        if args.len() != 2 {
+    /// The macro that `form` calls, if its head is a symbol bound to one.
+    pub(crate) fn macro_of(&self, form: &LispType) -> Option<Rc<Macro>> {
+        let LispType::Cons(list) = form else {
+            return None;
+        };
+        let Some(LispType::Symbol(name)) = list.car() else {
+            return None;
+        };
+        match self.lookup(name) {
+            Some(LispType::Macro(mac)) => Some(mac),
+            _ => None,
+        }
+    }
+
+    /// The current value of a symbol. A symbol renamed by a `syntax-rules`
+    /// expansion and not bound under its new name refers to the variable
+    /// it was written as, in the scope where the macro was defined.
+    pub(crate) fn lookup(&self, name: &str) -> Option<LispType> {
+        self.env
+            .get(name)
+            .or_else(|| self.macro_env(name)?.get(base_name(name)))
+    }
+
+    /// The scope of the macro that renamed `name`, if any.
+    fn macro_env(&self, name: &str) -> Option<&Rc<Env>> {
+        self.macro_scopes.get(macro_scope(name)?)
+    }
+
+    /// Expands one macro call. Every expansion is numbered, so the symbols
+    /// `syntax-rules` renames differ from one expansion to the next.
+    pub(crate) fn expand_macro(
+        &mut self,
+        mac: &Macro,
+        form: &LispType,
+    ) -> Result<LispType, EvaluatorError> {
+        match mac {
+            Macro::Procedural(lambda) => {
+                let args = match form {
+                    LispType::Cons(list) => self.list_to_vec(list.clone())?,
+                    _ => Vec::new(),
+                };
+                self.call_lambda(lambda, args[1..].to_vec())
+            }
+            Macro::SyntaxRules(rules) => {
+                self.symbol_counter += 1;
+                let scope = match self
+                    .macro_scopes
+                    .iter()
+                    .position(|env| Rc::ptr_eq(env, &rules.env))
+                {
+                    Some(scope) => scope,
+                    None => {
+                        self.macro_scopes.push(rules.env.clone());
+                        self.macro_scopes.len() - 1
+                    }
+                };
+                rules.expand(form, self.symbol_counter, scope)
+            }
+        }
+    }
+
+    /// Handles `(typeof expr)`, which returns the type name of the value as
+    /// a symbol, see `LispType::type_name`.
+    fn eval_typeof(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        if args.len() != 1 {
             return Err(EvaluatorError::InvalidArguments(
-                "cons requires exactly two arguments".to_string(),
+                "typeof requires exactly one argument".to_string(),
             ));
         }
-        let head = self.eval(args[0].clone())?;
-        let tail = self.eval(args[1].clone())?;
-        let tail_list = match tail {
-            LispType::Cons(tail_list) => tail_list,
-            _ => Rc::new(ConsList::Cons(tail, Rc::new(ConsList::Nil))),
+        let value = self.eval_core(args[0].clone())?;
+        Ok(LispType::Symbol(value.type_name().to_string()))
+    }
+
+    /// Handles `(defstruct name field...)`, which defines the record type
+    /// `name` along with the constructor `(make-name field...)`, the
+    /// predicate `name?`, an accessor `(name-field record)` per field and an
+    /// updater `(name-with-field record value)` that returns a copy of the
+    /// record with the field replaced.
+    fn eval_defstruct(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let (name, fields) = match args {
+            [LispType::Symbol(name), fields @ ..] => (name, fields),
+            _ => {
+                return Err(EvaluatorError::InvalidArguments(
+                    "defstruct requires a type name and field names".to_string(),
+                ))
+            }
+        };
+        let fields = Self::field_names(SpecForms::DEFSTRUCT, fields.iter())?;
+        let rtype = Rc::new(RecordType {
+            name: name.clone(),
+            fields: fields.clone(),
+            sum: None,
+            literal: false,
+        });
+
+        self.record_types.insert(name.clone(), rtype.clone());
+        let constructor = RecordOp::Construct(rtype.clone(), (0..fields.len()).collect());
+        self.define_record_proc(format!("make-{}", name), constructor);
+        self.define_record_procs(&rtype);
+        Ok(LispType::Symbol(name.clone()))
+    }
+
+    /// Handles the R7RS form `(define-record-type name (constructor field...)
+    /// predicate (field accessor [updater])...)`. Unlike R7RS modifiers, the
+    /// updaters return a modified copy. Fields the constructor does not take
+    /// start as `nil`.
+    fn eval_define_record_type(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let malformed = || {
+            EvaluatorError::InvalidArguments(
+                "define-record-type requires a name, (constructor field...), a predicate \
+                 and (field accessor [updater]) specs"
+                    .to_string(),
+            )
+        };
+        let (name, constructor, predicate, specs) = match args {
+            [LispType::Symbol(name), LispType::Cons(constructor), LispType::Symbol(predicate), specs @ ..]
+                if constructor.is_proper() =>
+            {
+                (name, constructor, predicate, specs)
+            }
+            _ => return Err(malformed()),
+        };
+        let specs = specs
+            .iter()
+            .map(|spec| match spec {
+                LispType::Cons(spec) if spec.is_proper() => {
+                    let parts: Vec<&LispType> = spec.iter().collect();
+                    match parts[..] {
+                        [field, LispType::Symbol(accessor)] => Ok((field, accessor, None)),
+                        [field, LispType::Symbol(accessor), LispType::Symbol(updater)] => {
+                            Ok((field, accessor, Some(updater)))
+                        }
+                        _ => Err(malformed()),
+                    }
+                }
+                _ => Err(malformed()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let fields = Self::field_names(
+            SpecForms::DEFINERECORDTYPE,
+            specs.iter().map(|(field, _, _)| *field),
+        )?;
+        let rtype = Rc::new(RecordType {
+            name: name.clone(),
+            fields,
+            sum: None,
+            literal: false,
+        });
+
+        let mut parts = constructor.iter();
+        let Some(LispType::Symbol(constructor)) = parts.next() else {
+            return Err(malformed());
+        };
+        let indices = parts
+            .map(|field| match field {
+                LispType::Symbol(field) => rtype.field_index(field).ok_or_else(|| {
+                    EvaluatorError::InvalidArguments(format!(
+                        "define-record-type: {} is not a field of {}",
+                        field, name
+                    ))
+                }),
+                _ => Err(malformed()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.record_types.insert(name.clone(), rtype.clone());
+        let constructor_op = RecordOp::Construct(rtype.clone(), indices);
+        self.define_record_proc(constructor.clone(), constructor_op);
+        self.define_record_proc(predicate.clone(), RecordOp::Predicate(rtype.clone()));
+        for (i, (_, accessor, updater)) in specs.into_iter().enumerate() {
+            self.define_record_proc(accessor.clone(), RecordOp::Get(rtype.clone(), i));
+            if let Some(updater) = updater {
+                self.define_record_proc(updater.clone(), RecordOp::Update(rtype.clone(), i));
+            }
+        }
+        Ok(LispType::Symbol(name.clone()))
+    }
+
+    /// Handles `(deftype name (variant field...)...)`, which defines the sum
+    /// type `name`. Each variant is a record type with the constructor
+    /// `(variant field...)` and the predicate, accessors and updaters that
+    /// `defstruct` would define; `name?` holds for a record of any variant.
+    /// `match` takes variants apart with patterns like `(variant pattern...)`
+    /// and warns when it leaves one out, see `check_exhaustive` in the expander.
+    fn eval_deftype(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let malformed = || {
+            EvaluatorError::InvalidArguments(
+                "deftype requires a type name and (variant field...) specs".to_string(),
+            )
+        };
+        let (name, specs) = match args {
+            [LispType::Symbol(name), specs @ ..] if !specs.is_empty() => (name, specs),
+            _ => return Err(malformed()),
         };
-        Ok(LispType::Cons(Rc::new(ConsList::Cons(head, tail_list))))
+        let mut variants: Vec<(String, Vec<String>)> = Vec::new();
+        for spec in specs {
+            let LispType::Cons(spec) = spec else {
+                return Err(malformed());
+            };
+            let mut parts = spec.iter();
+            let (Some(LispType::Symbol(variant)), true) = (parts.next(), spec.is_proper()) else {
+                return Err(malformed());
+            };
+            if variants.iter().any(|(other, _)| other == variant) {
+                return Err(EvaluatorError::InvalidArguments(format!(
+                    "deftype: duplicate variant {}",
+                    variant
+                )));
+            }
+            let fields = Self::field_names(SpecForms::DEFTYPE, parts)?;
+            variants.push((variant.clone(), fields));
+        }
+
+        let sum = Rc::new(SumType {
+            name: name.clone(),
+            variants: variants
+                .iter()
+                .map(|(variant, _)| variant.clone())
+                .collect(),
+        });
+        for (variant, fields) in variants {
+            let rtype = Rc::new(RecordType {
+                name: variant.clone(),
+                fields,
+                sum: Some(sum.clone()),
+                literal: false,
+            });
+            self.record_types.insert(variant.clone(), rtype.clone());
+            let constructor = RecordOp::Construct(rtype.clone(), (0..rtype.fields.len()).collect());
+            self.define_record_proc(variant, constructor);
+            self.define_record_procs(&rtype);
+        }
+        self.define_record_proc(format!("{}?", name), RecordOp::SumPredicate(sum));
+        Ok(LispType::Symbol(name.clone()))
     }
 
-    fn eval_do(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
-        println!("{:?}", args);
-        Ok(args[1].clone())
+    /// Handles `(defgeneric name (params...))`, which defines `name` as a
+    /// generic function of the parameters, with no methods yet.
+    fn eval_defgeneric(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let (name, params) = match args {
+            [LispType::Symbol(name), LispType::Cons(params)] => (name, params),
+            _ => {
+                return Err(EvaluatorError::InvalidArguments(
+                    "defgeneric requires a name and a parameter list".to_string(),
+                ))
+            }
+        };
+        let generic = Generic::new(name.clone(), params.iter().count());
+        self.env
+            .define(name.clone(), LispType::Generic(Rc::new(generic)));
+        Ok(LispType::Symbol(name.clone()))
     }
 
-    /// Converts a ConsList into a Vec of LispType elements.
-    fn list_to_vec(&self, list: Rc<ConsList>) -> Result<Vec<LispType>, EvaluatorError> {
-        let mut vec = Vec::new();
-        let mut current = list;
+    /// Handles `(defmethod name (param...) body...)`, which adds a method to
+    /// the generic function `name`, defining the function first if `name`
+    /// is unbound. A parameter written `(var type)` is specialized on the
+    /// type name `type`, as returned by `typeof`, or on `number`, the name of
+    /// a `deftype` or `any`, see `generic::type_names`; a plain parameter is
+    /// specialized on `any`. The body can call `(call-next-method [args...])`
+    /// to run the next most specific method.
+    fn eval_defmethod(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let malformed = || {
+            EvaluatorError::InvalidArguments(
+                "defmethod requires a name, a parameter list and a body".to_string(),
+            )
+        };
+        let (name, params, body) = match args {
+            [LispType::Symbol(name), LispType::Cons(params), body @ ..] if !body.is_empty() => {
+                (name, params, body)
+            }
+            _ => return Err(malformed()),
+        };
+
+        let mut required = Vec::new();
+        let mut rest = None;
+        let mut current: &ConsList = params;
         loop {
-            match &*current {
-                ConsList::Cons(head, tail) => {
-                    vec.push(head.clone());
-                    current = tail.clone();
+            match current {
+                ConsList::Cons(param, tail) => {
+                    required.push(param);
+                    current = tail;
                 }
+                ConsList::Pair(param, LispType::Symbol(tail)) => {
+                    required.push(param);
+                    rest = Some(tail.clone());
+                    break;
+                }
+                ConsList::Pair(..) => return Err(malformed()),
                 ConsList::Nil => break,
             }
         }
-        Ok(vec)
+
+        let mut vars = Vec::new();
+        let mut specializers = Vec::new();
+        for param in required {
+            let (var, specializer) = match param {
+                LispType::Symbol(var) => (var.clone(), ANY.to_string()),
+                LispType::Cons(spec) if spec.is_proper() && spec.len() == 2 => {
+                    match (spec.nth(0), spec.nth(1)) {
+                        (Some(LispType::Symbol(var)), Some(specializer)) => (
+                            var.clone(),
+                            Self::type_name_of(SpecForms::DEFMETHOD, specializer)?,
+                        ),
+                        _ => return Err(malformed()),
+                    }
+                }
+                other => {
+                    return Err(EvaluatorError::TypeMismatch(format!(
+                        "defmethod: a parameter must be a symbol or (var type), got {}",
+                        other.show()
+                    )))
+                }
+            };
+            vars.push(LispType::Symbol(var));
+            specializers.push(specializer);
+        }
+
+        let generic = match self.lookup(name) {
+            Some(LispType::Generic(generic)) => generic,
+            None => {
+                let generic = Rc::new(Generic::new(name.clone(), vars.len()));
+                self.env
+                    .define(name.clone(), LispType::Generic(generic.clone()));
+                generic
+            }
+            Some(_) => {
+                return Err(EvaluatorError::TypeMismatch(format!(
+                    "defmethod: {} is not a generic function",
+                    name
+                )))
+            }
+        };
+        if vars.len() != generic.arity {
+            return Err(EvaluatorError::InvalidArguments(format!(
+                "defmethod: {} takes {} argument{}, got {} parameters",
+                name,
+                generic.arity,
+                if generic.arity == 1 { "" } else { "s" },
+                vars.len()
+            )));
+        }
+        generic.add_method(Method {
+            specializers,
+            lambda: Lambda {
+                params: vars,
+                rest,
+                body: body.to_vec(),
+                env: self.env.clone(),
+            },
+        });
+        Ok(LispType::Symbol(name.clone()))
+    }
+
+    /// A type name written in a form. Names like `list` or
+    /// `vector` are read as builtins, so those are accepted too.
+    pub(crate) fn type_name_of(
+        form: SpecForms,
+        specializer: &LispType,
+    ) -> Result<String, EvaluatorError> {
+        match specializer {
+            LispType::Symbol(name) => Ok(name.clone()),
+            LispType::Builtin(builtin) => Ok(builtin.to_string().to_string()),
+            other => Err(EvaluatorError::TypeMismatch(format!(
+                "{}: expected a type name, got {}",
+                form.to_string(),
+                other.show()
+            ))),
+        }
+    }
+
+    /// Calls a generic function: runs its most specific applicable method.
+    fn call_generic(
+        &mut self,
+        generic: &Rc<Generic>,
+        args: Vec<LispType>,
+    ) -> Result<LispType, EvaluatorError> {
+        if args.len() < generic.arity {
+            return Err(EvaluatorError::InvalidArguments(format!(
+                "{} expects {} argument{}, got {}",
+                generic.name,
+                generic.arity,
+                if generic.arity == 1 { "" } else { "s" },
+                args.len()
+            )));
+        }
+        let methods = generic.applicable(&args);
+        if methods.is_empty() {
+            let types: Vec<&str> = args[..generic.arity]
+                .iter()
+                .map(LispType::type_name)
+                .collect();
+            return Err(EvaluatorError::TypeMismatch(format!(
+                "{}: no method for argument types ({})",
+                generic.name,
+                types.join(" ")
+            )));
+        }
+        self.call_method(generic, methods, args)
+    }
+
+    /// Runs the first of `methods` with `call-next-method` bound to the
+    /// others.
+    pub(crate) fn call_method(
+        &mut self,
+        generic: &Rc<Generic>,
+        mut methods: Vec<Rc<Method>>,
+        args: Vec<LispType>,
+    ) -> Result<LispType, EvaluatorError> {
+        let method = methods.remove(0);
+        let next = NextMethod {
+            generic: generic.clone(),
+            methods,
+            args: args.clone(),
+        };
+        let frame = Env::with_parent(method.lambda.env.clone());
+        frame.define(
+            "call-next-method".to_string(),
+            LispType::NextMethod(Rc::new(next)),
+        );
+        let lambda = Lambda {
+            params: method.lambda.params.clone(),
+            rest: method.lambda.rest.clone(),
+            body: method.lambda.body.clone(),
+            env: Rc::new(frame),
+        };
+        self.call_lambda(&lambda, args)
+    }
+
+    /// Checks that the field names of a record definition are distinct
+    /// symbols.
+    fn field_names<'a>(
+        form: SpecForms,
+        fields: impl Iterator<Item = &'a LispType>,
+    ) -> Result<Vec<String>, EvaluatorError> {
+        let mut names: Vec<String> = Vec::new();
+        for field in fields {
+            match field {
+                LispType::Symbol(name) if !names.contains(name) => names.push(name.clone()),
+                LispType::Symbol(name) => {
+                    return Err(EvaluatorError::InvalidArguments(format!(
+                        "{}: duplicate field {}",
+                        form.to_string(),
+                        name
+                    )))
+                }
+                other => {
+                    return Err(EvaluatorError::TypeMismatch(format!(
+                        "{}: field names must be symbols, got {}",
+                        form.to_string(),
+                        other.show()
+                    )))
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    fn define_record_proc(&self, name: String, op: RecordOp) {
+        let proc = RecordProc {
+            name: name.clone(),
+            op,
+        };
+        self.env.define(name, LispType::RecordProc(Rc::new(proc)));
+    }
+
+    /// Defines the predicate `name?`, an accessor `name-field` and an
+    /// updater `name-with-field` per field of a record type.
+    fn define_record_procs(&self, rtype: &Rc<RecordType>) {
+        let name = &rtype.name;
+        self.define_record_proc(format!("{}?", name), RecordOp::Predicate(rtype.clone()));
+        for (i, field) in rtype.fields.iter().enumerate() {
+            let accessor = format!("{}-{}", name, field);
+            self.define_record_proc(accessor, RecordOp::Get(rtype.clone(), i));
+            let updater = format!("{}-with-{}", name, field);
+            self.define_record_proc(updater, RecordOp::Update(rtype.clone(), i));
+        }
+    }
+
+    /// Handles `(do forms...)`: evaluates the forms in order and returns the
+    /// value of the last one.
+    fn eval_do(&mut self, args: &[LispType], tail: bool) -> Result<LispType, EvaluatorError> {
+        self.eval_body(args, tail)
+    }
+
+    /// Handles `(while test body...)`, which returns `nil`.
+    fn eval_while(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let (test, body) = args
+            .split_first()
+            .ok_or_else(|| EvaluatorError::InvalidArguments("while requires a test".to_string()))?;
+        while self.eval_core(test.clone())?.is_truthy() {
+            self.eval_body(body, false)?;
+        }
+        Ok(LispType::nil())
+    }
+
+    /// Handles `(loop ((pattern init) ...) body...)`. The body runs with the
+    /// bindings in a fresh frame; a `(recur values...)` in tail position
+    /// starts the next iteration with new values, and any other result ends
+    /// the loop. Iterations reuse the same Rust stack frame.
+    fn eval_loop(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let (bindings, body) = Self::let_parts(SpecForms::LOOP, args)?;
+        for (i, form) in body.iter().enumerate() {
+            Self::check_recur(form, i + 1 == body.len(), bindings.len())?;
+        }
+
+        let mut patterns = Vec::new();
+        let mut values = Vec::new();
+        for (pattern, init) in bindings {
+            patterns.push(pattern);
+            values.push(self.eval_core(init)?);
+        }
+
+        loop {
+            let frame = Env::with_parent(self.env.clone());
+            for (pattern, value) in patterns.iter().zip(values) {
+                Self::bind_pattern(&frame, pattern, value)?;
+            }
+
+            let outer_in_loop = std::mem::replace(&mut self.in_loop, true);
+            let result = self.eval_in_env(Rc::new(frame), body, false);
+            self.in_loop = outer_in_loop;
+
+            let pending = self.pending_recur.take();
+            match (result?, pending) {
+                (_, Some(next)) => values = next,
+                (result, None) => return Ok(result),
+            }
+        }
+    }
+
+    /// Handles `(recur values...)`. It only records the values: since it is
+    /// in tail position, its `loop` is the next thing to see them.
+    fn eval_recur(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        if !self.in_loop {
+            return Err(EvaluatorError::Other(
+                "recur used outside of a loop".to_string(),
+            ));
+        }
+        let values = self.eval_args(args)?;
+        self.pending_recur = Some(values);
+        Ok(LispType::nil())
+    }
+
+    /// Checks, before a loop runs, that every `recur` in `form` is in tail
+    /// position and passes one value per loop binding. Lambdas and named
+    /// lets start a new function, so a `recur` inside them is never in tail
+    /// position; nested loops check their own bodies.
+    fn check_recur(form: &LispType, tail: bool, arity: usize) -> Result<(), EvaluatorError> {
+        let list = match form {
+            LispType::Cons(list) if list.is_proper() => list,
+            _ => return Ok(()),
+        };
+        let items: Vec<&LispType> = list.iter().collect();
+        let Some((head, args)) = items.split_first() else {
+            return Ok(());
+        };
+        let check_all = |forms: &[&LispType], tail: bool| {
+            forms.iter().enumerate().try_for_each(|(i, form)| {
+                Self::check_recur(form, tail && i + 1 == forms.len(), arity)
+            })
+        };
+
+        let LispType::SpecForm(spec_form) = head else {
+            return check_all(&items, false);
+        };
+        match spec_form {
+            SpecForms::QUOTE | SpecForms::QUASIQUOTE => Ok(()),
+            SpecForms::RECUR => {
+                if !tail {
+                    return Err(EvaluatorError::Other(format!(
+                        "recur must be in tail position of its loop: {}",
+                        form.show()
+                    )));
+                }
+                if args.len() != arity {
+                    return Err(EvaluatorError::InvalidArguments(format!(
+                        "recur expects {} value{} to match its loop, got {}",
+                        arity,
+                        if arity == 1 { "" } else { "s" },
+                        args.len()
+                    )));
+                }
+                check_all(args, false)
+            }
+            SpecForms::COND => args.chunks(2).try_for_each(|clause| match clause {
+                [test, expr] => {
+                    Self::check_recur(test, false, arity)?;
+                    Self::check_recur(expr, tail, arity)
+                }
+                [default] => Self::check_recur(default, tail, arity),
+                _ => unreachable!(),
+            }),
+            SpecForms::DO | SpecForms::AND | SpecForms::OR => check_all(args, tail),
+            SpecForms::IF | SpecForms::WHEN | SpecForms::UNLESS => {
+                let (test, branches) = args.split_at(args.len().min(1));
+                check_all(test, false)?;
+                match spec_form {
+                    SpecForms::IF => branches
+                        .iter()
+                        .try_for_each(|branch| Self::check_recur(branch, tail, arity)),
+                    _ => check_all(branches, tail),
+                }
+            }
+            // Clause data are quoted; only the bodies can contain code.
+            SpecForms::CASE => {
+                let (key, clauses) = args.split_at(args.len().min(1));
+                check_all(key, false)?;
+                clauses.iter().try_for_each(|clause| match clause {
+                    LispType::Cons(clause) if clause.is_proper() => {
+                        let body: Vec<&LispType> = clause.iter().skip(1).collect();
+                        check_all(&body, tail)
+                    }
+                    _ => Ok(()),
+                })
+            }
+            // Patterns are not code, and a guard runs before its body.
+            SpecForms::MATCH => {
+                let (subject, clauses) = args.split_at(args.len().min(1));
+                check_all(subject, false)?;
+                clauses.iter().try_for_each(|clause| match clause {
+                    LispType::Cons(clause) if clause.is_proper() => {
+                        let code: Vec<&LispType> = clause.iter().skip(1).collect();
+                        let body = match code[..] {
+                            [LispType::SpecForm(SpecForms::WHEN), guard, ref body @ ..] => {
+                                Self::check_recur(guard, false, arity)?;
+                                body
+                            }
+                            ref body => body,
+                        };
+                        check_all(body, tail)
+                    }
+                    _ => Ok(()),
+                })
+            }
+            SpecForms::LET | SpecForms::LETSTAR | SpecForms::LETREC
+                if !matches!(args.first(), Some(LispType::Symbol(_))) =>
+            {
+                let (bindings, body) = args.split_at(args.len().min(1));
+                check_all(bindings, false)?;
+                check_all(body, tail)
+            }
+            SpecForms::LOOP => check_all(&args[..args.len().min(1)], false),
+            _ => check_all(args, false),
+        }
+    }
+
+    /// Handles `(dotimes (var count [result]) body...)`: runs the body with
+    /// `var` bound to 0 up to `count - 1`, then evaluates `result` (default
+    /// `nil`) with `var` bound to `count`.
+    fn eval_dotimes(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let iteration = Self::iteration_parts(SpecForms::DOTIMES, args)?;
+        let count = match self.eval_core(iteration.source.clone())? {
+            LispType::Integer(count) => count,
+            other => {
+                return Err(EvaluatorError::TypeMismatch(format!(
+                    "dotimes expects an integer count, got {}",
+                    other.show()
+                )))
+            }
+        };
+
+        for i in 0..count {
+            self.eval_iteration(&iteration, LispType::Integer(i))?;
+        }
+        self.eval_iteration_result(&iteration, LispType::Integer(count.max(0)))
+    }
+
+    /// Handles `(dolist (var seq [result]) body...)`: runs the body once per
+    /// element of a list or vector, then evaluates `result` (default `nil`)
+    /// with `var` bound to `nil`.
+    fn eval_dolist(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let iteration = Self::iteration_parts(SpecForms::DOLIST, args)?;
+        let source = self.eval_core(iteration.source.clone())?;
+        match self.as_sequence(source)? {
+            LispType::Cons(list) if list.is_proper() => {
+                for item in list.iter() {
+                    self.eval_iteration(&iteration, item.clone())?;
+                }
+            }
+            LispType::Vector(vector) => {
+                let items = vector.items.borrow().clone();
+                for item in items {
+                    self.eval_iteration(&iteration, item)?;
+                }
+            }
+            other => {
+                return Err(EvaluatorError::TypeMismatch(format!(
+                    "dolist expects a list or vector, got {}",
+                    other.show()
+                )))
+            }
+        }
+        self.eval_iteration_result(&iteration, LispType::nil())
+    }
+
+    /// Runs one iteration in its own frame, so closures created by the body
+    /// capture that iteration's value.
+    fn eval_iteration(
+        &mut self,
+        iteration: &Iteration,
+        value: LispType,
+    ) -> Result<LispType, EvaluatorError> {
+        let frame = Env::with_parent(self.env.clone());
+        frame.define(iteration.var.clone(), value);
+        self.eval_in_env(Rc::new(frame), iteration.body, false)
+    }
+
+    fn eval_iteration_result(
+        &mut self,
+        iteration: &Iteration,
+        value: LispType,
+    ) -> Result<LispType, EvaluatorError> {
+        match iteration.result {
+            Some(result) => {
+                let frame = Env::with_parent(self.env.clone());
+                frame.define(iteration.var.clone(), value);
+                self.eval_in_env(Rc::new(frame), std::slice::from_ref(result), false)
+            }
+            None => Ok(LispType::nil()),
+        }
+    }
+
+    fn iteration_parts(
+        form: SpecForms,
+        args: &[LispType],
+    ) -> Result<Iteration<'_>, EvaluatorError> {
+        let malformed = || {
+            EvaluatorError::InvalidArguments(format!(
+                "{} requires (var expr [result]) and a body",
+                form.to_string()
+            ))
+        };
+        let (spec, body) = match args {
+            [LispType::Cons(spec), body @ ..] if spec.is_proper() => (spec, body),
+            _ => return Err(malformed()),
+        };
+        let parts: Vec<&LispType> = spec.iter().collect();
+        match parts[..] {
+            [LispType::Symbol(var), source] | [LispType::Symbol(var), source, _] => Ok(Iteration {
+                var: var.clone(),
+                source,
+                result: parts.get(2).copied(),
+                body,
+            }),
+            _ => Err(malformed()),
+        }
+    }
+
+    /// Collects the elements of a form, which must be a proper list.
+    fn list_to_vec(&self, list: Rc<ConsList>) -> Result<Vec<LispType>, EvaluatorError> {
+        if !list.is_proper() {
+            return Err(EvaluatorError::TypeMismatch(format!(
+                "Expected a proper list, got {}",
+                list.show()
+            )));
+        }
+        Ok(list.iter().cloned().collect())
     }
 
     /// Applies a binary predicate to two LispType operands.
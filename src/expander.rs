@@ -0,0 +1,644 @@
+use crate::{
+    conslist::ConsList,
+    evaluator::{Evaluator, EvaluatorError},
+    lisptype::{LispMap, LispSet, LispType, LispVector, Macro},
+    record::{Record, RecordOp, RecordType, SumType},
+    syntax_rules::base_name,
+    type_enums::SpecForms,
+};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Past this many entries in either table the cache starts over, so
+/// programs that `eval` freshly built code or expand macro calls at run
+/// time in a loop do not grow it without bound.
+const MAX_CACHED_FORMS: usize = 4096;
+
+/// Expanded top-level forms, and the macro call each expansion replaced.
+///
+/// Both tables are keyed by the address of a list, which they keep alive
+/// so that the address cannot be reused by another list.
+#[derive(Default)]
+pub struct ExpansionCache {
+    forms: HashMap<*const ConsList, (Rc<ConsList>, LispType)>,
+    origins: HashMap<*const ConsList, (Rc<ConsList>, LispType)>,
+}
+
+impl ExpansionCache {
+    /// Forgets every expansion, as needed when a macro is (re)defined.
+    pub fn clear(&mut self) {
+        self.forms.clear();
+        self.origins.clear();
+    }
+
+    /// The macro call `list` is the expansion of, if any.
+    pub fn origin(&self, list: &Rc<ConsList>) -> Option<&LispType> {
+        self.origins.get(&Rc::as_ptr(list)).map(|(_, call)| call)
+    }
+
+    fn record_origin(&mut self, expansion: &LispType, call: &LispType) {
+        if let LispType::Cons(list) = expansion {
+            if !list.is_empty() {
+                if self.origins.len() >= MAX_CACHED_FORMS {
+                    self.clear();
+                }
+                self.origins
+                    .insert(Rc::as_ptr(list), (list.clone(), call.clone()));
+            }
+        }
+    }
+}
+
+impl Evaluator {
+    /// Expands every macro call in `form`, returning a form made only of
+    /// special forms and function calls. Quoted data, patterns and binding
+    /// names are left alone, and a local variable shadows a macro of the
+    /// same name. The expansion of a list is cached, so evaluating the same
+    /// form again does not expand it again.
+    ///
+    /// A macro defined by the form itself is not known yet while it is
+    /// expanded; such calls are expanded when they are evaluated.
+    pub fn expand(&mut self, form: &LispType) -> Result<LispType, EvaluatorError> {
+        let list = match form {
+            LispType::Cons(list) if !list.is_empty() => list,
+            _ => return self.expand_form(&self.resolve_literals(form)?, &mut Vec::new()),
+        };
+        if let Some((_, expanded)) = self.expansions.forms.get(&Rc::as_ptr(list)) {
+            return Ok(expanded.clone());
+        }
+
+        let expanded = self.expand_form(&self.resolve_literals(form)?, &mut Vec::new())?;
+        if self.expansions.forms.len() >= MAX_CACHED_FORMS {
+            self.expansions.clear();
+        }
+        self.expansions
+            .forms
+            .insert(Rc::as_ptr(list), (list.clone(), expanded.clone()));
+        Ok(expanded)
+    }
+
+    /// Replaces every record read from a `#S(type field value ...)` literal
+    /// within `form`, quoted or not, by a record of the type currently
+    /// defined as `type`. Fields the literal leaves out are `nil`.
+    fn resolve_literals(&self, form: &LispType) -> Result<LispType, EvaluatorError> {
+        if !has_literal(form) {
+            return Ok(form.clone());
+        }
+        match form {
+            LispType::Cons(list) => {
+                let mut items = Vec::new();
+                let mut current: &ConsList = list;
+                let tail = loop {
+                    match current {
+                        ConsList::Cons(head, rest) => {
+                            items.push(self.resolve_literals(head)?);
+                            current = rest;
+                        }
+                        ConsList::Pair(head, tail) => {
+                            items.push(self.resolve_literals(head)?);
+                            break self.resolve_literals(tail)?;
+                        }
+                        ConsList::Nil => break LispType::nil(),
+                    }
+                };
+                Ok(ConsList::from(items).append(tail))
+            }
+            LispType::Vector(vector) => {
+                let items = vector
+                    .items
+                    .borrow()
+                    .iter()
+                    .map(|item| self.resolve_literals(item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(LispType::Vector(Rc::new(LispVector::new(items, false))))
+            }
+            LispType::HashMap(map) => {
+                let mut result = LispMap::default();
+                for (key, value) in map.iter() {
+                    result.insert(self.resolve_literals(key)?, self.resolve_literals(value)?);
+                }
+                Ok(LispType::HashMap(Rc::new(result)))
+            }
+            LispType::Set(set) => {
+                let items = set
+                    .iter()
+                    .map(|item| self.resolve_literals(item))
+                    .collect::<Result<LispSet, _>>()?;
+                Ok(LispType::Set(Rc::new(items)))
+            }
+            LispType::Record(record) if !record.rtype.literal => {
+                let values = record
+                    .values
+                    .iter()
+                    .map(|value| self.resolve_literals(value))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let rtype = record.rtype.clone();
+                Ok(LispType::Record(Rc::new(Record { rtype, values })))
+            }
+            LispType::Record(record) => {
+                let name = &record.rtype.name;
+                let rtype = self.record_types.get(name).cloned().ok_or_else(|| {
+                    EvaluatorError::UndefinedSymbol(format!("{} (record type)", name))
+                })?;
+                let mut values = vec![LispType::nil(); rtype.fields.len()];
+                for (field, value) in record.rtype.fields.iter().zip(&record.values) {
+                    let index = rtype.field_index(field).ok_or_else(|| {
+                        EvaluatorError::InvalidArguments(format!(
+                            "#S: {} is not a field of {}",
+                            field, name
+                        ))
+                    })?;
+                    values[index] = self.resolve_literals(value)?;
+                }
+                Ok(LispType::Record(Rc::new(Record { rtype, values })))
+            }
+            _ => Ok(form.clone()),
+        }
+    }
+
+    /// Expands the call `form` to `mac`, then the expansion itself, and
+    /// remembers which call the result came from for error messages. A
+    /// macro whose expansion keeps calling macros fails once the Rust stack
+    /// reaches its limit, see `Evaluator::set_stack_limit`.
+    pub(crate) fn expand_call(
+        &mut self,
+        mac: &Macro,
+        form: &LispType,
+        bound: &mut Vec<String>,
+    ) -> Result<LispType, EvaluatorError> {
+        let overflow = |depth| {
+            format!(
+                "stack overflow: {} nested calls and macro expansions",
+                depth
+            )
+        };
+        let expanded = self.nested(overflow, |this| {
+            let expansion = this.expand_macro(mac, form)?;
+            this.expand_form(&expansion, bound)
+        })?;
+        self.expansions.record_origin(&expanded, form);
+        Ok(expanded)
+    }
+
+    /// Expands `form`, where `bound` lists the local variables in scope.
+    fn expand_form(
+        &mut self,
+        form: &LispType,
+        bound: &mut Vec<String>,
+    ) -> Result<LispType, EvaluatorError> {
+        let list = match form {
+            LispType::Cons(list) if list.is_proper() && !list.is_empty() => list,
+            LispType::Vector(vector) => {
+                let items = vector.items.borrow().clone();
+                let items = self.expand_all(&items, bound)?;
+                return Ok(LispType::Vector(Rc::new(LispVector::new(items, false))));
+            }
+            LispType::HashMap(map) => {
+                let mut result = LispMap::default();
+                for (key, value) in map.iter() {
+                    result.insert(
+                        self.expand_form(key, bound)?,
+                        self.expand_form(value, bound)?,
+                    );
+                }
+                return Ok(LispType::HashMap(Rc::new(result)));
+            }
+            LispType::Set(set) => {
+                let items = set
+                    .iter()
+                    .map(|item| self.expand_form(item, bound))
+                    .collect::<Result<LispSet, _>>()?;
+                return Ok(LispType::Set(Rc::new(items)));
+            }
+            _ => return Ok(form.clone()),
+        };
+
+        if let Some(LispType::Symbol(name)) = list.car() {
+            if !bound.contains(name) {
+                if let Some(mac) = self.macro_of(form) {
+                    return self.expand_call(&mac, form, bound);
+                }
+            }
+        }
+
+        let items: Vec<LispType> = list.iter().cloned().collect();
+        let LispType::SpecForm(spec_form) = &items[0] else {
+            return Ok(LispType::list(self.expand_all(&items, bound)?));
+        };
+        let expanded = match self.expand_special(spec_form, &items, bound)? {
+            Some(expanded) => expanded,
+            // Malformed forms are left for the evaluator to report.
+            None => return Ok(form.clone()),
+        };
+        Ok(LispType::list(expanded))
+    }
+
+    fn expand_all(
+        &mut self,
+        forms: &[LispType],
+        bound: &mut Vec<String>,
+    ) -> Result<Vec<LispType>, EvaluatorError> {
+        forms
+            .iter()
+            .map(|form| self.expand_form(form, bound))
+            .collect()
+    }
+
+    /// Expands `body` with the symbols of `patterns` in scope.
+    fn expand_scoped(
+        &mut self,
+        patterns: &[&LispType],
+        body: &[LispType],
+        bound: &mut Vec<String>,
+    ) -> Result<Vec<LispType>, EvaluatorError> {
+        let outer = bound.len();
+        for pattern in patterns {
+            Self::pattern_symbols(pattern, bound);
+        }
+        let result = self.expand_all(body, bound);
+        bound.truncate(outer);
+        result
+    }
+
+    /// Expands the parts of a special form that are code. Returns `None`
+    /// when the form is to be kept as it is: when it holds no code, or does
+    /// not have the expected shape.
+    fn expand_special(
+        &mut self,
+        spec_form: &SpecForms,
+        items: &[LispType],
+        bound: &mut Vec<String>,
+    ) -> Result<Option<Vec<LispType>>, EvaluatorError> {
+        let head = items[0].clone();
+        let args = &items[1..];
+        let mut result = vec![head];
+        match spec_form {
+            SpecForms::QUOTE
+            | SpecForms::GET
+            | SpecForms::SYNTAXRULES
+            | SpecForms::DEFSTRUCT
+            | SpecForms::DEFINERECORDTYPE
+            | SpecForms::DEFTYPE
+            | SpecForms::DEFGENERIC
+            | SpecForms::EXPORT
+            | SpecForms::IMPORT => return Ok(None),
+            SpecForms::QUASIQUOTE => match args {
+                [template] => result.push(self.expand_quasi(template, 1, bound)?),
+                _ => return Ok(None),
+            },
+            SpecForms::LAMBDA | SpecForms::MACRO => match args {
+                [params, body @ ..] => {
+                    result.push(params.clone());
+                    let body = self.expand_scoped(&[params], body, bound)?;
+                    check_function_body(&body)?;
+                    result.extend(body);
+                }
+                _ => return Ok(None),
+            },
+            // Of a `(var type)` parameter, only the variable is bound.
+            SpecForms::DEFMETHOD => match args {
+                [name, params @ LispType::Cons(list), body @ ..] => {
+                    let mut vars: Vec<LispType> = list
+                        .iter()
+                        .map(|param| match param {
+                            LispType::Cons(spec) => spec.car().cloned().unwrap_or(LispType::nil()),
+                            other => other.clone(),
+                        })
+                        .collect();
+                    let mut tail: &ConsList = list;
+                    while let ConsList::Cons(_, next) = tail {
+                        tail = next;
+                    }
+                    if let ConsList::Pair(_, rest) = tail {
+                        vars.push(rest.clone());
+                    }
+                    result.push(name.clone());
+                    result.push(params.clone());
+                    let vars: Vec<&LispType> = vars.iter().collect();
+                    let body = self.expand_scoped(&vars, body, bound)?;
+                    check_function_body(&body)?;
+                    result.extend(body);
+                }
+                _ => return Ok(None),
+            },
+            SpecForms::DEF | SpecForms::SET | SpecForms::DEFINESYNTAX => match args {
+                [name, value] => {
+                    result.push(name.clone());
+                    result.push(self.expand_form(value, bound)?);
+                }
+                _ => return Ok(None),
+            },
+            SpecForms::LET | SpecForms::LETSTAR | SpecForms::LETREC | SpecForms::LOOP => {
+                let (name, rest) = match args {
+                    [name @ LispType::Symbol(_), rest @ ..] if *spec_form == SpecForms::LET => {
+                        (Some(name), rest)
+                    }
+                    _ => (None, args),
+                };
+                let Some((LispType::Cons(bindings), body)) = rest.split_first() else {
+                    return Ok(None);
+                };
+                let Some(bindings) = Self::binding_pairs(bindings) else {
+                    return Ok(None);
+                };
+
+                let mut patterns: Vec<&LispType> = bindings.iter().map(|(p, _)| p).collect();
+                // The inits of a plain let do not see its variables; those
+                // of the other forms may.
+                let inits = match (spec_form, name) {
+                    (SpecForms::LET, _) => self.expand_all(
+                        &bindings.iter().map(|(_, i)| i.clone()).collect::<Vec<_>>(),
+                        bound,
+                    )?,
+                    _ => {
+                        let inits: Vec<LispType> =
+                            bindings.iter().map(|(_, i)| i.clone()).collect();
+                        self.expand_scoped(&patterns, &inits, bound)?
+                    }
+                };
+                result.extend(name.cloned());
+                result.push(LispType::list(
+                    bindings
+                        .iter()
+                        .zip(inits)
+                        .map(|((pattern, _), init)| LispType::list(vec![pattern.clone(), init]))
+                        .collect(),
+                ));
+                patterns.extend(name);
+                let body = self.expand_scoped(&patterns, body, bound)?;
+                if name.is_some() {
+                    check_function_body(&body)?;
+                }
+                result.extend(body);
+            }
+            // `(dotimes (var count [result]) body...)`
+            SpecForms::DOTIMES | SpecForms::DOLIST => {
+                let Some((LispType::Cons(spec), body)) = args.split_first() else {
+                    return Ok(None);
+                };
+                let spec: Vec<LispType> = spec.iter().cloned().collect();
+                let [var, source, rest @ ..] = &spec[..] else {
+                    return Ok(None);
+                };
+                let mut expanded_spec = vec![var.clone(), self.expand_form(source, bound)?];
+                expanded_spec.extend(self.expand_scoped(&[var], rest, bound)?);
+                result.push(LispType::list(expanded_spec));
+                result.extend(self.expand_scoped(&[var], body, bound)?);
+            }
+            // `(extend-type type (operation (params...) body...)...)`
+            SpecForms::EXTENDTYPE => {
+                let Some((type_name, specs)) = args.split_first() else {
+                    return Ok(None);
+                };
+                result.push(type_name.clone());
+                for spec in specs {
+                    let LispType::Cons(spec) = spec else {
+                        return Ok(None);
+                    };
+                    let items: Vec<LispType> = spec.iter().cloned().collect();
+                    let [operation, params, body @ ..] = &items[..] else {
+                        return Ok(None);
+                    };
+                    let mut expanded = vec![operation.clone(), params.clone()];
+                    expanded.extend(self.expand_scoped(&[params], body, bound)?);
+                    result.push(LispType::list(expanded));
+                }
+            }
+            // Clause data are quoted; only the bodies are code.
+            SpecForms::CASE => {
+                let Some((key, clauses)) = args.split_first() else {
+                    return Ok(None);
+                };
+                result.push(self.expand_form(key, bound)?);
+                for clause in clauses {
+                    let LispType::Cons(clause) = clause else {
+                        return Ok(None);
+                    };
+                    let items: Vec<LispType> = clause.iter().cloned().collect();
+                    let Some((data, body)) = items.split_first() else {
+                        return Ok(None);
+                    };
+                    let mut expanded = vec![data.clone()];
+                    expanded.extend(self.expand_all(body, bound)?);
+                    result.push(LispType::list(expanded));
+                }
+            }
+            // `(match subject (pattern [when guard] body...) ...)`
+            SpecForms::MATCH => {
+                let Some((subject, clauses)) = args.split_first() else {
+                    return Ok(None);
+                };
+                result.push(self.expand_form(subject, bound)?);
+                self.check_exhaustive(clauses, bound);
+                for clause in clauses {
+                    let LispType::Cons(clause) = clause else {
+                        return Ok(None);
+                    };
+                    let items: Vec<LispType> = clause.iter().cloned().collect();
+                    let Some((pattern, code)) = items.split_first() else {
+                        return Ok(None);
+                    };
+                    let mut expanded = vec![pattern.clone()];
+                    expanded.extend(self.expand_scoped(&[pattern], code, bound)?);
+                    result.push(LispType::list(expanded));
+                }
+            }
+            _ => result.extend(self.expand_all(args, bound)?),
+        }
+        Ok(Some(result))
+    }
+
+    /// Warns when the clauses of a `match` take apart variants of a sum type
+    /// but leave some out. An unguarded clause covers a variant when its
+    /// field patterns are all distinct variables, and covers everything
+    /// when its whole pattern is a symbol.
+    fn check_exhaustive(&mut self, clauses: &[LispType], bound: &[String]) {
+        let mut sum: Option<Rc<SumType>> = None;
+        let mut covered: Vec<String> = Vec::new();
+        for clause in clauses {
+            let LispType::Cons(clause) = clause else {
+                return;
+            };
+            let guarded = matches!(clause.nth(1), Some(LispType::SpecForm(SpecForms::WHEN)));
+            let pattern = match clause.car() {
+                Some(LispType::Symbol(_)) if !guarded => return,
+                Some(LispType::Cons(pattern)) => pattern,
+                _ => continue,
+            };
+            let Some(rtype) = self.variant_of(pattern, bound) else {
+                continue;
+            };
+            let Some(variant_sum) = &rtype.sum else {
+                continue;
+            };
+            match &sum {
+                None => sum = Some(variant_sum.clone()),
+                Some(sum) if !Rc::ptr_eq(sum, variant_sum) => continue,
+                Some(_) => {}
+            }
+            // A repeated variable only matches equal fields.
+            let mut names = Vec::new();
+            let irrefutable = pattern.iter().skip(1).all(|p| match p {
+                LispType::Symbol(name) if base_name(name) == "_" => true,
+                LispType::Symbol(name) if !names.contains(&name) => {
+                    names.push(name);
+                    true
+                }
+                _ => false,
+            });
+            if !guarded && irrefutable {
+                covered.push(rtype.name.clone());
+            }
+        }
+
+        let Some(sum) = sum else {
+            return;
+        };
+        let missing: Vec<&str> = sum
+            .variants
+            .iter()
+            .filter(|variant| !covered.contains(variant))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            self.warnings.push(format!(
+                "match over {} does not cover {}",
+                sum.name,
+                missing.join(", ")
+            ));
+        }
+    }
+
+    /// The variant whose constructor heads a `match` pattern, unless a local
+    /// variable shadows the constructor.
+    fn variant_of(&self, pattern: &ConsList, bound: &[String]) -> Option<Rc<RecordType>> {
+        let Some(LispType::Symbol(head)) = pattern.car() else {
+            return None;
+        };
+        if bound.contains(head) {
+            return None;
+        }
+        match self.lookup(head)? {
+            LispType::RecordProc(proc) => match &proc.op {
+                RecordOp::Construct(rtype, _) if rtype.sum.is_some() => Some(rtype.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The `(pattern init)` pairs of a let form.
+    fn binding_pairs(bindings: &ConsList) -> Option<Vec<(LispType, LispType)>> {
+        bindings
+            .iter()
+            .map(|binding| match binding {
+                LispType::Cons(pair) if pair.is_proper() && pair.len() == 2 => {
+                    Some((pair.nth(0)?.clone(), pair.nth(1)?.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Expands the unquoted parts of a quasiquote template. `depth` counts
+    /// the enclosing quasiquotes, as in `Evaluator::quasiquote`.
+    fn expand_quasi(
+        &mut self,
+        template: &LispType,
+        depth: usize,
+        bound: &mut Vec<String>,
+    ) -> Result<LispType, EvaluatorError> {
+        let list = match template {
+            LispType::Cons(list) if !list.is_empty() => list,
+            _ => return Ok(template.clone()),
+        };
+        let depth = match (Self::as_quasi_form(list), depth) {
+            (Some((form @ (SpecForms::UNQUOTE | SpecForms::UNQUOTESPLICING), arg)), 1) => {
+                let arg = self.expand_form(&arg, bound)?;
+                return Ok(LispType::list(vec![LispType::SpecForm(form), arg]));
+            }
+            (Some((SpecForms::UNQUOTE | SpecForms::UNQUOTESPLICING, _)), _) => depth - 1,
+            (Some((SpecForms::QUASIQUOTE, _)), _) => depth + 1,
+            _ => depth,
+        };
+        let mut items = Vec::new();
+        let mut current: &ConsList = list;
+        let tail = loop {
+            match current {
+                ConsList::Cons(item, rest) => {
+                    items.push(self.expand_quasi(item, depth, bound)?);
+                    // The tail of `(a . ,x)`, which reads as `(a unquote x)`.
+                    if Self::as_quasi_form(rest).is_some() {
+                        let rest = LispType::Cons(rest.clone());
+                        break self.expand_quasi(&rest, depth, bound)?;
+                    }
+                    current = rest;
+                }
+                ConsList::Pair(item, tail) => {
+                    items.push(self.expand_quasi(item, depth, bound)?);
+                    break self.expand_quasi(tail, depth, bound)?;
+                }
+                ConsList::Nil => break LispType::nil(),
+            }
+        };
+        Ok(ConsList::from(items).append(tail))
+    }
+}
+
+/// Whether `form` contains a record read from a `#S(...)` literal.
+fn has_literal(form: &LispType) -> bool {
+    match form {
+        LispType::Cons(list) => {
+            let mut current: &ConsList = list;
+            loop {
+                match current {
+                    ConsList::Cons(head, rest) => {
+                        if has_literal(head) {
+                            return true;
+                        }
+                        current = rest;
+                    }
+                    ConsList::Pair(head, tail) => return has_literal(head) || has_literal(tail),
+                    ConsList::Nil => return false,
+                }
+            }
+        }
+        LispType::Vector(vector) => vector.items.borrow().iter().any(has_literal),
+        LispType::HashMap(map) => map
+            .iter()
+            .any(|(key, value)| has_literal(key) || has_literal(value)),
+        LispType::Set(set) => set.iter().any(has_literal),
+        LispType::Record(record) => record.rtype.literal || record.values.iter().any(has_literal),
+        _ => false,
+    }
+}
+
+/// Rejects a `recur` in the expanded body of a function that is not inside
+/// a `loop` of that body: it could only ever fail, since a function body is
+/// never in tail position of a loop around it.
+fn check_function_body(body: &[LispType]) -> Result<(), EvaluatorError> {
+    match body.iter().find_map(stray_recur) {
+        Some(recur) => Err(EvaluatorError::Other(format!(
+            "recur must be in tail position of a loop, but is in a function body: {}",
+            recur.show()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// The first `recur` in `form` that is not in the body of a `loop`.
+fn stray_recur(form: &LispType) -> Option<&LispType> {
+    let LispType::Cons(list) = form else {
+        return None;
+    };
+    if !list.is_proper() {
+        return None;
+    }
+    match list.car() {
+        Some(LispType::SpecForm(SpecForms::QUOTE | SpecForms::QUASIQUOTE)) => None,
+        Some(LispType::SpecForm(SpecForms::RECUR)) => Some(form),
+        // Only the bindings of a loop are outside of it.
+        Some(LispType::SpecForm(SpecForms::LOOP)) => list.iter().nth(1).and_then(stray_recur),
+        _ => list.iter().find_map(stray_recur),
+    }
+}
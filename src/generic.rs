@@ -0,0 +1,99 @@
+use crate::lisptype::{Lambda, LispType};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The type every value belongs to, which unspecialized method parameters
+/// dispatch on.
+pub const ANY: &str = "any";
+
+/// A generic function made by `defgeneric` or `defmethod`. Calling it runs
+/// the most specific of its methods that apply to the types of the
+/// arguments.
+#[derive(Debug)]
+pub struct Generic {
+    pub name: String,
+    /// The number of required arguments, which the methods dispatch on.
+    pub arity: usize,
+    pub methods: RefCell<Vec<Rc<Method>>>,
+}
+
+/// A method of a generic function.
+#[derive(Debug)]
+pub struct Method {
+    /// The type name each required parameter is specialized on.
+    pub specializers: Vec<String>,
+    pub lambda: Lambda,
+}
+
+/// The methods left to run after the current one, bound to
+/// `call-next-method` in a method body.
+#[derive(Debug)]
+pub struct NextMethod {
+    pub generic: Rc<Generic>,
+    pub methods: Vec<Rc<Method>>,
+    /// The arguments of the current call, used when `call-next-method` is
+    /// called without any.
+    pub args: Vec<LispType>,
+}
+
+/// The type names `value` belongs to, from the most to the least specific:
+/// its `typeof` name, then `number` for numbers or the sum type of a
+/// `deftype` variant, then `any`.
+pub fn type_names(value: &LispType) -> Vec<String> {
+    let mut names = vec![value.type_name().to_string()];
+    match value {
+        LispType::Integer(_) | LispType::Float(_) => names.push("number".to_string()),
+        LispType::Record(record) => {
+            names.extend(record.rtype.sum.as_ref().map(|sum| sum.name.clone()))
+        }
+        _ => {}
+    }
+    names.push(ANY.to_string());
+    names
+}
+
+impl Generic {
+    pub fn new(name: String, arity: usize) -> Self {
+        Generic {
+            name,
+            arity,
+            methods: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Adds a method, replacing the one with the same specializers if any.
+    pub fn add_method(&self, method: Method) {
+        let mut methods = self.methods.borrow_mut();
+        let method = Rc::new(method);
+        match methods
+            .iter_mut()
+            .find(|other| other.specializers == method.specializers)
+        {
+            Some(other) => *other = method,
+            None => methods.push(method),
+        }
+    }
+
+    /// The methods that apply to `args`, most specific first. Methods are
+    /// ordered by how specific their first specializer is for the first
+    /// argument, then the second, and so on, as in CLOS.
+    pub fn applicable(&self, args: &[LispType]) -> Vec<Rc<Method>> {
+        let types: Vec<Vec<String>> = args.iter().take(self.arity).map(type_names).collect();
+        let mut ranked: Vec<(Vec<usize>, Rc<Method>)> = self
+            .methods
+            .borrow()
+            .iter()
+            .filter_map(|method| {
+                let ranks = method
+                    .specializers
+                    .iter()
+                    .zip(&types)
+                    .map(|(specializer, names)| names.iter().position(|name| name == specializer))
+                    .collect::<Option<Vec<usize>>>()?;
+                Some((ranks, method.clone()))
+            })
+            .collect();
+        ranked.sort_by(|(a, _), (b, _)| a.cmp(b));
+        ranked.into_iter().map(|(_, method)| method).collect()
+    }
+}
@@ -2,11 +2,26 @@
 pub enum Token {
     LParen(usize, i64),
     RParen(usize, i64),
+    LBrace(usize, i64),
+    /// Opening `#{` of a set literal, closed by `RBrace`.
+    LSetBrace(usize, i64),
+    /// Opening `#S(` of a record literal, closed by `RParen`.
+    LRecordParen(usize, i64),
+    RBrace(usize, i64),
+    LBracket(usize, i64),
+    RBracket(usize, i64),
     Float(f64),
     Integer(i64),
+    Bool(bool),
+    Char(char),
     Symbol(String),
     StringLiteral(String),
     Comment(String),
+    Dot,
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
 }
 
 #[derive(Debug)]
@@ -24,7 +39,7 @@ pub struct Lexer {
     paren_count: isize,
 }
 
-static SYMB_CHARS: &str = "'=+-!*/><";
+static SYMB_CHARS: &str = "=+-!?*/><_";
 
 impl Lexer {
     pub fn new(input: &str) -> Self {
@@ -74,23 +89,63 @@ impl Lexer {
                 }
                 Ok(Some(Token::RParen(self.string, self.string_position)))
             }
+            '{' => {
+                self.position += 1;
+                self.string_position += 1;
+                Ok(Some(Token::LBrace(self.string, self.string_position)))
+            }
+            '}' => {
+                self.position += 1;
+                self.string_position += 1;
+                Ok(Some(Token::RBrace(self.string, self.string_position)))
+            }
+            '[' => {
+                self.position += 1;
+                self.string_position += 1;
+                Ok(Some(Token::LBracket(self.string, self.string_position)))
+            }
+            ']' => {
+                self.position += 1;
+                self.string_position += 1;
+                Ok(Some(Token::RBracket(self.string, self.string_position)))
+            }
             '"' => self.read_string(),
             ';' => self.read_comment(),
+            '#' => self.read_dispatch(),
+            '.' if self
+                .peek_char()
+                .is_none_or(|ch| ch.is_whitespace() || "(){}[]".contains(ch)) =>
+            {
+                self.position += 1;
+                self.string_position += 1;
+                Ok(Some(Token::Dot))
+            }
+            // Symbols made of dots, such as the `...` of syntax-rules patterns.
+            '.' if self.peek_char() == Some('.') => {
+                let start_pos = self.position;
+                while self.input.get(self.position) == Some(&'.') {
+                    self.position += 1;
+                    self.string_position += 1;
+                }
+                let symbol: String = self.input[start_pos..self.position].iter().collect();
+                Ok(Some(Token::Symbol(symbol)))
+            }
+            '\'' | '`' | ',' => self.read_quote(),
             '-' => {
-                if self.peek_char().map_or(false, |ch| ch.is_digit(10)) {
+                if self.peek_char().is_some_and(|ch| ch.is_ascii_digit()) {
                     self.read_number()
                 } else {
                     self.read_symbol()
                 }
             }
             '+' => {
-                if self.peek_char().map_or(false, |ch| ch.is_digit(10)) {
+                if self.peek_char().is_some_and(|ch| ch.is_ascii_digit()) {
                     self.read_number()
                 } else {
                     self.read_symbol()
                 }
             }
-            c if c.is_digit(10) => self.read_number(),
+            c if c.is_ascii_digit() => self.read_number(),
             c if is_symbol_start(c) => self.read_symbol(),
             _ => Err(LexerError::UnexpectedChar(
                 current_char,
@@ -130,7 +185,7 @@ impl Lexer {
             let current_char = self.input[self.position];
             if current_char == '.' && !has_dot {
                 has_dot = true;
-            } else if !current_char.is_digit(10) && current_char != '.' {
+            } else if !current_char.is_ascii_digit() && current_char != '.' {
                 break;
             }
             self.position += 1;
@@ -166,26 +221,45 @@ impl Lexer {
         }
     }
 
+    /// Reads a string literal, handling the escapes `\"`, `\\`, `\n`, `\t` and `\r`.
     fn read_string(&mut self) -> Result<Option<Token>, LexerError> {
         self.position += 1;
         self.string_position += 1;
 
-        let start_pos = self.position;
+        let mut string_content = String::new();
 
         while self.position < self.input.len() {
             match self.input[self.position] {
                 '"' => {
-                    let string_content: String =
-                        self.input[start_pos..self.position].iter().collect();
                     self.position += 1;
                     self.string_position += 1;
                     return Ok(Some(Token::StringLiteral(string_content)));
                 }
+                '\\' => {
+                    let escaped = match self.peek_char() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some(ch @ ('"' | '\\')) => ch,
+                        Some(ch) => {
+                            return Err(LexerError::UnexpectedChar(
+                                ch,
+                                self.string,
+                                self.string_position + 1,
+                            ))
+                        }
+                        None => return Err(LexerError::UnexpectedEof),
+                    };
+                    string_content.push(escaped);
+                    self.position += 2;
+                    self.string_position += 2;
+                }
                 '\n' => {
                     // Handle multi-line strings or unexpected newline
                     return Err(LexerError::UnexpectedEof);
                 }
-                _ => {
+                ch => {
+                    string_content.push(ch);
                     self.position += 1;
                     self.string_position += 1;
                 }
@@ -217,6 +291,109 @@ impl Lexer {
         Ok(Some(Token::Comment(comment_content)))
     }
 
+    /// Reads one of the quoting reader macros: `'`, `` ` ``, `,` or `,@`.
+    fn read_quote(&mut self) -> Result<Option<Token>, LexerError> {
+        let token = match self.input[self.position] {
+            '\'' => Token::Quote,
+            '`' => Token::Quasiquote,
+            _ if self.peek_char() == Some('@') => {
+                self.position += 1;
+                self.string_position += 1;
+                Token::UnquoteSplicing
+            }
+            _ => Token::Unquote,
+        };
+
+        self.position += 1;
+        self.string_position += 1;
+        Ok(Some(token))
+    }
+
+    /// Reads a `#`-prefixed literal such as `#t`, `#f`, the `#{` of a set
+    /// or the `#S(` of a record.
+    fn read_dispatch(&mut self) -> Result<Option<Token>, LexerError> {
+        if self.peek_char() == Some('{') {
+            self.position += 2;
+            self.string_position += 2;
+            return Ok(Some(Token::LSetBrace(self.string, self.string_position)));
+        }
+
+        if self.peek_char() == Some('S') && self.input.get(self.position + 2) == Some(&'(') {
+            self.position += 3;
+            self.string_position += 3;
+            self.paren_count += 1;
+            return Ok(Some(Token::LRecordParen(self.string, self.string_position)));
+        }
+
+        if self.peek_char() == Some('\\') {
+            return self.read_char();
+        }
+
+        let token = match self.peek_char() {
+            Some('t') => Token::Bool(true),
+            Some('f') => Token::Bool(false),
+            _ => {
+                return Err(LexerError::UnexpectedChar(
+                    '#',
+                    self.string,
+                    self.string_position,
+                ))
+            }
+        };
+
+        if self
+            .input
+            .get(self.position + 2)
+            .is_some_and(|&ch| is_symbol_part(ch))
+        {
+            return Err(LexerError::UnexpectedChar(
+                self.input[self.position + 2],
+                self.string,
+                self.string_position + 2,
+            ));
+        }
+
+        self.position += 2;
+        self.string_position += 2;
+        Ok(Some(token))
+    }
+
+    /// Reads a character literal: `#\a`, a named character such as
+    /// `#\space` or `#\newline`, or a hex code point such as `#\x41`.
+    fn read_char(&mut self) -> Result<Option<Token>, LexerError> {
+        let start_pos = self.position + 2;
+        if start_pos >= self.input.len() {
+            return Err(LexerError::UnexpectedEof);
+        }
+
+        // The first character is always part of the literal, so `#\(` and
+        // `#\ ` work; a name continues with alphanumeric characters.
+        let mut end_pos = start_pos + 1;
+        while end_pos < self.input.len() && self.input[end_pos].is_alphanumeric() {
+            end_pos += 1;
+        }
+
+        let name: String = self.input[start_pos..end_pos].iter().collect();
+        let ch = if name.chars().count() == 1 {
+            name.chars().next()
+        } else {
+            char_from_name(&name)
+        };
+
+        match ch {
+            Some(ch) => {
+                self.string_position += (end_pos - self.position) as i64;
+                self.position = end_pos;
+                Ok(Some(Token::Char(ch)))
+            }
+            None => Err(LexerError::UnexpectedChar(
+                self.input[start_pos],
+                self.string,
+                self.string_position + 2,
+            )),
+        }
+    }
+
     fn read_symbol(&mut self) -> Result<Option<Token>, LexerError> {
         let start_pos = self.position;
 
@@ -230,10 +407,29 @@ impl Lexer {
     }
 }
 
+/// Named characters accepted after `#\`, shared with the printer.
+pub const CHAR_NAMES: [(&str, char); 7] = [
+    ("space", ' '),
+    ("newline", '\n'),
+    ("tab", '\t'),
+    ("return", '\r'),
+    ("nul", '\0'),
+    ("delete", '\x7f'),
+    ("escape", '\x1b'),
+];
+
+fn char_from_name(name: &str) -> Option<char> {
+    if let Some(&(_, ch)) = CHAR_NAMES.iter().find(|(n, _)| *n == name) {
+        return Some(ch);
+    }
+    let hex = name.strip_prefix('x')?;
+    u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+}
+
 fn is_symbol_start(c: char) -> bool {
     c.is_alphabetic() || SYMB_CHARS.contains(c)
 }
 
 fn is_symbol_part(c: char) -> bool {
-    c.is_alphanumeric() || SYMB_CHARS.contains(c)
+    c.is_alphanumeric() || SYMB_CHARS.contains(c) || c == ':'
 }
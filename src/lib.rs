@@ -1,17 +1,40 @@
+pub mod builtins;
 pub mod conslist;
+pub mod env;
 pub mod evaluator;
+pub mod expander;
+pub mod generic;
 pub mod lexer;
 pub mod lisptype;
+pub mod module;
 pub mod parser;
+pub mod protocol;
+pub mod record;
+pub mod regex;
+pub mod syntax_rules;
 pub mod type_enums;
 
 #[cfg(test)]
 mod tests {
     use super::conslist::*;
+    use super::evaluator::*;
     use super::lisptype::*;
+    use super::parser::*;
+    use std::collections::HashMap;
     use std::rc::Rc;
 
+    /// Reads `src` and evaluates each top-level form, returning the last result.
+    fn run(src: &str) -> Result<LispType, EvaluatorError> {
+        let mut evaluator = Evaluator::new();
+        let mut result = LispType::nil();
+        for form in Parser::read(src).expect("parser error").iter() {
+            result = evaluator.eval(form.clone())?;
+        }
+        Ok(result)
+    }
+
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_show_list_of_all_types() {
         let cons_list = Rc::new(ConsList::Cons(
             LispType::Integer(42),
@@ -35,13 +58,1546 @@ mod tests {
 
         println!("{:?}", cons_test_lisp_type);
         assert_eq!(
-            "( 10 ( 42 ( 3.14 ( true ( 'hello' ( my_symbol  ) ) ) ) ) )",
+            "(10 42 3.14 #t \"hello\" my_symbol)",
             cons_test_lisp_type.show()
         );
 
+        assert_eq!("(10 42 3.14 #t \"hello\" my_symbol)", new_cons_list.show());
+        assert_eq!(
+            "(10 42 3.14 #t hello my_symbol)",
+            cons_test_lisp_type.display()
+        );
+    }
+
+    #[test]
+    fn test_boolean_and_nil_literals() {
+        assert!(matches!(run("#t").unwrap(), LispType::Bool(true)));
+        assert!(matches!(run("false").unwrap(), LispType::Bool(false)));
+        assert!(run("nil").unwrap().is_nil());
+        assert!(run("()").unwrap().is_nil());
+    }
+
+    #[test]
+    fn test_truthiness_in_cond() {
+        let pick = |test: &str| run(&format!("(cond {} 1 2)", test)).unwrap().show();
+        assert_eq!(pick("#f"), "2");
+        assert_eq!(pick("nil"), "2");
+        assert_eq!(pick("0"), "1");
+        assert_eq!(pick("\"\""), "1");
+        assert_eq!(pick("#t"), "1");
+        assert!(run("(cond #f 1 #f 2)").unwrap().is_nil());
+    }
+
+    #[test]
+    fn test_quote_reader_macros() {
+        assert_eq!(run("'(a b)").unwrap().show(), "(a b)");
+        assert_eq!(run("''a").unwrap().show(), "(quote a)");
+        assert_eq!(
+            run("'(`a ,b ,@c)").unwrap().show(),
+            "((quasiquote a) (unquote b) (unquote-splicing c))"
+        );
+    }
+
+    #[test]
+    fn test_quasiquote() {
+        assert_eq!(
+            run("(def x 5) `(a ,x ,@'(1 2) b)").unwrap().show(),
+            "(a 5 1 2 b)"
+        );
+        assert_eq!(
+            run("(def x 5) `(1 `(2 ,(3 ,x)))").unwrap().show(),
+            "(1 (quasiquote (2 (unquote (3 5)))))"
+        );
+        assert_eq!(run("`(a . ,(+ 1 2))").unwrap().show(), "(a . 3)");
+        assert_eq!(run("(def x '(2 3)) `(1 . ,x)").unwrap().show(), "(1 2 3)");
+        assert!(run(",x").is_err());
+        assert!(run("`,@x").is_err());
+    }
+
+    #[test]
+    fn test_dotted_pairs() {
+        assert_eq!(run("(cons 1 2)").unwrap().show(), "(1 . 2)");
+        assert_eq!(run("(cons 1 '(2))").unwrap().show(), "(1 2)");
+        assert_eq!(run("'(1 2 . 3)").unwrap().show(), "(1 2 . 3)");
+        assert_eq!(run("'(1 . (2 3))").unwrap().show(), "(1 2 3)");
+        assert_eq!(run("(car '(1 . 2))").unwrap().show(), "1");
+        assert_eq!(run("(cdr '(1 . 2))").unwrap().show(), "2");
+        assert_eq!(run("(cdr '(1 2 . 3))").unwrap().show(), "(2 . 3)");
+        assert_eq!(run("(cdr '(1))").unwrap().show(), "()");
+        assert!(run("(car '())").is_err());
+        assert_eq!(run("`(1 . 2)").unwrap().show(), "(1 . 2)");
+        assert_eq!(run("`(,(+ 1 1) ,@'(3) . x)").unwrap().show(), "(2 3 . x)");
+
+        assert!(Parser::read("(. 1)").is_err());
+        assert!(Parser::read("(1 . 2 3)").is_err());
+        assert!(Parser::read("(1 .)").is_err());
+        assert_eq!(run("(quote (a. b))").unwrap().show(), "(a . b)");
+    }
+
+    #[test]
+    fn test_list_and_pair_predicates() {
+        let check = |src: &str| matches!(run(src).unwrap(), LispType::Bool(true));
+        assert!(check("(list? '(1 2))"));
+        assert!(check("(list? nil)"));
+        assert!(!check("(list? '(1 . 2))"));
+        assert!(!check("(list? 1)"));
+        assert!(check("(pair? '(1 . 2))"));
+        assert!(check("(pair? (list 1))"));
+        assert!(!check("(pair? nil)"));
+    }
+
+    #[test]
+    fn test_write_and_display_modes() {
+        let value = run(r#"'("a \"quoted\"\nline" 1.0 -2.5 (x . "y"))"#).unwrap();
+        assert_eq!(value.show(), r#"("a \"quoted\"\nline" 1.0 -2.5 (x . "y"))"#);
+        assert_eq!(value.to_string(), value.show());
+        assert_eq!(value.display(), "(a \"quoted\"\nline 1.0 -2.5 (x . y))");
+    }
+
+    /// Small xorshift generator so the round-trip property needs no dependencies.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    fn random_datum(rng: &mut Rng, depth: u32) -> LispType {
+        const SYMBOLS: [&str; 5] = ["x", "my_symbol", "ok?", "swap!", "a-b"];
+        const CHARS: [char; 10] = ['a', 'Z', ' ', '"', '\\', '\n', '\t', 'λ', '(', '\u{1}'];
+
+        match rng.below(if depth == 0 { 6 } else { 11 }) {
+            0 => LispType::Integer(rng.next() as i64),
+            1 => LispType::Float((rng.next() as i64 as f64) / (1u64 << rng.below(64)) as f64),
+            2 => LispType::Bool(rng.below(2) == 0),
+            3 => LispType::String(
+                (0..rng.below(6))
+                    .map(|_| CHARS[rng.below(CHARS.len() as u64) as usize])
+                    .collect(),
+            ),
+            4 => LispType::Symbol(SYMBOLS[rng.below(SYMBOLS.len() as u64) as usize].to_string()),
+            5 => LispType::Char(CHARS[rng.below(CHARS.len() as u64) as usize]),
+            6 => {
+                let items: Vec<LispType> = (0..rng.below(4))
+                    .map(|_| random_datum(rng, depth - 1))
+                    .collect();
+                items.into_iter().rev().fold(LispType::nil(), |tail, head| {
+                    LispType::Cons(ConsList::cons(head, tail))
+                })
+            }
+            7 => LispType::Cons(ConsList::cons(
+                random_datum(rng, depth - 1),
+                random_datum(rng, 0),
+            )),
+            8 => {
+                let items = (0..rng.below(4))
+                    .map(|_| random_datum(rng, depth - 1))
+                    .collect();
+                LispType::Vector(Rc::new(LispVector::new(items, false)))
+            }
+            9 => {
+                let items = (0..rng.below(4))
+                    .map(|_| random_datum(rng, depth - 1))
+                    .collect();
+                LispType::Set(Rc::new(items))
+            }
+            _ => {
+                let mut map = LispMap::default();
+                for _ in 0..rng.below(3) {
+                    map.insert(random_datum(rng, depth - 1), random_datum(rng, depth - 1));
+                }
+                LispType::HashMap(Rc::new(map))
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_read_round_trip() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            let datum = random_datum(&mut rng, 3);
+            let written = datum.show();
+            let read = Parser::read(&written).unwrap_or_else(|e| panic!("{}: {:?}", written, e));
+            assert_eq!(read.car(), Some(&datum), "{}", written);
+        }
+    }
+
+    #[test]
+    fn test_equality_builtins() {
+        let check = |src: &str| matches!(run(src).unwrap(), LispType::Bool(true));
+        assert!(check("(equal? '(1 (2 \"x\") . 3) '(1 (2 \"x\") . 3))"));
+        assert!(!check("(equal? '(1 2) '(1 2 3))"));
+        assert!(!check("(equal? 1 1.0)"));
+        assert!(check("(eqv? 1.5 1.5)"));
+        assert!(!check("(eqv? '(1) '(1))"));
+        assert!(check("(def x '(1)) (eq? x x)"));
+        assert!(check("(eq? nil '())"));
+    }
+
+    #[test]
+    fn test_lisp_values_as_hash_keys() {
+        let key = run("'(a (1 2.0) \"s\")").unwrap();
+        let mut map = HashMap::new();
+        map.insert(key, 1);
+        map.insert(LispType::Float(f64::NAN), 2);
+        map.insert(LispType::Float(-0.0), 3);
+
+        assert_eq!(map.get(&run("(list 'a '(1 2.0) \"s\")").unwrap()), Some(&1));
+        assert_eq!(map.get(&LispType::Float(-f64::NAN)), Some(&2));
+        assert_eq!(map.get(&LispType::Float(0.0)), Some(&3));
+        assert_eq!(map.get(&LispType::Integer(0)), None);
+    }
+
+    #[test]
+    fn test_hash_map_literals() {
+        assert_eq!(run("{\"a\" (+ 1 2)}").unwrap().show(), "{\"a\" 3}");
+        assert_eq!(run("'{\"a\" (+ 1 2)}").unwrap().show(), "{\"a\" (+ 1 2)}");
+        assert_eq!(run("(hash-get {'k '(1 2)} 'k)").unwrap().show(), "(1 2)");
+        assert!(run("(hash-get {} 'missing)").unwrap().is_nil());
+        assert_eq!(run("(hash-get {} 'missing 0)").unwrap().show(), "0");
+        assert!(matches!(
+            Parser::read("{1}"),
+            Err(ParserError::OddMapLiteral)
+        ));
+    }
+
+    #[test]
+    fn test_hash_map_operations() {
+        let check = |src: &str| matches!(run(src).unwrap(), LispType::Bool(true));
+        assert!(check(
+            "(def m {1 \"one\"}) (def n (hash-set m 2 \"two\")) (equal? m {1 \"one\"})"
+        ));
+        assert!(check("(equal? (hash-set {1 2} 3 4) (hash-map 3 4 1 2))"));
+        assert!(check("(equal? (hash-remove {1 2 3 4} 1) {3 4})"));
+        assert!(check("(hash-contains? {nil #f} nil)"));
+        assert!(check(
+            "(equal? (hash-merge {1 2 3 4} {3 5} {6 7}) {1 2 3 5 6 7})"
+        ));
+        assert!(check("(== (hash-count (hash-merge {1 2} {1 3})) 1)"));
+        assert!(check("(equal? (hash-keys {(list 1 2) 3}) '((1 2)))"));
+        assert!(check("(equal? (hash-values {1 2}) '(2))"));
+        assert!(check("(equal? (hash->list {1 2}) '((1 . 2)))"));
+        assert!(run("(hash-get '(1 2) 1)").is_err());
+    }
+
+    #[test]
+    fn test_vector_literals_and_access() {
+        assert_eq!(run("[1 (+ 1 1) \"x\"]").unwrap().show(), "[1 2 \"x\"]");
+        assert_eq!(run("'[a (b)]").unwrap().show(), "[a (b)]");
+        assert_eq!(run("(vector-ref [10 20 30] 2)").unwrap().show(), "30");
+        assert_eq!(
+            run("(vector-length (make-vector 4 0))").unwrap().show(),
+            "4"
+        );
+        assert!(run("(vector-ref [1] 1)").is_err());
+        assert!(run("(vector-ref [1] -1)").is_err());
+        assert!(Parser::read("[1 2").is_err());
+    }
+
+    #[test]
+    fn test_vector_mutation_and_conversion() {
+        assert_eq!(
+            run("(def v (vector 1 2 3)) (vector-set! v 0 'x) v")
+                .unwrap()
+                .show(),
+            "[x 2 3]"
+        );
+        assert!(run("(vector-set! [1 2] 0 3)").is_err());
+        assert_eq!(run("(vector-slice [0 1 2 3] 1 3)").unwrap().show(), "[1 2]");
+        assert_eq!(run("(vector-slice [0 1 2 3] 4)").unwrap().show(), "[]");
+        assert_eq!(run("(vector->list [1 [2]])").unwrap().show(), "(1 [2])");
+        assert_eq!(run("(list->vector '(1 2))").unwrap().show(), "[1 2]");
+        assert!(matches!(
+            run("(equal? (list->vector '(1 2)) [1 2])").unwrap(),
+            LispType::Bool(true)
+        ));
+        assert_eq!(run("(length [1 2 3])").unwrap().show(), "3");
+        assert_eq!(run("(length '(1 2))").unwrap().show(), "2");
+        assert_eq!(run("(length \"λx\")").unwrap().show(), "2");
+    }
+
+    #[test]
+    fn test_set_literals() {
+        assert_eq!(run("#{(+ 1 1)}").unwrap().show(), "#{2}");
+        assert_eq!(run("'#{(+ 1 1)}").unwrap().show(), "#{(+ 1 1)}");
+        assert_eq!(run("(length #{1 1 '(2) (list 2)})").unwrap().show(), "2");
+        assert!(Parser::read("#{1 2").is_err());
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let check = |src: &str| matches!(run(src).unwrap(), LispType::Bool(true));
+        assert!(check("(equal? (set-add #{1} 2) #{2 1})"));
+        assert!(check("(equal? (set-remove #{1 2} 1) (set 2))"));
+        assert!(check("(def s #{1}) (set-add s 2) (equal? s #{1})"));
+        assert!(check("(member? '(1 2) #{(list 1 2)})"));
+        assert!(!check("(member? 3 #{1 2})"));
+        assert!(check("(member? 2 '(1 2))"));
+        assert!(check("(equal? (union #{1 2} #{2 3} #{4}) #{1 2 3 4})"));
+        assert!(check("(equal? (intersection #{1 2 3} #{2 3 4}) #{2 3})"));
+        assert!(check("(equal? (difference #{1 2 3} #{2}) #{1 3})"));
+        assert!(check("(subset? #{1} #{1 2})"));
+        assert!(!check("(subset? #{1 3} #{1 2})"));
+        assert!(check("(equal? (set->list #{\"a\"}) '(\"a\"))"));
+        assert!(run("(union #{1} '(2))").is_err());
+    }
+
+    #[test]
+    fn test_char_literals() {
+        assert!(matches!(run("#\\a").unwrap(), LispType::Char('a')));
+        assert!(matches!(run("#\\space").unwrap(), LispType::Char(' ')));
+        assert!(matches!(run("#\\newline").unwrap(), LispType::Char('\n')));
+        assert!(matches!(run("#\\x41").unwrap(), LispType::Char('A')));
+        assert!(matches!(run("#\\x").unwrap(), LispType::Char('x')));
+        assert!(matches!(run("#\\(").unwrap(), LispType::Char('(')));
+        assert_eq!(
+            run("'(#\\a #\\space #\\x7 #\\λ)").unwrap().show(),
+            "(#\\a #\\space #\\x7 #\\λ)"
+        );
+        assert_eq!(run("'(#\\a #\\b)").unwrap().display(), "(a b)");
+        assert!(Parser::read("#\\bogus").is_err());
+        assert!(Parser::read("#\\xd800").is_err());
+    }
+
+    #[test]
+    fn test_char_builtins() {
+        let show = |src: &str| run(src).unwrap().show();
+        assert_eq!(show("(char->integer #\\A)"), "65");
+        assert_eq!(show("(integer->char 955)"), "#\\λ");
+        assert!(run("(integer->char 55296)").is_err());
+        assert_eq!(show("(char-upcase #\\a)"), "#\\A");
+        assert_eq!(show("(char-upcase #\\ß)"), "#\\ß");
+        assert_eq!(show("(char-downcase #\\Λ)"), "#\\λ");
+        assert_eq!(show("(char-alphabetic? #\\λ)"), "#t");
+        assert_eq!(show("(char-numeric? #\\a)"), "#f");
+        assert_eq!(show("(char-whitespace? #\\tab)"), "#t");
+        assert_eq!(show("(string-ref \"aλb\" 2)"), "#\\b");
+        assert!(run("(string-ref \"ab\" 2)").is_err());
+    }
+
+    #[test]
+    fn test_string_slicing_and_search() {
+        let show = |src: &str| run(src).unwrap().show();
+        assert_eq!(show("(string-length \"aλb\")"), "3");
+        assert_eq!(show("(substring \"hλllo\" 1 3)"), "\"λl\"");
+        assert_eq!(show("(substring \"hello\" 2)"), "\"llo\"");
+        assert!(run("(substring \"abc\" 2 4)").is_err());
+        assert_eq!(show("(string-index \"λab\" \"b\")"), "2");
+        assert_eq!(show("(string-index \"abc\" #\\z)"), "#f");
+        assert_eq!(show("(string-contains? \"haystack\" \"st\")"), "#t");
+        assert_eq!(show("(string-starts-with? \"haystack\" \"hay\")"), "#t");
+        assert_eq!(show("(string-ends-with? \"haystack\" \"hay\")"), "#f");
+    }
+
+    #[test]
+    fn test_string_transformations() {
+        let show = |src: &str| run(src).unwrap().show();
+        assert_eq!(show("(string-split \" a  b\\tc \")"), "(\"a\" \"b\" \"c\")");
+        assert_eq!(show("(string-split \"a,,b\" \",\")"), "(\"a\" \"\" \"b\")");
+        assert_eq!(show("(string-join '(\"a\" \"b\") \", \")"), "\"a, b\"");
+        assert_eq!(show("(string-join [\"a\" \"b\"])"), "\"ab\"");
+        assert_eq!(show("(string-trim \"  x \")"), "\"x\"");
+        assert_eq!(show("(string-upcase \"straße\")"), "\"STRASSE\"");
+        assert_eq!(show("(string-downcase \"ABC\")"), "\"abc\"");
+        assert_eq!(show("(string-replace \"a-b-c\" \"-\" \"+\")"), "\"a+b+c\"");
+        assert_eq!(show("(string->list \"aλ\")"), "(#\\a #\\λ)");
+        assert_eq!(show("(list->string '(#\\o #\\k))"), "\"ok\"");
+    }
+
+    #[test]
+    fn test_string_conversions() {
+        let show = |src: &str| run(src).unwrap().show();
+        assert_eq!(show("(string->number \"-42\")"), "-42");
+        assert_eq!(show("(string->number \"2.5\")"), "2.5");
+        assert_eq!(show("(string->number \"abc\")"), "#f");
+        assert_eq!(show("(string->number \"inf\")"), "#f");
+        assert_eq!(show("(number->string 3.0)"), "\"3.0\"");
+        assert_eq!(show("(string->symbol \"foo\")"), "foo");
+        assert_eq!(show("(symbol->string 'foo)"), "\"foo\"");
+        assert_eq!(show("(symbol->string 'car)"), "\"car\"");
+        assert_eq!(show("(equal? (string->symbol \"car\") 'car)"), "#t");
+        assert_eq!(show("((string->symbol \"car\") '(1 2))"), "1");
+        assert!(run("(symbol->string \"foo\")").is_err());
+    }
+
+    #[test]
+    fn test_format_directives() {
+        let format = |src: &str| match run(src).unwrap() {
+            LispType::String(s) => s,
+            other => panic!("expected a string, got {}", other.show()),
+        };
+        assert_eq!(
+            format("(format nil \"~a and ~s\" \"x\" \"x\")"),
+            "x and \"x\""
+        );
+        assert_eq!(
+            format("(format #f \"[~5a][~5@a]\" 'ab 'ab)"),
+            "[ab   ][   ab]"
+        );
+        assert_eq!(
+            format("(format #f \"~5,'0d|~@d|~x\" 42 7 255)"),
+            "00042|+7|ff"
+        );
+        assert_eq!(
+            format("(format #f \"~,2f|~8,3f|~f\" 3.14159 2 1)"),
+            "3.14|   2.000|1.0"
+        );
+        assert_eq!(format("(format nil \"~8,2,,,'*f\" 3.14159)"), "****3.14");
+        assert_eq!(format("(format #f \"a~%b~~\")"), "a\nb~");
+        assert_eq!(format("(format #f \"~{~a~^, ~}\" '(1 2 3))"), "1, 2, 3");
+        assert_eq!(
+            format("(format #f \"~{<~{~a~}>~}\" '((1 2) (3)))"),
+            "<12><3>"
+        );
+        assert_eq!(
+            format("(format #f \"~{~a=~a ~}\" [\"x\" 1 \"y\" 2])"),
+            "x=1 y=2 "
+        );
+        assert!(run("(format #t \"~a~%\" \"to stdout\")").unwrap().is_nil());
+    }
+
+    #[test]
+    fn test_format_errors_point_at_directive() {
+        let error = |src: &str| match run(src) {
+            Err(EvaluatorError::Format {
+                position, message, ..
+            }) => (position, message),
+            other => panic!("expected a format error, got {:?}", other),
+        };
+        assert_eq!(error("(format #f \"ok ~q\")").0, 3);
+        assert_eq!(error("(format #f \"~a ~a\" 1)").0, 3);
+        assert_eq!(error("(format #f \"~d\" 1.5)").0, 0);
+        assert_eq!(error("(format #f \"x ~{~a\" '(1))").0, 2);
+        assert_eq!(error("(format #f \"~}\")").0, 0);
+        assert_eq!(error("(format #f \"x ~8,2,1f\" 1.5)").0, 2);
+
+        let message = run("(format #f \"ab~q\")").unwrap_err().to_string();
+        assert!(message.contains("unknown directive ~q"), "{}", message);
+        assert!(message.ends_with("\n     ^"), "{:?}", message);
+    }
+
+    #[test]
+    fn test_regex_engine() {
+        use super::regex::Regex;
+
+        fn find<'a>(pattern: &str, text: &'a str) -> Option<Vec<Option<&'a str>>> {
+            let regex = Regex::new(pattern).unwrap();
+            regex
+                .captures_at(text, 0)
+                .map(|caps| caps.iter().map(|c| c.map(|(s, e)| &text[s..e])).collect())
+        }
+        assert_eq!(find("a+b", "xaaab"), Some(vec![Some("aaab")]));
+        assert_eq!(find("a+?", "aaa"), Some(vec![Some("a")]));
+        assert_eq!(
+            find("(a|ab)(c|bcd)", "abcd"),
+            Some(vec![Some("abcd"), Some("a"), Some("bcd")])
+        );
+        assert_eq!(find("(x)?y", "y"), Some(vec![Some("y"), None]));
+        assert_eq!(find("^\\d{2,3}$", "1234"), None);
+        assert_eq!(find("[^a-c\\s]+", "ab de"), Some(vec![Some("de")]));
+        assert_eq!(find("\\bcat\\b", "concat cat"), Some(vec![Some("cat")]));
+        assert_eq!(find("(?:ab){2}", "abab"), Some(vec![Some("abab")]));
+        assert_eq!(find("a{,", "a{,"), Some(vec![Some("a{,")]));
+        assert_eq!(find("é.", "café!"), Some(vec![Some("é!")]));
+
+        // Nested quantifiers run in linear time instead of backtracking.
+        let text = "a".repeat(5000);
+        assert!(!Regex::new("(a*)*b").unwrap().is_match(&text));
+
+        for bad in ["(ab", "ab)", "*a", "[a-", "a{5,2}", "\\q", "a{1001}"] {
+            assert!(Regex::new(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_regex_builtins() {
+        let ok = |src: &str| run(src).unwrap().show();
+        assert_eq!(
+            ok(r#"(regex-match "(\\w+)@(\\w+)" "mail bob@host now")"#),
+            r#"("bob@host" "bob" "host")"#
+        );
+        assert_eq!(ok(r#"(regex-match "(x)|y" "y")"#), r#"("y" ())"#);
+        assert_eq!(ok(r#"(regex-match "z" "abc")"#), "#f");
+        assert_eq!(
+            ok(r#"(regex-find-all "\\d+" "a1 b22 c333")"#),
+            r#"("1" "22" "333")"#
+        );
+        assert_eq!(
+            ok(r#"(regex-find-all (regex "(\\w)=(\\d)") "a=1,b=2")"#),
+            r#"(("a=1" "a" "1") ("b=2" "b" "2"))"#
+        );
+        assert_eq!(
+            ok(r#"(regex-replace "(\\w+)-(\\w+)" "ab-cd ef-gh" "$2-$1 $$")"#),
+            r#""cd-ab $ gh-ef $""#
+        );
+        assert_eq!(ok(r#"(regex-split ",\\s*" "a, b,c")"#), r#"("a" "b" "c")"#);
+        assert_eq!(ok(r#"(regex-split "" "abc")"#), r#"("a" "b" "c")"#);
+
+        assert_eq!(ok(r#"(regex "a\\.b")"#), r#"#<regex "a\\.b">"#);
+        assert_eq!(ok(r#"(regex? (regex "a"))"#), "#t");
+        assert_eq!(ok(r#"(equal? (regex "a+") (regex "a+"))"#), "#t");
+        assert_eq!(ok(r#"(eqv? (regex "a+") (regex "a+"))"#), "#f");
+
+        assert!(matches!(
+            run(r#"(regex "(a")"#),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run(r#"(regex-replace "a" "a" "$3")"#),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run("(regex-match 1 \"a\")"),
+            Err(EvaluatorError::TypeMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_lambdas_and_closures() {
+        let ok = |src: &str| run(src).unwrap().show();
+        assert_eq!(ok("((lambda (x y) (+ x y)) 1 2)"), "3");
+        assert_eq!(ok("(def sq (lambda (x) (* x x))) (sq 7)"), "49");
+        assert_eq!(ok("((lambda (a . rest) rest) 1 2 3)"), "(2 3)");
+        assert_eq!(ok("((lambda args args))"), "()");
+        assert_eq!(
+            ok("(def make-adder (lambda (n) (lambda (x) (+ x n)))) ((make-adder 10) 5)"),
+            "15"
+        );
+        // A closure keeps its own frame; set! updates the captured binding.
+        assert_eq!(
+            ok("(def counter ((lambda (n) (lambda () (set! n (+ n 1)))) 0))
+                (counter) (counter)"),
+            "2"
+        );
+        assert_eq!(ok("(def x 1) ((lambda (x) (set! x 5)) 2) x"), "1");
+        assert_eq!(
+            ok("(def fact (lambda (n) (cond (< n 2) 1 (* n (fact (- n 1)))))) (fact 10)"),
+            "3628800"
+        );
+        assert_eq!(ok("(lambda (a b . c) a)"), "#<lambda (a b . c)>");
+
+        assert!(matches!(
+            run("((lambda (x) x))"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(run("(1 2)"), Err(EvaluatorError::TypeMismatch(_))));
+        assert!(matches!(
+            run("(lambda (1) 1)"),
+            Err(EvaluatorError::TypeMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_let_forms() {
+        let ok = |src: &str| run(src).unwrap().show();
+        assert_eq!(ok("(let ((x 1) (y 2)) (+ x y))"), "3");
+        // let inits see the outer scope, let* inits the earlier bindings.
+        assert_eq!(ok("(def x 10) (let ((x 1) (y x)) y)"), "10");
+        assert_eq!(ok("(def x 10) (let* ((x 1) (y x)) y)"), "1");
+        // Bindings and defs in the body stay local.
+        assert_eq!(ok("(def x 10) (let ((x 1)) (def z x)) x"), "10");
+        assert!(matches!(
+            run("(let ((x 1)) (def z x)) z"),
+            Err(EvaluatorError::UndefinedSymbol(_))
+        ));
+        assert_eq!(
+            ok(
+                "(letrec ((ev? (lambda (n) (cond (== n 0) #t (od? (- n 1)))))
+                         (od? (lambda (n) (cond (== n 0) #f (ev? (- n 1))))))
+                  (ev? 10))"
+            ),
+            "#t"
+        );
+        assert_eq!(
+            ok("(let lp ((i 0) (acc '())) (cond (== i 3) acc (lp (+ i 1) (cons i acc))))"),
+            "(2 1 0)"
+        );
+
+        // Destructuring, including nested and dotted patterns.
+        assert_eq!(ok("(let (((a b) '(1 2))) (list b a))"), "(2 1)");
+        assert_eq!(
+            ok("(let (((a (b c) . rest) '(1 (2 3) 4 5))) (list a b c rest))"),
+            "(1 2 3 (4 5))"
+        );
+        assert_eq!(ok("(let* (((a . b) '(1 . 2)) (c (+ a b))) c)"), "3");
+        assert_eq!(ok("((lambda ((x y)) (* x y)) '(6 7))"), "42");
+        assert!(matches!(
+            run("(let (((a b) '(1))) a)"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run("(let (((a) 1)) a)"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run("(let ((1 2)) 3)"),
+            Err(EvaluatorError::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            run("(let (x) 1)"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_loops() {
+        let ok = |src: &str| run(src).unwrap().show();
+        assert_eq!(
+            ok("(def i 0) (def sum 0) (while (< i 5) (set! sum (+ sum i)) (set! i (+ i 1))) sum"),
+            "10"
+        );
+        assert_eq!(
+            ok("(loop ((i 0) (acc '())) (cond (== i 3) acc (recur (+ i 1) (cons i acc))))"),
+            "(2 1 0)"
+        );
+        // Constant stack: far more iterations than recursion could manage.
+        assert_eq!(
+            ok("(loop ((i 0) (sum 0)) (cond (== i 100000) sum (recur (+ i 1) (+ sum i))))"),
+            "4999950000"
+        );
+        assert_eq!(
+            ok("(loop (((a b) '(1 2))) (cond (> a 10) (list a b) (let ((c (+ a b))) (recur (list b c)))))"),
+            "(13 21)"
+        );
+        assert_eq!(
+            ok("(def n 0) (dotimes (i 100000) (set! n (+ n 1))) n"),
+            "100000"
+        );
+        assert_eq!(
+            ok("(def acc '()) (dotimes (i 3 acc) (set! acc (cons i acc)))"),
+            "(2 1 0)"
+        );
+        assert_eq!(ok("(dotimes (i 3 i))"), "3");
+        assert_eq!(
+            ok("(def acc 0) (dolist (x (range 100000) acc) (set! acc (+ acc x)))"),
+            "4999950000"
+        );
+        assert_eq!(
+            ok("(def acc '()) (dolist (x [1 2] acc) (set! acc (cons x acc)))"),
+            "(2 1)"
+        );
+        assert_eq!(ok("(do (def a 1) (+ a 1))"), "2");
+        // Each iteration gets its own binding for closures to capture.
+        assert_eq!(
+            ok("(def fs '()) (dotimes (i 3) (set! fs (cons (lambda () i) fs))) (map (lambda (f) (f)) fs)"),
+            "(2 1 0)"
+        );
+
+        let other = |src: &str| match run(src) {
+            Err(EvaluatorError::Other(message)) => message,
+            other => panic!("expected an error for {}, got {:?}", src, other),
+        };
+        assert!(other("(loop ((i 0)) (+ 1 (recur i)))").contains("tail position"));
+        assert!(other("(loop ((i 0)) (recur i) 1)").contains("tail position"));
+        assert!(other("(loop ((i 0)) (cond (recur i) 1))").contains("tail position"));
+        assert!(other("(loop ((i 0)) (lambda () (recur i)))").contains("tail position"));
+        assert!(other("(recur 1)").contains("outside of a loop"));
+        // A recur in a function body outside of any loop in it is rejected
+        // when the function is defined.
+        assert!(other("(def f (lambda (x) (recur x)))").contains("function body"));
+        assert!(other("(let lp ((i 0)) (if (< i 3) (recur (+ i 1)) i))").contains("function body"));
+        assert_eq!(
+            ok("(def f (lambda (n) (loop ((i 0)) (if (< i n) (recur (+ i 1)) i)))) (f 3)"),
+            "3"
+        );
+        assert!(matches!(
+            run("(let loop ((i 0)) i)"),
+            Err(EvaluatorError::InvalidArguments(message)) if message.contains("reserved form")
+        ));
+        assert!(matches!(
+            run("(loop ((i 0)) (recur 1 2))"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run("(dotimes (i \"3\") i)"),
+            Err(EvaluatorError::TypeMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let ok = |src: &str| run(src).unwrap().show();
+        assert_eq!(ok("(if (< 1 2) 'yes 'no)"), "yes");
+        assert_eq!(ok("(if '() 'yes 'no)"), "no");
+        assert_eq!(ok("(if #f 'yes)"), "()");
+        assert_eq!(ok("(when 0 1 2)"), "2");
+        assert_eq!(ok("(when #f (undefined))"), "()");
+        assert_eq!(ok("(unless #f 1 2)"), "2");
+        assert_eq!(ok("(unless 1 (undefined))"), "()");
+
+        // and/or short-circuit and return the deciding value.
+        assert_eq!(ok("(and)"), "#t");
+        assert_eq!(ok("(and 1 2 3)"), "3");
+        assert_eq!(ok("(and 1 #f (undefined))"), "#f");
+        assert_eq!(ok("(or)"), "#f");
+        assert_eq!(ok("(or #f '() 7 (undefined))"), "7");
+        assert_eq!(ok("(or #f '())"), "()");
+        assert_eq!(ok("(not '())"), "#t");
+        assert_eq!(ok("(not 0)"), "#f");
+        assert_eq!(ok("(filter not '(1 #f 2 ()))"), "(#f ())");
+
+        // case compares with equal?, so lists and strings match too.
+        let case = |key: &str| {
+            ok(&format!(
+                "(case {} ((1 2) 'small) ((\"a\" (x y)) 'data) (#\\c 'char) (else 'other))",
+                key
+            ))
+        };
+        assert_eq!(case("(+ 1 1)"), "small");
+        assert_eq!(case("\"a\""), "data");
+        assert_eq!(case("(list 'x 'y)"), "data");
+        assert_eq!(case("#\\c"), "char");
+        assert_eq!(case("3.5"), "other");
+        assert_eq!(ok("(case 1 ((2) 'two))"), "()");
+        assert_eq!(ok("(case 1.0 ((1) 'int) ((1.0) 'float))"), "float");
+
+        assert_eq!(
+            ok("(loop ((i 0)) (if (< i 5) (when #t (recur (+ i 1))) (case i ((5) (and #t i)))))"),
+            "5"
+        );
+        assert!(matches!(
+            run("(loop ((i 0)) (if (recur 1) 1 2))"),
+            Err(EvaluatorError::Other(_))
+        ));
+        assert!(matches!(
+            run("(if 1)"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run("(case 1 2)"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_match() {
+        let ok = |src: &str| run(src).unwrap().show();
+        let shape = |value: &str| {
+            ok(&format!(
+                "(match {}
+                   (0 'zero)
+                   (\"s\" 'string)
+                   ('circle 'symbol)
+                   (() 'empty)
+                   (('point x y) when (== x y) (list 'diagonal x))
+                   (('point x y) (+ x y))
+                   ((_ (a . b) . rest) (list a b rest))
+                   ({{'name n \"tags\" (first . _)}} (list n first))
+                   (other (list 'other other)))",
+                value
+            ))
+        };
+        assert_eq!(shape("0"), "zero");
+        assert_eq!(shape("\"s\""), "string");
+        assert_eq!(shape("'circle"), "symbol");
+        assert_eq!(shape("'()"), "empty");
+        assert_eq!(shape("'(point 2 2)"), "(diagonal 2)");
+        assert_eq!(shape("'(point 1 2)"), "3");
+        assert_eq!(shape("'(line (1 2 3) 4 5)"), "(1 (2 3) (4 5))");
+        assert_eq!(shape("{'name \"Ann\" \"tags\" '(x y)}"), "(\"Ann\" x)");
+        assert_eq!(shape("{'name \"Ann\"}"), "(other {name \"Ann\"})");
+        assert_eq!(shape("0.0"), "(other 0.0)");
+
+        // A repeated variable matches only equal values.
+        assert_eq!(ok("(match '(1 2) ((x x) x) (_ 'different))"), "different");
+        assert_eq!(ok("(match '((1 2) 2) (((_ x) x) x) (_ 'different))"), "2");
+
+        // Bindings of a failed clause do not leak into the next one.
+        assert_eq!(ok("(def x 1) (match '(2 3) ((x 4) x) ((_ y) x))"), "1");
+        assert_eq!(
+            ok("(loop ((i 0)) (match i (5 i) (n when (< n 5) (recur (+ n 1)))))"),
+            "5"
+        );
+
+        let err = run("(match '(1 2) ((a) a) ((a b c) a))").unwrap_err();
+        assert!(matches!(err, EvaluatorError::NoMatch(_)));
+        assert!(err.to_string().contains("(1 2)"));
+        assert!(matches!(
+            run("(match 1 (+ 1))"),
+            Err(EvaluatorError::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            run("(loop ((i 0)) (match i (n when (recur 1) n)))"),
+            Err(EvaluatorError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn test_macros() {
+        let ok = |src: &str| run(src).unwrap().show();
+        assert!(ok("(gensym)").starts_with("#:g"));
+        assert!(ok("(gensym \"tmp\")").starts_with("#:tmp"));
+        assert_eq!(ok("(equal? (gensym) (gensym))"), "#f");
+
+        // A procedural macro must use gensym to avoid capturing `tmp`.
+        let swap = "(def swap (macro (a b)
+                      (let ((tmp (gensym)))
+                        `(let ((,tmp ,a)) (set! ,a ,b) (set! ,b ,tmp)))))
+                    (def tmp 1) (def other 2)";
+        assert_eq!(
+            ok(&format!("{} (swap tmp other) (list tmp other)", swap)),
+            "(2 1)"
+        );
+        assert_eq!(
+            ok(
+                "(def my-unless (macro (test . body) `(if ,test () (do ,@body))))
+                (my-unless #f 1 2)"
+            ),
+            "2"
+        );
+
+        // syntax-rules renames the `tmp` it introduces.
+        let swap = "(define-syntax swap!
+                      (syntax-rules () ((_ a b) (let ((tmp a)) (set! a b) (set! b tmp)))))
+                    (def tmp 1) (def other 2)";
+        assert_eq!(
+            ok(&format!("{} (swap! tmp other) (list tmp other)", swap)),
+            "(2 1)"
+        );
+        assert!(ok(&format!("{} (macroexpand '(swap! x y))", swap)).contains("((tmp#"));
+
+        let my_or = "(define-syntax my-or
+                       (syntax-rules ()
+                         ((_) #f)
+                         ((_ e) e)
+                         ((_ e rest ...) (let ((t e)) (if t t (my-or rest ...))))))";
+        assert_eq!(ok(&format!("{} (def t 5) (my-or #f t)", my_or)), "5");
+        assert_eq!(ok(&format!("{} (my-or)", my_or)), "#f");
+
+        // Free template symbols refer to the macro's scope, not the caller's.
+        assert_eq!(
+            ok("(def helper (lambda (x) (* x 2)))
+                (define-syntax dbl (syntax-rules () ((_ e) (helper e))))
+                (let ((helper (lambda (x) 0))) (dbl 5))"),
+            "10"
+        );
+
+        let my_let_star = "(define-syntax my-let*
+                             (syntax-rules ()
+                               ((_ () body ...) (let () body ...))
+                               ((_ ((x v) rest ...) body ...)
+                                (let ((x v)) (my-let* (rest ...) body ...)))))";
+        assert_eq!(
+            ok(&format!(
+                "{} (my-let* ((a 1) (b (+ a 1))) (* a b))",
+                my_let_star
+            )),
+            "2"
+        );
+
+        // Literals, nested patterns under an ellipsis and quoted templates.
+        assert_eq!(
+            ok("(define-syntax for (syntax-rules (in) ((_ x in xs body ...) (map (lambda (x) body ...) xs))))
+                (for y in '(1 2 3) (* y y))"),
+            "(1 4 9)"
+        );
+        assert_eq!(
+            ok(
+                "(define-syntax pairs (syntax-rules () ((_ (k v) ...) (list (cons 'k v) ...))))
+                (pairs (a 1) (b (+ 1 1)))"
+            ),
+            "((a . 1) (b . 2))"
+        );
+        assert_eq!(
+            ok("(define-syntax tag (syntax-rules () ((_ x) '(tag x)))) (tag 5)"),
+            "(tag 5)"
+        );
+
+        assert!(matches!(
+            run("(define-syntax two (syntax-rules () ((_ a b) a))) (two 1)"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run("(syntax-rules () ((_ ... a) a))"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run("(define-syntax f (lambda (x) x))"),
+            Err(EvaluatorError::TypeMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_macro_expansion_pass() {
+        let ok = |src: &str| run(src).unwrap().show();
+        // The macro counts its expansions: each form is expanded only once,
+        // and evaluating the same form again reuses its expansion.
+        let counting = "(def n 0) (def m (macro (x) (set! n (+ n 1)) x))";
+        assert_eq!(ok(&format!("{} (dotimes (i 5) (m i)) n", counting)), "1");
+        assert_eq!(
+            ok(&format!(
+                "{} (def code '(m 1)) (dotimes (i 3) (eval code)) n",
+                counting
+            )),
+            "1"
+        );
+        // Redefining a macro drops cached expansions.
+        assert_eq!(
+            ok(&format!(
+                "{} (def code '(m 1)) (eval code) (def m (macro (x) 2)) (eval code)",
+                counting
+            )),
+            "2"
+        );
+        // So does rebinding a macro's name to something else.
+        assert_eq!(
+            ok("(def m (macro (x) x)) (def code '(m 1)) (eval code)
+                (def m (lambda (x) 99)) (eval code)"),
+            "99"
+        );
+        assert_eq!(
+            ok("(def m (macro (x) x)) (def code '(m 1)) (eval code)
+                (set! m (lambda (x) 99)) (eval code)"),
+            "99"
+        );
+
+        let mut evaluator = Evaluator::new();
+        let define = Parser::read(
+            "(define-syntax swap! (syntax-rules () ((_ a b) (let ((tmp a)) (set! a b) (set! b tmp)))))",
+        )
+        .unwrap();
+        evaluator.eval(define.car().unwrap().clone()).unwrap();
+        let form = Parser::read("(lambda (x y) (swap! x y) '(swap! x y))").unwrap();
+        let expanded = evaluator.expand(form.car().unwrap()).unwrap().show();
+        assert!(expanded.starts_with("(lambda (x y) (let ((tmp#"));
+        assert!(expanded.ends_with("(quote (swap! x y)))"));
+
+        // Local variables shadow macros; macros defined while a form runs
+        // are expanded when reached.
+        assert_eq!(
+            ok(&format!(
+                "{} (let ((m (lambda (x) (* x 10)))) (m 2))",
+                counting
+            )),
+            "20"
+        );
+        assert_eq!(
+            ok("(do (define-syntax one (syntax-rules () ((_) 1))) (one))"),
+            "1"
+        );
+        // recur may come from a macro, since loops see the expanded body.
+        assert_eq!(
+            ok("(define-syntax again (syntax-rules () ((_ x) (recur x))))
+                (loop ((i 0)) (if (< i 3) (again (+ i 1)) i))"),
+            "3"
+        );
+
+        let err =
+            run("(define-syntax first (syntax-rules () ((_ x) (car x)))) (first 5)").unwrap_err();
+        let EvaluatorError::InExpansion { call, error } = &err else {
+            panic!("expected an expansion error, got {:?}", err);
+        };
+        assert_eq!(call.show(), "(first 5)");
+        assert!(matches!(**error, EvaluatorError::TypeMismatch(_)));
+        assert!(err.to_string().ends_with("in expansion of (first 5)"));
+    }
+
+    #[test]
+    fn test_runaway_macro_expansion() {
+        // Expansions that never end fail instead of overflowing the stack.
+        for src in [
+            "(def m (macro (x) (list 'm x))) (m 1)",
+            "(define-syntax inf (syntax-rules () ((_ x) (inf (x))))) (inf 1)",
+        ] {
+            let err = run(src).unwrap_err();
+            assert!(err.to_string().contains("stack overflow"), "{}", src);
+        }
+        assert_eq!(
+            run("(def m (macro (x) (list 'm x))) (define-syntax one (syntax-rules () ((_) 1))) (one)")
+                .unwrap()
+                .show(),
+            "1"
+        );
+    }
+
+    #[test]
+    fn test_records() {
+        let ok = |src: &str| run(src).unwrap().show();
+        let point = "(defstruct point x y) (def p (make-point 1 2))";
+        assert_eq!(ok(&format!("{} p", point)), "#S(point x 1 y 2)");
+        assert_eq!(
+            ok(&format!("{} (list (point-x p) (point-y p))", point)),
+            "(1 2)"
+        );
+        assert_eq!(
+            ok(&format!(
+                "{} (list (point? p) (point? 5) (typeof p))",
+                point
+            )),
+            "(#t #f point)"
+        );
+        // Updaters leave the original record unchanged.
+        assert_eq!(
+            ok(&format!("{} (list (point-with-x p 10) p)", point)),
+            "(#S(point x 10 y 2) #S(point x 1 y 2))"
+        );
+        assert_eq!(ok(&format!("{} (equal? p (make-point 1 2))", point)), "#t");
+        assert_eq!(ok(&format!("{} (map point-x (list p p))", point)), "(1 1)");
+
+        // Types are nominal: a second definition makes a new type.
+        assert_eq!(
+            ok("(defstruct a v) (def old (make-a 1)) (defstruct a v) (list (a? old) (equal? old (make-a 1)))"),
+            "(#f #f)"
+        );
+
+        assert_eq!(
+            ok("(define-record-type account (make-account owner) account?
+                  (owner account-owner) (balance account-balance set-account-balance))
+                (def acc (set-account-balance (make-account \"ann\") 10))
+                (list (account-owner acc) (account-balance acc) (account? acc) acc)"),
+            "(\"ann\" 10 #t #S(account owner \"ann\" balance 10))"
+        );
+
+        let types = "(map (lambda (v) (typeof v)) (list 1 1.5 #t #\\a \"s\" 'x '() '(1) (cons 1 2) {} [] #{} car (lambda () 1)))";
+        assert_eq!(
+            ok(types),
+            "(integer float boolean char string symbol list list pair hash-map vector set function function)"
+        );
+
+        assert!(matches!(
+            run(&format!("{} (point-x 5)", point)),
+            Err(EvaluatorError::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            run(&format!("{} (make-point 1)", point)),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run("(defstruct dup x x)"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run("(define-record-type r (make-r z) r? (x r-x))"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_record_literals() {
+        let ok = |src: &str| run(src).unwrap().show();
+        // Printed records read back as records of the type with their name.
+        let mut evaluator = Evaluator::new();
+        let types = "(defstruct point x y) (deftype shape (circle r) (rect w h))";
+        for form in Parser::read(types).unwrap().iter() {
+            evaluator.eval(form.clone()).unwrap();
+        }
+        let read = |src: &str| Parser::read(src).unwrap().car().unwrap().clone();
+        for src in [
+            "(make-point 1 '(2 \"s\" . #\\a))",
+            "(rect [1 2] (make-point {'k #{3}} (circle 0.5)))",
+        ] {
+            let value = evaluator.eval(read(src)).unwrap();
+            let quoted = read(&format!("'{}", value.show()));
+            assert_eq!(evaluator.eval(quoted).unwrap(), value, "{}", src);
+        }
+
+        let point = "(defstruct point x y)";
+        assert_eq!(ok(&format!("{} (point-x '#S(point y 2 x 1))", point)), "1");
+        assert_eq!(
+            ok(&format!("{} #S(point x 1)", point)),
+            "#S(point x 1 y ())"
+        );
+        assert_eq!(
+            ok(&format!(
+                "{} (equal? '(#S(point x 1 y 2)) (list (make-point 1 2)))",
+                point
+            )),
+            "#t"
+        );
+
+        assert!(matches!(
+            run("'#S(point x 1 y 2)"),
+            Err(EvaluatorError::UndefinedSymbol(_))
+        ));
+        assert!(matches!(
+            run(&format!("{} '#S(point z 1)", point)),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            Parser::read("#S(point x)"),
+            Err(ParserError::MalformedRecordLiteral)
+        ));
+        assert!(matches!(
+            Parser::read("#S(point x 1 x 2)"),
+            Err(ParserError::MalformedRecordLiteral)
+        ));
+    }
+
+    #[test]
+    fn test_sum_types() {
+        let ok = |src: &str| run(src).unwrap().show();
+        let shape = "(deftype shape (circle r) (rect w h) (empty))";
+        let area = "(def area (lambda (s) (match s
+                      ((circle r) (* 3 (* r r)))
+                      ((rect w h) (* w h))
+                      ((empty) 0))))";
+        assert_eq!(
+            ok(&format!(
+                "{} {} (map area (list (circle 2) (rect 2 3) (empty)))",
+                shape, area
+            )),
+            "(12 6 0)"
+        );
+        assert_eq!(
+            ok(&format!(
+                "{} (def c (circle 1)) (list c (typeof c) (circle-r c))",
+                shape
+            )),
+            "(#S(circle r 1) circle 1)"
+        );
+        assert_eq!(
+            ok(&format!(
+                "{} (map shape? (list (circle 1) (empty) 5))",
+                shape
+            )),
+            "(#t #t #f)"
+        );
+        // Field patterns can be any pattern.
+        assert_eq!(
+            ok(&format!(
+                "{} (match (rect 2 2) ((rect 1 h) 'tall) ((rect w w) 'square) (_ 'other))",
+                shape
+            )),
+            "square"
+        );
+
+        let warnings = |src: &str| {
+            let mut evaluator = Evaluator::new();
+            for form in Parser::read(src).expect("parser error").iter() {
+                evaluator.eval(form.clone()).unwrap();
+            }
+            evaluator.take_warnings()
+        };
+        assert_eq!(
+            warnings(&format!("{} {}", shape, area)),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            warnings(&format!("{} (lambda (s) (match s ((circle r) r)))", shape)),
+            vec!["match over shape does not cover rect, empty".to_string()]
+        );
+        // A guard or a refutable field pattern does not cover its variant.
+        assert_eq!(
+            warnings(&format!(
+                "{} (lambda (s) (match s ((circle 0) 0) ((rect w h) when (> w 0) w) ((empty) 0)))",
+                shape
+            )),
+            vec!["match over shape does not cover circle, rect".to_string()]
+        );
+        assert_eq!(
+            warnings(&format!(
+                "{} (lambda (s) (match s ((circle _) 0) ((rect w w) w) ((empty) 0)))",
+                shape
+            )),
+            vec!["match over shape does not cover rect".to_string()]
+        );
+        assert_eq!(
+            warnings(&format!(
+                "{} (lambda (s) (match s ((circle r) r) (_ 0)))",
+                shape
+            )),
+            Vec::<String>::new()
+        );
+
+        assert!(matches!(
+            run(&format!("{} (match (circle 1) ((circle) 0))", shape)),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run("(deftype t (a) (a x))"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_generic_functions() {
+        let ok = |src: &str| run(src).unwrap().show();
+        let describe = "(defgeneric describe (x))
+            (defmethod describe (x) 'thing)
+            (defmethod describe ((x number)) (list 'number (call-next-method)))
+            (defmethod describe ((x integer)) (list 'integer (call-next-method)))
+            (defmethod describe ((x string)) 'string)";
+        assert_eq!(
+            ok(&format!(
+                "{} (map describe (list 1 1.5 \"s\" #\\c))",
+                describe
+            )),
+            "((integer (number thing)) (number thing) string thing)"
+        );
+
+        // Dispatch on several arguments, most specific first from the left,
+        // and on the sum type of a variant.
+        let collide = "(deftype shape (circle r) (rect w h))
+            (defmethod collide ((a circle) (b circle)) 'circles)
+            (defmethod collide ((a circle) b) 'circle-first)
+            (defmethod collide (a (b shape)) 'shape-second)";
+        assert_eq!(
+            ok(&format!(
+                "{} (list (collide (circle 1) (circle 2)) (collide (circle 1) (rect 1 2))
+                          (collide (rect 1 2) (circle 1)) (collide 5 (rect 1 2)))",
+                collide
+            )),
+            "(circles circle-first shape-second shape-second)"
+        );
+
+        // call-next-method can pass new arguments; a method with the same
+        // specializers replaces the old one.
+        assert_eq!(
+            ok("(defmethod inc (x) (+ x 1))
+                (defmethod inc ((x integer)) (* 10 (call-next-method (+ x 1))))
+                (defmethod inc ((x list)) 'old)
+                (defmethod inc ((x list)) 'new)
+                (list (inc 1) (inc '()) (typeof inc))"),
+            "(30 new function)"
+        );
+
+        assert!(matches!(
+            run("(defgeneric g (x)) (defmethod g ((x string)) x) (g 1)"),
+            Err(EvaluatorError::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            run("(defmethod g ((x string)) (call-next-method)) (g \"s\")"),
+            Err(EvaluatorError::Other(_))
+        ));
+        assert!(matches!(
+            run("(defgeneric g (x)) (defmethod g (x y) x)"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_protocols() {
+        let money = "(defstruct money cents)
+            (extend-type money
+              (+ (a b) (make-money (+ (money-cents a) (money-cents b))))
+              (* (a k) (make-money (* (money-cents a) k)))
+              (< (a b) (< (money-cents a) (money-cents b)))
+              (== (a b) (== (money-cents a) (money-cents b)))
+              (show (m) (format #f \"$~d.~2,'0d\" (/ (money-cents m) 100) (- (money-cents m) (* 100 (/ (money-cents m) 100))))))
+            (def a (make-money 150)) (def b (make-money 275))";
+        let ok = |src: &str| {
+            let mut evaluator = Evaluator::new();
+            let mut result = LispType::nil();
+            for form in Parser::read(&format!("{} {}", money, src)).unwrap().iter() {
+                result = evaluator.eval(form.clone()).unwrap();
+            }
+            evaluator.show_value(&result, true).unwrap()
+        };
+        assert_eq!(ok("(+ a b)"), "$4.25");
+        assert_eq!(ok("(list (* a 3) [b])"), "($4.50 [$2.75])");
+        assert_eq!(
+            ok("(list (< a b) (> a b) (<= a a) (>= a b) (== a (make-money 150)) (!= a b))"),
+            "(#t #f #t #f #t #t)"
+        );
+        assert_eq!(
+            ok("(format #f \"~a and ~s\" a (list b))"),
+            "\"$1.50 and ($2.75)\""
+        );
+        assert_eq!(ok("(reduce + (list a b a))"), "$5.75");
+        assert_eq!(ok("(sort (list b a) <)"), "($1.50 $2.75)");
+
+        // The second operand's implementation runs when the first operand
+        // has none, with the operands still in order.
+        let meters = "(defstruct m v) (def v (lambda (x) (if (m? x) (m-v x) x)))
+            (extend-type m (- (a b) (make-m (- (v a) (v b)))) (< (a b) (< (v a) (v b))))";
+        assert_eq!(
+            run(&format!(
+                "{} (list (- 10 (make-m 3)) (< 1 (make-m 2)) (> 1 (make-m 2)))",
+                meters
+            ))
+            .unwrap()
+            .show(),
+            "(#S(m v 7) #t #f)"
+        );
+
+        // A record type implementing seq can be iterated like a list.
+        let bag = "(defstruct bag items)
+            (extend-type bag (seq (b) (bag-items b)))
+            (def b (make-bag [1 2 3]))";
+        assert_eq!(
+            run(&format!(
+                "{} (def sum 0) (dolist (x b) (set! sum (+ sum x)))
+                 (list sum (map (lambda (x) (* x x)) b) (length b))",
+                bag
+            ))
+            .unwrap()
+            .show(),
+            "(6 (1 4 9) 3)"
+        );
+        assert_eq!(
+            run(&format!(
+                "{} (list (member 2 (make-bag '(1 2 3))) (member? 3 b) (list->vector b)
+                       (string-join (make-bag '(\"x\" \"y\")) \",\")
+                       (format #f \"~{{~a~^+~}}\" (make-bag '(1 2))))",
+                bag
+            ))
+            .unwrap()
+            .show(),
+            "((2 3) #t [1 2 3] \"x,y\" \"1+2\")"
+        );
+
+        // Types without an implementation keep failing as before.
+        assert!(matches!(
+            run("(defstruct p x) (+ (make-p 1) 2)"),
+            Err(EvaluatorError::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            run("(defstruct p x) (extend-type p (frobnicate (a) a))"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            run("(defstruct p x) (extend-type p (+ (a) a))"),
+            Err(EvaluatorError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_modules() {
+        let ok = |src: &str| run(src).unwrap().show();
+        let libs = "(module json (export parse)
+                      (def helper (lambda (s) (list 'json s)))
+                      (def parse (lambda (s) (helper s))))
+                    (module csv (export parse count)
+                      (def count 0)
+                      (def parse (lambda (s) (string-split s \",\"))))";
+        // Bindings stay inside their module unless imported.
+        assert_eq!(
+            ok(&format!(
+                "{} (list (json/parse \"x\") (csv/parse \"a,b\"))",
+                libs
+            )),
+            "((json \"x\") (\"a\" \"b\"))"
+        );
+        assert_eq!(
+            ok(&format!(
+                "{} (import (prefix json j:) (only csv parse)) (list (j:parse 1) (parse \"a\"))",
+                libs
+            )),
+            "((json 1) (\"a\"))"
+        );
+        assert_eq!(
+            ok(&format!("{} (import (prefix (only csv count) csv-))", libs)),
+            "(csv-count)"
+        );
+        assert!(matches!(
+            run(&format!("{} helper", libs)),
+            Err(EvaluatorError::UndefinedSymbol(_))
+        ));
+        assert!(matches!(
+            run(&format!("{} json/helper", libs)),
+            Err(EvaluatorError::UndefinedSymbol(_))
+        ));
+        assert!(matches!(
+            run(&format!("{} (import (only json helper))", libs)),
+            Err(EvaluatorError::UndefinedSymbol(_))
+        ));
+
+        // Modules run on first use, so they may import modules defined
+        // after them, and a module imported twice runs once.
+        assert_eq!(
+            ok("(def runs 0)
+                (module app (export main) (import util) (def main (lambda () (twice 21))))
+                (module util (export twice) (set! runs (+ runs 1)) (def twice (lambda (x) (* 2 x))))
+                (import util) (list (app/main) runs)"),
+            "(42 1)"
+        );
+
+        match run("(module a (export f) (import b) (def f 1))
+                   (module b (export g) (import c) (def g 2))
+                   (module c (export h) (import a) (def h 3))
+                   (import a)")
+        {
+            Err(e) => assert_eq!(e.to_string(), "Error: import cycle: a -> b -> c -> a"),
+            Ok(value) => panic!("expected an import cycle, got {}", value.show()),
+        }
+        assert!(matches!(
+            run("(module m (export missing) (def x 1)) (import m)"),
+            Err(EvaluatorError::UndefinedSymbol(_))
+        ));
+        // Exports must be bound by the module, not by an enclosing scope.
+        assert!(matches!(
+            run("(def g 5) (module c (export g)) c/g"),
+            Err(EvaluatorError::UndefinedSymbol(_))
+        ));
+        match run("(module d (export z) (def z (d/z))) d/z") {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Error: module d uses its own exports before its body has run"
+            ),
+            Ok(value) => panic!("expected an error, got {}", value.show()),
+        }
+        assert!(matches!(run("(export x)"), Err(EvaluatorError::Other(_))));
+        assert!(matches!(
+            run("(import nowhere)"),
+            Err(EvaluatorError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn test_list_library() {
+        let ok = |src: &str| run(src).unwrap().show();
+        assert_eq!(ok("(map (lambda (x) (* x x)) '(1 2 3))"), "(1 4 9)");
+        assert_eq!(ok("(map + '(1 2 3) [10 20])"), "(11 22)");
+        assert_eq!(ok("(for-each (lambda (x) x) '(1))"), "()");
+        assert_eq!(ok("(filter (lambda (x) (> x 1)) '(1 2 3))"), "(2 3)");
+        assert_eq!(ok("(remove (lambda (x) (> x 1)) '(1 2 3))"), "(1)");
+        assert_eq!(ok("(reduce + '(1 2 3 4))"), "10");
+        assert_eq!(ok("(fold-left list '() '(1 2))"), "((() 1) 2)");
+        assert_eq!(ok("(fold-right cons '() '(1 2 3))"), "(1 2 3)");
+        assert_eq!(ok("(apply + 1 '(2))"), "3");
+        assert_eq!(ok("(apply list 1 2 '(3 4))"), "(1 2 3 4)");
+        assert_eq!(ok("(append '(1 2) '() [3] '(4))"), "(1 2 3 4)");
+        assert_eq!(ok("(append '(1) 2)"), "(1 . 2)");
+        assert_eq!(ok("(append)"), "()");
+        assert_eq!(ok("(reverse '(1 2 3))"), "(3 2 1)");
+        assert_eq!(ok("(length '(1 2 3))"), "3");
+        assert_eq!(ok("(nth '(a b c) 1)"), "b");
+        assert_eq!(ok("(last '(a b c))"), "c");
+        assert_eq!(ok("(take '(1 2 3) 2)"), "(1 2)");
+        assert_eq!(ok("(drop '(1 2 3) 2)"), "(3)");
+        assert_eq!(ok("(zip '(1 2 3) '(a b))"), "((1 a) (2 b))");
+        assert_eq!(ok("(range 4)"), "(0 1 2 3)");
+        assert_eq!(ok("(range 5 0 -2)"), "(5 3 1)");
+        assert_eq!(ok("(assoc 'b '((a . 1) (b . 2)))"), "(b . 2)");
+        assert_eq!(ok("(assoc \"z\" '((a . 1)))"), "#f");
+        assert_eq!(ok("(member 2 '(1 2 3))"), "(2 3)");
+        assert_eq!(ok("(member 4 '(1 2 3))"), "#f");
+        assert_eq!(ok("(sort '(3 1 2) <)"), "(1 2 3)");
+        // Stable: equal keys keep their order.
+        assert_eq!(
+            ok("(sort '((1 . a) (0 . b) (1 . c) (0 . d)) (lambda (x y) (< (car x) (car y))))"),
+            "((0 . b) (0 . d) (1 . a) (1 . c))"
+        );
+
+        for bad in [
+            "(reduce + '())",
+            "(nth '(1) 1)",
+            "(take '(1) 2)",
+            "(drop '(1) 2)",
+            "(range 1 2 0)",
+            "(last [])",
+        ] {
+            assert!(
+                matches!(run(bad), Err(EvaluatorError::InvalidArguments(_))),
+                "{}",
+                bad
+            );
+        }
+        assert!(matches!(
+            run("(sort '(1 \"a\") <)"),
+            Err(EvaluatorError::TypeMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_cons_list_iteration_and_conversions() {
+        let list = ConsList::from(vec![
+            LispType::Integer(1),
+            LispType::Integer(2),
+            LispType::Integer(3),
+        ]);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.nth(1), Some(&LispType::Integer(2)));
+        assert_eq!(list.nth(3), None);
+        assert_eq!(list.show(), "(1 2 3)");
+
+        let doubled: ConsList = list
+            .iter()
+            .map(|x| LispType::Integer(x.as_integer().unwrap() * 2))
+            .collect();
+        assert_eq!(doubled.show(), "(2 4 6)");
+        let mut sum = 0;
+        for item in &doubled {
+            sum += item.as_integer().unwrap();
+        }
+        assert_eq!(sum, 12);
+        assert_eq!(doubled.into_iter().last(), Some(LispType::Integer(6)));
+
+        assert_eq!(
+            list.append(LispType::list(vec![LispType::Integer(4)]))
+                .show(),
+            "(1 2 3 4)"
+        );
+        assert_eq!(list.append(LispType::Integer(4)).show(), "(1 2 3 . 4)");
+        assert_eq!(ConsList::Nil.append(LispType::Integer(4)).show(), "4");
+
+        // Improper lists yield their cars; the final cdr is not an element.
+        let dotted = Parser::read("(1 2 . 3)").unwrap();
+        let dotted = match dotted.car() {
+            Some(LispType::Cons(list)) => list.clone(),
+            other => panic!("expected a list, got {:?}", other),
+        };
+        assert_eq!(dotted.len(), 2);
+        assert_eq!(
+            dotted.iter().cloned().collect::<Vec<_>>(),
+            vec![LispType::Integer(1), LispType::Integer(2)]
+        );
+
+        let empty: ConsList = std::iter::empty().collect();
+        assert!(empty.is_empty());
+        assert_eq!(empty.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let ok = |src: &str| run(src).unwrap().show();
+        assert_eq!(
+            ok("(let lp ((i 0)) (cond (< i 100000) (lp (+ i 1)) i))"),
+            "100000"
+        );
+        assert_eq!(
+            ok(
+                "(def count (lambda (n acc) (if (== n 0) acc (count (- n 1) (+ acc 1)))))
+                (count 100000 0)"
+            ),
+            "100000"
+        );
+        assert_eq!(
+            ok("(letrec ((ev? (lambda (n) (or (== n 0) (od? (- n 1)))))
+                         (od? (lambda (n) (and (!= n 0) (ev? (- n 1))))))
+                  (ev? 100001))"),
+            "#f"
+        );
+        assert_eq!(
+            ok(
+                "(def f (lambda (n) (when (> n 0) (let ((m (- n 1))) (f m)))))
+                (f 100000)"
+            ),
+            "()"
+        );
+
+        // Deep recursion that is not in tail position fails cleanly.
+        let mut evaluator = Evaluator::new();
+        let mut eval =
+            |src: &str| evaluator.eval(Parser::read(src).unwrap().car().unwrap().clone());
+        eval("(def depth (lambda (n) (if (== n 0) 0 (+ 1 (depth (- n 1))))))").unwrap();
+        let error = eval("(depth 1000000)").unwrap_err();
+        assert!(error.to_string().contains("stack overflow"), "{}", error);
+        assert_eq!(eval("(depth 10)").unwrap().show(), "10");
+    }
+
+    #[test]
+    fn test_long_lists_stay_off_the_stack() {
+        let ok = |src: &str| run(src).unwrap().show();
+        assert_eq!(
+            ok("(length (sort (map (lambda (x) (- 0 x)) (range 200000)) <))"),
+            "200000"
+        );
+        assert_eq!(
+            ok("(fold-left + 0 (drop (reverse (range 200000)) 199998))"),
+            "1"
+        );
         assert_eq!(
-            "( 10 ( 42 ( 3.14 ( true ( 'hello' ( my_symbol  ) ) ) ) ) )",
-            new_cons_list.show()
+            ok("(length (append (range 100000) (range 100000)))"),
+            "200000"
         );
     }
 }
@@ -1,21 +1,120 @@
 use super::conslist::ConsList;
+use crate::env::Env;
+use crate::generic::{Generic, NextMethod};
+use crate::lexer::CHAR_NAMES;
+use crate::record::{Record, RecordProc};
+use crate::regex::Regex;
+use crate::syntax_rules::SyntaxRules;
 use crate::type_enums::*;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{BuildHasherDefault, DefaultHasher, Hash, Hasher};
 use std::rc::Rc;
 
+/// Backing store of `LispType::HashMap`. A fixed hasher keeps iteration and
+/// printing order stable between runs.
+pub type LispMap = HashMap<LispType, LispType, BuildHasherDefault<DefaultHasher>>;
+
+/// Backing store of `LispType::Set`, ordered like `LispMap`.
+pub type LispSet = HashSet<LispType, BuildHasherDefault<DefaultHasher>>;
+
+/// Backing store of `LispType::Vector`. Vectors written as literals are
+/// immutable; those built at runtime can be updated with `vector-set!`.
+#[derive(Debug)]
+pub struct LispVector {
+    pub items: RefCell<Vec<LispType>>,
+    pub mutable: bool,
+}
+
+impl LispVector {
+    pub fn new(items: Vec<LispType>, mutable: bool) -> Self {
+        LispVector {
+            items: RefCell::new(items),
+            mutable,
+        }
+    }
+}
+
+/// A user-defined function created by `lambda`. It closes over the
+/// environment it was created in.
+#[derive(Debug)]
+pub struct Lambda {
+    /// One pattern per argument: a symbol, or a list pattern that
+    /// destructures the argument as in `let`.
+    pub params: Vec<LispType>,
+    /// Receives the remaining arguments as a list: `(lambda (a . rest) ...)`.
+    pub rest: Option<String>,
+    pub body: Vec<LispType>,
+    pub env: Rc<Env>,
+}
+
+/// A macro. It is bound like a variable, but a call `(name args...)` is
+/// replaced by the form the macro builds from the unevaluated arguments,
+/// which is then evaluated in place of the call.
+#[derive(Debug)]
+pub enum Macro {
+    /// Created by `(macro (params...) body...)`: a function that receives the
+    /// argument forms and returns the expansion.
+    Procedural(Rc<Lambda>),
+    /// Created by `syntax-rules`.
+    SyntaxRules(SyntaxRules),
+}
+
 #[derive(Debug, Clone)]
 pub enum LispType {
     String(String),
     Integer(i64),
     Float(f64),
     Bool(bool),
+    /// A single Unicode scalar value.
+    Char(char),
     Symbol(String),
     Cons(Rc<ConsList>),
+    /// Immutable hash map; operations return a new map.
+    HashMap(Rc<LispMap>),
+    Vector(Rc<LispVector>),
+    /// Immutable set of structurally distinct values.
+    Set(Rc<LispSet>),
+    /// A compiled regular expression, as returned by `regex`.
+    Regex(Rc<Regex>),
+    Lambda(Rc<Lambda>),
+    Macro(Rc<Macro>),
+    /// An instance of a user-defined record type.
+    Record(Rc<Record>),
+    /// A procedure generated for a record type, such as `make-point`.
+    RecordProc(Rc<RecordProc>),
+    /// A generic function made by `defgeneric` or `defmethod`.
+    Generic(Rc<Generic>),
+    /// The `call-next-method` of a running method.
+    NextMethod(Rc<NextMethod>),
     SpecForm(SpecForms),
     BinPred(BinPred),
     BinOp(BinOp),
+    Builtin(Builtin),
 }
 
 impl LispType {
+    /// The empty list, which doubles as `nil`.
+    pub fn nil() -> Self {
+        LispType::Cons(Rc::new(ConsList::Nil))
+    }
+
+    /// A proper list of `items`.
+    pub fn list(items: Vec<LispType>) -> Self {
+        LispType::Cons(Rc::new(ConsList::from(items)))
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, LispType::Cons(list) if matches!(**list, ConsList::Nil))
+    }
+
+    /// The single truthiness rule of the language: `#f` and `nil` (the empty
+    /// list) are false, every other value is true — including `0` and `""`.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, LispType::Bool(false)) && !self.is_nil()
+    }
+
     pub fn as_float(&self) -> Option<f64> {
         match self {
             LispType::Integer(i) => Some(*i as f64),
@@ -38,17 +137,320 @@ impl LispType {
         }
     }
 
+    /// The type name `typeof` reports: the name of a record's type, or one
+    /// of `integer`, `float`, `boolean`, `char`, `string`, `symbol`, `list`
+    /// (proper lists, including `nil`), `pair`, `hash-map`, `vector`, `set`,
+    /// `regex`, `function`, `macro` and `special-form`.
+    pub fn type_name(&self) -> &str {
+        match self {
+            LispType::Integer(_) => "integer",
+            LispType::Float(_) => "float",
+            LispType::Bool(_) => "boolean",
+            LispType::Char(_) => "char",
+            LispType::String(_) => "string",
+            LispType::Symbol(_) => "symbol",
+            LispType::Cons(list) if list.is_proper() => "list",
+            LispType::Cons(_) => "pair",
+            LispType::HashMap(_) => "hash-map",
+            LispType::Vector(_) => "vector",
+            LispType::Set(_) => "set",
+            LispType::Regex(_) => "regex",
+            LispType::Record(record) => &record.rtype.name,
+            LispType::Lambda(_)
+            | LispType::RecordProc(_)
+            | LispType::Generic(_)
+            | LispType::NextMethod(_)
+            | LispType::Builtin(_)
+            | LispType::BinOp(_)
+            | LispType::BinPred(_) => "function",
+            LispType::Macro(_) => "macro",
+            LispType::SpecForm(_) => "special-form",
+        }
+    }
+
+    /// `eqv?`: atoms are compared by type and value (so `1` and `1.0` differ),
+    /// pairs by identity.
+    pub fn is_eqv(&self, other: &LispType) -> bool {
+        match (self, other) {
+            (LispType::Cons(a), LispType::Cons(b)) => {
+                Rc::ptr_eq(a, b) || (self.is_nil() && other.is_nil())
+            }
+            (LispType::HashMap(a), LispType::HashMap(b)) => Rc::ptr_eq(a, b),
+            (LispType::Vector(a), LispType::Vector(b)) => Rc::ptr_eq(a, b),
+            (LispType::Set(a), LispType::Set(b)) => Rc::ptr_eq(a, b),
+            (LispType::Regex(a), LispType::Regex(b)) => Rc::ptr_eq(a, b),
+            (LispType::Lambda(a), LispType::Lambda(b)) => Rc::ptr_eq(a, b),
+            (LispType::Macro(a), LispType::Macro(b)) => Rc::ptr_eq(a, b),
+            (LispType::Record(a), LispType::Record(b)) => Rc::ptr_eq(a, b),
+            _ => self == other,
+        }
+    }
+
+    /// Readable representation, identical to the `Display` output: strings
+    /// are quoted and escaped so the result can be read back.
     pub fn show(&self) -> String {
+        self.to_string()
+    }
+
+    /// Human-oriented representation: like `show`, but strings are printed
+    /// verbatim without quotes or escapes.
+    pub fn display(&self) -> String {
+        self.repr_string(Repr::default())
+    }
+
+    pub(crate) fn repr_string(&self, repr: Repr) -> String {
+        let mut out = String::new();
+        self.write_repr(&mut out, repr)
+            .expect("writing to a String cannot fail");
+        out
+    }
+
+    /// Writes the value in canonical Lisp syntax. When `repr.readable` is
+    /// false, strings are written without quotes (display mode).
+    pub(crate) fn write_repr(&self, f: &mut impl fmt::Write, repr: Repr) -> fmt::Result {
         match self {
-            LispType::Bool(boolean) => format!("{}", boolean),
-            LispType::String(s) => format!("'{}'", s),
-            LispType::Integer(val) => format!("{}", val),
-            LispType::Float(val) => format!("{}", val),
-            LispType::Symbol(symb) => symb.to_string(),
-            LispType::Cons(list) => list.show(),
-            LispType::BinOp(op) => format!("{}", op.to_string()),
-            LispType::BinPred(pred) => format!("{}", pred.to_string()),
-            LispType::SpecForm(sf) => format!("{}", sf.to_string()),
+            LispType::Bool(boolean) => f.write_str(if *boolean { "#t" } else { "#f" }),
+            LispType::String(s) if repr.readable => write_escaped(f, s),
+            LispType::String(s) => f.write_str(s),
+            LispType::Char(ch) if repr.readable => write_char_literal(f, *ch),
+            LispType::Char(ch) => f.write_char(*ch),
+            LispType::Integer(val) => write!(f, "{}", val),
+            LispType::Float(val) if val.is_finite() && val.fract() == 0.0 => {
+                write!(f, "{:.1}", val)
+            }
+            LispType::Float(val) => write!(f, "{}", val),
+            LispType::Symbol(symb) => f.write_str(symb),
+            LispType::Cons(list) => list.write_repr(f, repr),
+            LispType::HashMap(map) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(' ')?;
+                    }
+                    key.write_repr(f, repr)?;
+                    f.write_char(' ')?;
+                    value.write_repr(f, repr)?;
+                }
+                f.write_char('}')
+            }
+            LispType::Vector(vector) => {
+                f.write_char('[')?;
+                for (i, item) in vector.items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(' ')?;
+                    }
+                    item.write_repr(f, repr)?;
+                }
+                f.write_char(']')
+            }
+            LispType::Set(set) => {
+                f.write_str("#{")?;
+                for (i, item) in set.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(' ')?;
+                    }
+                    item.write_repr(f, repr)?;
+                }
+                f.write_char('}')
+            }
+            // Not readable: regexes are built with `(regex "...")`.
+            LispType::Regex(regex) => {
+                f.write_str("#<regex ")?;
+                write_escaped(f, regex.as_str())?;
+                f.write_char('>')
+            }
+            // Shows the parameter list the lambda was written with.
+            LispType::Lambda(lambda) => match (&lambda.params[..], &lambda.rest) {
+                ([], Some(rest)) => write!(f, "#<lambda {}>", rest),
+                (params, rest) => {
+                    f.write_str("#<lambda (")?;
+                    for (i, param) in params.iter().enumerate() {
+                        if i > 0 {
+                            f.write_char(' ')?;
+                        }
+                        param.write_repr(f, repr)?;
+                    }
+                    if let Some(rest) = rest {
+                        write!(f, " . {}", rest)?;
+                    }
+                    f.write_str(")>")
+                }
+            },
+            LispType::Macro(_) => f.write_str("#<macro>"),
+            // `#S(point x 1 y 2)`, as Common Lisp prints structures, which
+            // reads back as a record of the type then defined as `point`.
+            LispType::Record(record) => {
+                if let Some(text) = repr
+                    .records
+                    .and_then(|shown| shown.get(&Rc::as_ptr(record)))
+                {
+                    return f.write_str(text);
+                }
+                write!(f, "#S({}", record.rtype.name)?;
+                for (field, value) in record.rtype.fields.iter().zip(&record.values) {
+                    write!(f, " {} ", field)?;
+                    value.write_repr(f, repr)?;
+                }
+                f.write_char(')')
+            }
+            LispType::RecordProc(proc) => write!(f, "#<procedure {}>", proc.name),
+            LispType::Generic(generic) => write!(f, "#<generic {}>", generic.name),
+            LispType::NextMethod(next) => write!(f, "#<next-method {}>", next.generic.name),
+            LispType::BinOp(op) => f.write_str(op.to_string()),
+            LispType::BinPred(pred) => f.write_str(pred.to_string()),
+            LispType::SpecForm(sf) => f.write_str(sf.to_string()),
+            LispType::Builtin(builtin) => f.write_str(builtin.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for LispType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_repr(f, Repr::READABLE)
+    }
+}
+
+/// How `write_repr` writes a value.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Repr<'a> {
+    /// Whether strings and characters are quoted and escaped so that they
+    /// can be read back.
+    pub readable: bool,
+    /// The text of records whose type implements `show`, written in place
+    /// of `#S(...)`; see `Evaluator::show_value`.
+    pub records: Option<&'a HashMap<*const Record, String>>,
+}
+
+impl Repr<'_> {
+    pub const READABLE: Repr<'static> = Repr {
+        readable: true,
+        records: None,
+    };
+}
+
+/// Structural equality, as used by `equal?`. Values of different types are
+/// never equal, so `1` and `1.0` differ.
+///
+/// Float policy: floats compare by bit pattern after mapping `-0.0` to `0.0`
+/// and every NaN to a single canonical NaN. This makes NaN equal to itself,
+/// so the relation is a true equivalence and `LispType` can implement `Eq`
+/// and `Hash` and be used as a map key.
+impl PartialEq for LispType {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LispType::String(a), LispType::String(b)) => a == b,
+            (LispType::Integer(a), LispType::Integer(b)) => a == b,
+            (LispType::Float(a), LispType::Float(b)) => float_bits(*a) == float_bits(*b),
+            (LispType::Bool(a), LispType::Bool(b)) => a == b,
+            (LispType::Char(a), LispType::Char(b)) => a == b,
+            (LispType::Symbol(a), LispType::Symbol(b)) => a == b,
+            (LispType::Cons(a), LispType::Cons(b)) => Rc::ptr_eq(a, b) || a == b,
+            (LispType::HashMap(a), LispType::HashMap(b)) => Rc::ptr_eq(a, b) || a == b,
+            (LispType::Vector(a), LispType::Vector(b)) => {
+                Rc::ptr_eq(a, b) || *a.items.borrow() == *b.items.borrow()
+            }
+            (LispType::Set(a), LispType::Set(b)) => Rc::ptr_eq(a, b) || a == b,
+            (LispType::Regex(a), LispType::Regex(b)) => a.as_str() == b.as_str(),
+            // Functions cannot be compared structurally.
+            (LispType::Lambda(a), LispType::Lambda(b)) => Rc::ptr_eq(a, b),
+            (LispType::Macro(a), LispType::Macro(b)) => Rc::ptr_eq(a, b),
+            // Records of the same type compare field by field.
+            (LispType::Record(a), LispType::Record(b)) => {
+                Rc::ptr_eq(a, b) || (Rc::ptr_eq(&a.rtype, &b.rtype) && a.values == b.values)
+            }
+            (LispType::RecordProc(a), LispType::RecordProc(b)) => Rc::ptr_eq(a, b),
+            (LispType::Generic(a), LispType::Generic(b)) => Rc::ptr_eq(a, b),
+            (LispType::NextMethod(a), LispType::NextMethod(b)) => Rc::ptr_eq(a, b),
+            (LispType::SpecForm(a), LispType::SpecForm(b)) => a == b,
+            (LispType::BinPred(a), LispType::BinPred(b)) => a == b,
+            (LispType::BinOp(a), LispType::BinOp(b)) => a == b,
+            (LispType::Builtin(a), LispType::Builtin(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for LispType {}
+
+impl Hash for LispType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            LispType::String(s) | LispType::Symbol(s) => s.hash(state),
+            LispType::Integer(i) => i.hash(state),
+            LispType::Float(f) => float_bits(*f).hash(state),
+            LispType::Bool(b) => b.hash(state),
+            LispType::Char(ch) => ch.hash(state),
+            LispType::Cons(list) => list.hash(state),
+            LispType::HashMap(map) => hash_unordered(map.iter(), state),
+            LispType::Set(set) => hash_unordered(set.iter(), state),
+            LispType::Regex(regex) => regex.as_str().hash(state),
+            LispType::Lambda(lambda) => Rc::as_ptr(lambda).hash(state),
+            LispType::Macro(mac) => Rc::as_ptr(mac).hash(state),
+            LispType::Record(record) => {
+                Rc::as_ptr(&record.rtype).hash(state);
+                record.values.hash(state);
+            }
+            LispType::RecordProc(proc) => Rc::as_ptr(proc).hash(state),
+            LispType::Generic(generic) => Rc::as_ptr(generic).hash(state),
+            LispType::NextMethod(next) => Rc::as_ptr(next).hash(state),
+            // Hashing a mutable vector is only meaningful while it is unchanged.
+            LispType::Vector(vector) => vector.items.borrow().hash(state),
+            LispType::SpecForm(sf) => sf.hash(state),
+            LispType::BinPred(pred) => pred.hash(state),
+            LispType::BinOp(op) => op.hash(state),
+            LispType::Builtin(builtin) => builtin.hash(state),
+        }
+    }
+}
+
+/// Hashes the items of a map or set. They are combined commutatively, since
+/// iteration order is not part of a collection's identity.
+fn hash_unordered<T: Hash, H: Hasher>(items: impl ExactSizeIterator<Item = T>, state: &mut H) {
+    items.len().hash(state);
+    items
+        .fold(0u64, |acc, item| {
+            let mut hasher = DefaultHasher::new();
+            item.hash(&mut hasher);
+            acc.wrapping_add(hasher.finish())
+        })
+        .hash(state);
+}
+
+/// Canonical bits of a float for equality and hashing (see `PartialEq`).
+fn float_bits(f: f64) -> u64 {
+    if f.is_nan() {
+        f64::NAN.to_bits()
+    } else if f == 0.0 {
+        0
+    } else {
+        f.to_bits()
+    }
+}
+
+/// Writes `ch` as a character literal the lexer can read back.
+fn write_char_literal(f: &mut impl fmt::Write, ch: char) -> fmt::Result {
+    if let Some((name, _)) = CHAR_NAMES.iter().find(|(_, c)| *c == ch) {
+        write!(f, "#\\{}", name)
+    } else if ch.is_control() || ch.is_whitespace() {
+        write!(f, "#\\x{:x}", ch as u32)
+    } else {
+        write!(f, "#\\{}", ch)
+    }
+}
+
+/// Writes `s` as a string literal the lexer can read back.
+fn write_escaped(f: &mut impl fmt::Write, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for ch in s.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\r' => f.write_str("\\r")?,
+            _ => f.write_char(ch)?,
         }
     }
+    f.write_char('"')
 }
@@ -1,12 +1,27 @@
 use rs_lisp::evaluator::Evaluator;
+use rs_lisp::lexer::*;
 use rs_lisp::parser::Parser;
-use rs_lisp::{evaluator, lexer::*, lisptype::LispType};
 use std::io::{self, Write};
-use std::{env, fs};
+use std::{env, fs, thread};
+
+/// The REPL runs on a thread with a stack this big, so that Lisp code can
+/// recurse deeply.
+const REPL_STACK_SIZE: usize = 64 << 20;
 
 fn main() {
     env::set_var("RUST_BACKTRACE", "1");
+    thread::Builder::new()
+        .stack_size(REPL_STACK_SIZE)
+        .spawn(repl)
+        .expect("Failed to start the REPL thread!")
+        .join()
+        .expect("The REPL thread panicked!");
+}
+
+fn repl() {
     let mut evaluator = Evaluator::new();
+    // Keep an eighth of the stack for what runs besides nested calls.
+    evaluator.set_stack_limit(REPL_STACK_SIZE / 8 * 7);
 
     loop {
         print!(">>> ");
@@ -25,11 +40,9 @@ fn main() {
             break;
         }
 
-        if input.len() > 3 && input[0..2] == ":l".to_owned() {
+        if input.len() > 3 && &input[0..2] == ":l" {
             println!("Loading from file...");
-            input = fs::read_to_string(input[3..].to_string())
-                .unwrap()
-                .to_string();
+            input = fs::read_to_string(&input[3..]).unwrap().to_string();
         }
 
         let mut lexer = Lexer::new(&input);
@@ -41,15 +54,30 @@ fn main() {
             match lexer.next_token() {
                 Ok(Some(token)) => {
                     match token {
-                        Token::LParen(_, _) => braces.push(token.clone()),
-                        Token::RParen(_, _) => match braces.pop() {
-                            Some(_) => (),
-                            None => {
+                        Token::LParen(_, _)
+                        | Token::LBrace(_, _)
+                        | Token::LSetBrace(_, _)
+                        | Token::LRecordParen(_, _)
+                        | Token::LBracket(_, _) => braces.push(token.clone()),
+                        Token::RParen(_, _) | Token::RBrace(_, _) | Token::RBracket(_, _) => {
+                            let matched = match braces.pop() {
+                                Some(Token::LParen(_, _) | Token::LRecordParen(_, _)) => {
+                                    matches!(token, Token::RParen(_, _))
+                                }
+                                Some(Token::LBrace(_, _) | Token::LSetBrace(_, _)) => {
+                                    matches!(token, Token::RBrace(_, _))
+                                }
+                                Some(Token::LBracket(_, _)) => {
+                                    matches!(token, Token::RBracket(_, _))
+                                }
+                                _ => false,
+                            };
+                            if !matched {
                                 eprintln!("Unmatched brace {:?}", token);
                                 error = true;
                                 break;
                             }
-                        },
+                        }
                         _ => (),
                     };
 
@@ -62,28 +90,41 @@ fn main() {
                 }
             }
         }
-        match braces.pop() {
-            Some(token) => {
-                eprintln!("Unmatched brace {:?}", token);
-                error = true;
-            }
-            None => (),
+        if let Some(token) = braces.pop() {
+            eprintln!("Unmatched brace {:?}", token);
+            error = true;
         }
 
         if error {
             continue;
         }
 
-        let result = Parser::parse(tokens);
+        let result = match Parser::parse(tokens) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Parse error: {:?}", e);
+                continue;
+            }
+        };
 
         println!("{}", result.show());
         println!("{:?}", result);
 
-        let eval_result = evaluator.eval(LispType::Cons(result));
-
-        match eval_result {
-            Ok(result) => println!("{}", result.show()),
-            Err(e) => eprintln!("Evaluation error: {}", e),
+        for form in result.iter() {
+            let result = evaluator.eval(form.clone());
+            for warning in evaluator.take_warnings() {
+                eprintln!("Warning: {}", warning);
+            }
+            match result {
+                Ok(value) => match evaluator.show_value(&value, true) {
+                    Ok(text) => println!("{}", text),
+                    Err(e) => eprintln!("Evaluation error: {}", e),
+                },
+                Err(e) => {
+                    eprintln!("Evaluation error: {}", e);
+                    break;
+                }
+            }
         }
     }
 }
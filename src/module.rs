@@ -0,0 +1,237 @@
+use crate::{
+    env::Env,
+    evaluator::{Evaluator, EvaluatorError},
+    lisptype::LispType,
+};
+use std::rc::Rc;
+
+/// A module as registered by `(module name body...)`. Its body runs the
+/// first time the module is imported or one of its names is referenced, so
+/// modules may be defined in any order.
+pub enum ModuleState {
+    Defined {
+        body: Vec<LispType>,
+        /// The scope the module was defined in, which its own scope extends.
+        env: Rc<Env>,
+    },
+    Ready(Rc<Module>),
+}
+
+/// A module whose body has run.
+#[derive(Debug)]
+pub struct Module {
+    pub name: String,
+    /// The module's own bindings.
+    pub env: Rc<Env>,
+    pub exports: Vec<String>,
+}
+
+/// A module whose body is running, with the names it has exported so far.
+pub struct PendingModule {
+    name: String,
+    exports: Vec<String>,
+}
+
+impl Module {
+    /// The value of an exported name, which must be bound by the module
+    /// itself rather than by a scope around it.
+    pub fn export(&self, name: &str) -> Result<LispType, EvaluatorError> {
+        if !self.exports.iter().any(|export| export == name) {
+            return Err(EvaluatorError::UndefinedSymbol(format!(
+                "{}/{} (not exported by {})",
+                self.name, name, self.name
+            )));
+        }
+        self.env
+            .get_local(name)
+            .ok_or_else(|| EvaluatorError::UndefinedSymbol(format!("{}/{}", self.name, name)))
+    }
+}
+
+impl Evaluator {
+    /// Handles `(module name body...)`, which registers a module whose
+    /// top-level definitions belong to it rather than to the enclosing
+    /// scope. The body names what other code may use with `(export name...)`.
+    pub(crate) fn eval_module(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let Some((LispType::Symbol(name), body)) = args.split_first() else {
+            return Err(EvaluatorError::InvalidArguments(
+                "module requires a name and a body".to_string(),
+            ));
+        };
+        let state = ModuleState::Defined {
+            body: body.to_vec(),
+            env: self.env.clone(),
+        };
+        self.modules.insert(name.clone(), state);
+        Ok(LispType::Symbol(name.clone()))
+    }
+
+    /// Handles `(export name...)`, which is only allowed in the body of a
+    /// module.
+    pub(crate) fn eval_export(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let Some(module) = self.pending_modules.last_mut() else {
+            return Err(EvaluatorError::Other(
+                "export is only allowed in the body of a module".to_string(),
+            ));
+        };
+        for arg in args {
+            match arg {
+                LispType::Symbol(name) => module.exports.push(name.clone()),
+                other => {
+                    return Err(EvaluatorError::TypeMismatch(format!(
+                        "export expects symbols, got {}",
+                        other.show()
+                    )))
+                }
+            }
+        }
+        Ok(LispType::nil())
+    }
+
+    /// Handles `(import set...)`, which binds exported names in the current
+    /// scope and returns them as a list. A set is a module name, or one of
+    /// `(only set name...)`, which keeps the listed names, and
+    /// `(prefix set prefix)`, which prepends `prefix` to every name.
+    pub(crate) fn eval_import(&mut self, args: &[LispType]) -> Result<LispType, EvaluatorError> {
+        let mut imported = Vec::new();
+        for set in args {
+            for (name, value) in self.import_set(set)? {
+                self.env.define(name.clone(), value);
+                imported.push(LispType::Symbol(name));
+            }
+        }
+        Ok(LispType::list(imported))
+    }
+
+    fn import_set(&mut self, set: &LispType) -> Result<Vec<(String, LispType)>, EvaluatorError> {
+        let malformed = || {
+            EvaluatorError::InvalidArguments(format!(
+                "import expects a module name, (only set name...) or (prefix set prefix), got {}",
+                set.show()
+            ))
+        };
+        if let LispType::Symbol(name) = set {
+            let module = self.instantiate_module(name)?;
+            return module
+                .exports
+                .iter()
+                .map(|export| Ok((export.clone(), module.export(export)?)))
+                .collect();
+        }
+
+        let items: Vec<LispType> = match set {
+            LispType::Cons(list) if list.is_proper() => list.iter().cloned().collect(),
+            _ => return Err(malformed()),
+        };
+        match &items[..] {
+            [LispType::Symbol(kind), inner, names @ ..] if kind == "only" => {
+                let bindings = self.import_set(inner)?;
+                names
+                    .iter()
+                    .map(|name| match name {
+                        LispType::Symbol(name) => bindings
+                            .iter()
+                            .find(|(bound, _)| bound == name)
+                            .cloned()
+                            .ok_or_else(|| {
+                                EvaluatorError::UndefinedSymbol(format!(
+                                    "{} (not imported by {})",
+                                    name,
+                                    inner.show()
+                                ))
+                            }),
+                        _ => Err(malformed()),
+                    })
+                    .collect()
+            }
+            [LispType::Symbol(kind), inner, LispType::Symbol(prefix)] if kind == "prefix" => {
+                Ok(self
+                    .import_set(inner)?
+                    .into_iter()
+                    .map(|(name, value)| (format!("{}{}", prefix, name), value))
+                    .collect())
+            }
+            _ => Err(malformed()),
+        }
+    }
+
+    /// The value of a module-qualified symbol such as `mylib/parse`, if
+    /// `mylib` is a module.
+    pub(crate) fn qualified_value(
+        &mut self,
+        symbol: &str,
+    ) -> Option<Result<LispType, EvaluatorError>> {
+        let (module, name) = symbol.split_once('/')?;
+        if name.is_empty() || !self.modules.contains_key(module) {
+            return None;
+        }
+        Some(
+            self.instantiate_module(module)
+                .and_then(|module| module.export(name)),
+        )
+    }
+
+    /// Runs the body of a module unless it has already run. Fails if the
+    /// module is already running, which means that it refers to itself or
+    /// that its imports form a cycle.
+    fn instantiate_module(&mut self, name: &str) -> Result<Rc<Module>, EvaluatorError> {
+        let (body, parent) = match self.modules.get(name) {
+            Some(ModuleState::Ready(module)) => return Ok(module.clone()),
+            Some(ModuleState::Defined { body, env }) => (body.clone(), env.clone()),
+            None => return Err(EvaluatorError::Other(format!("unknown module {}", name))),
+        };
+        if self
+            .pending_modules
+            .last()
+            .is_some_and(|module| module.name == name)
+        {
+            return Err(EvaluatorError::Other(format!(
+                "module {} uses its own exports before its body has run",
+                name
+            )));
+        }
+        if let Some(start) = self
+            .pending_modules
+            .iter()
+            .position(|module| module.name == name)
+        {
+            let mut cycle: Vec<&str> = self.pending_modules[start..]
+                .iter()
+                .map(|module| module.name.as_str())
+                .collect();
+            cycle.push(name);
+            return Err(EvaluatorError::Other(format!(
+                "import cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+
+        let env = Rc::new(Env::with_parent(parent));
+        self.pending_modules.push(PendingModule {
+            name: name.to_string(),
+            exports: Vec::new(),
+        });
+        let result = self.eval_in_env(env.clone(), &body, false);
+        let pending = self.pending_modules.pop().expect("pushed above");
+        result?;
+
+        let module = Rc::new(Module {
+            name: name.to_string(),
+            env,
+            exports: pending.exports,
+        });
+        if let Some(missing) = module
+            .exports
+            .iter()
+            .find(|name| module.env.get_local(name).is_none())
+        {
+            return Err(EvaluatorError::UndefinedSymbol(format!(
+                "{} (exported by {})",
+                missing, name
+            )));
+        }
+        self.modules
+            .insert(name.to_string(), ModuleState::Ready(module.clone()));
+        Ok(module)
+    }
+}
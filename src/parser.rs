@@ -1,10 +1,20 @@
 use super::conslist::ConsList;
-use super::lexer::Token;
-use super::lisptype::LispType;
+use super::lexer::{Lexer, LexerError, Token};
+use super::lisptype::{LispMap, LispType, LispVector};
+use super::record::{Record, RecordType};
 use super::type_enums::*;
 
 use std::rc::Rc;
 
+#[derive(Debug)]
+pub enum ParserError {
+    Lexer(LexerError),
+    UnexpectedToken(Token),
+    OddMapLiteral,
+    MalformedRecordLiteral,
+    UnexpectedEof,
+}
+
 pub struct Parser {}
 
 impl Parser {
@@ -12,15 +22,29 @@ impl Parser {
         match token {
             Token::LParen(_, _) => None,
             Token::RParen(_, _) => None,
+            Token::LBrace(_, _) | Token::LSetBrace(_, _) | Token::RBrace(_, _) => None,
+            Token::LRecordParen(_, _) => None,
+            Token::LBracket(_, _) | Token::RBracket(_, _) => None,
             Token::Float(val) => Some(LispType::Float(val.to_owned())),
             Token::Integer(val) => Some(LispType::Integer(val.to_owned())),
+            Token::Bool(val) => Some(LispType::Bool(*val)),
+            Token::Char(val) => Some(LispType::Char(*val)),
             Token::Comment(_) => None,
-            Token::Symbol(s) => Some(Self::parse_symbol(&s)),
+            Token::Symbol(s) => Some(Self::parse_symbol(s)),
             Token::StringLiteral(s) => Some(LispType::String(s.to_owned())),
+            Token::Dot => None,
+            Token::Quote | Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing => None,
         }
     }
 
-    fn parse_symbol(s: &str) -> LispType {
+    pub(crate) fn parse_symbol(s: &str) -> LispType {
+        match s {
+            "true" => return LispType::Bool(true),
+            "false" => return LispType::Bool(false),
+            "nil" => return LispType::nil(),
+            _ => (),
+        }
+
         if let Some(value) = BinPred::from_str(s) {
             return LispType::BinPred(value);
         }
@@ -33,44 +57,181 @@ impl Parser {
             return LispType::BinOp(value);
         }
 
+        if let Some(value) = Builtin::from_str(s) {
+            return LispType::Builtin(value);
+        }
+
         LispType::Symbol(s.to_string())
     }
 
-    fn parse_list(tokens: &mut std::slice::Iter<Token>) -> Rc<ConsList> {
-        let mut result = Rc::new(ConsList::Nil);
+    /// Maps a reader macro token to the special form it expands to.
+    fn reader_macro(token: &Token) -> Option<SpecForms> {
+        match token {
+            Token::Quote => Some(SpecForms::QUOTE),
+            Token::Quasiquote => Some(SpecForms::QUASIQUOTE),
+            Token::Unquote => Some(SpecForms::UNQUOTE),
+            Token::UnquoteSplicing => Some(SpecForms::UNQUOTESPLICING),
+            _ => None,
+        }
+    }
+
+    /// Parses the datum starting at `token`. Comments yield `None`.
+    fn parse_datum(
+        token: &Token,
+        tokens: &mut std::slice::Iter<Token>,
+    ) -> Result<Option<LispType>, ParserError> {
+        match token {
+            Token::LParen(_, _) => return Ok(Some(Self::parse_list(tokens)?)),
+            Token::LBrace(_, _) => return Ok(Some(Self::parse_map(tokens)?)),
+            Token::LSetBrace(_, _) => {
+                let items = Self::parse_items(tokens, |t| matches!(t, Token::RBrace(_, _)))?;
+                return Ok(Some(LispType::Set(Rc::new(items.into_iter().collect()))));
+            }
+            Token::LRecordParen(_, _) => return Ok(Some(Self::parse_record(tokens)?)),
+            Token::LBracket(_, _) => {
+                let items = Self::parse_items(tokens, |t| matches!(t, Token::RBracket(_, _)))?;
+                return Ok(Some(LispType::Vector(Rc::new(LispVector::new(
+                    items, false,
+                )))));
+            }
+            Token::RParen(_, _) | Token::RBrace(_, _) | Token::RBracket(_, _) | Token::Dot => {
+                return Err(ParserError::UnexpectedToken(token.clone()))
+            }
+            _ => (),
+        }
+
+        if let Some(form) = Self::reader_macro(token) {
+            let datum = Self::next_datum(tokens)?;
+            return Ok(Some(LispType::list(vec![LispType::SpecForm(form), datum])));
+        }
+
+        Ok(Self::parse_token(token))
+    }
+
+    /// Reads the next complete datum, as required after a reader macro.
+    fn next_datum(tokens: &mut std::slice::Iter<Token>) -> Result<LispType, ParserError> {
+        while let Some(token) = tokens.next() {
+            if let Some(value) = Self::parse_datum(token, tokens)? {
+                return Ok(value);
+            }
+        }
+
+        Err(ParserError::UnexpectedEof)
+    }
+
+    /// Parses the rest of a list after its opening paren, including an
+    /// optional dotted tail as in `(a b . c)`.
+    fn parse_list(tokens: &mut std::slice::Iter<Token>) -> Result<LispType, ParserError> {
+        let mut items = Vec::new();
 
         while let Some(token) = tokens.next() {
             match token {
-                Token::LParen(_, _) => {
-                    let sublist = Self::parse_list(tokens);
-                    result = Rc::new(ConsList::Cons(LispType::Cons(sublist), result));
+                Token::RParen(_, _) => return Ok(LispType::list(items)),
+                Token::Dot if !items.is_empty() => {
+                    let tail = Self::next_datum(tokens)?;
+                    return match tokens.find(|t| !matches!(t, Token::Comment(_))) {
+                        Some(Token::RParen(_, _)) => Ok(ConsList::from(items).append(tail)),
+                        Some(other) => Err(ParserError::UnexpectedToken(other.clone())),
+                        None => Err(ParserError::UnexpectedEof),
+                    };
                 }
-                Token::RParen(_, _) => break,
                 _ => {
-                    if let Some(value) = Self::parse_token(&token.clone()) {
-                        result = Rc::new(ConsList::Cons(value, result));
+                    if let Some(value) = Self::parse_datum(token, tokens)? {
+                        items.push(value);
                     }
                 }
             }
         }
 
-        Self::reverse_list(result)
+        Ok(LispType::list(items))
     }
 
-    fn reverse_list(list: Rc<ConsList>) -> Rc<ConsList> {
-        let mut reversed = Rc::new(ConsList::Nil);
-        let mut current = list;
+    /// Parses the rest of a `{key value ...}` literal after its opening brace.
+    fn parse_map(tokens: &mut std::slice::Iter<Token>) -> Result<LispType, ParserError> {
+        let items = Self::parse_items(tokens, |t| matches!(t, Token::RBrace(_, _)))?;
+        if !items.len().is_multiple_of(2) {
+            return Err(ParserError::OddMapLiteral);
+        }
+
+        let mut map = LispMap::default();
+        let mut items = items.into_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            map.insert(key, value);
+        }
+        Ok(LispType::HashMap(Rc::new(map)))
+    }
 
-        while let ConsList::Cons(head, tail) = &*current {
-            reversed = Rc::new(ConsList::Cons(head.clone(), reversed));
-            current = tail.clone();
+    /// Parses the rest of a `#S(type field value ...)` literal after its
+    /// opening `#S(`. The reader does not know record types, so the result
+    /// has a placeholder type that the evaluator replaces by the type
+    /// defined under the same name, see `RecordType::literal`.
+    fn parse_record(tokens: &mut std::slice::Iter<Token>) -> Result<LispType, ParserError> {
+        let items = Self::parse_items(tokens, |t| matches!(t, Token::RParen(_, _)))?;
+        let Some((LispType::Symbol(name), pairs)) = items.split_first() else {
+            return Err(ParserError::MalformedRecordLiteral);
+        };
+        if !pairs.len().is_multiple_of(2) {
+            return Err(ParserError::MalformedRecordLiteral);
         }
 
-        reversed
+        let mut fields: Vec<String> = Vec::new();
+        let mut values = Vec::new();
+        for pair in pairs.chunks(2) {
+            match &pair[0] {
+                LispType::Symbol(field) if !fields.contains(field) => fields.push(field.clone()),
+                _ => return Err(ParserError::MalformedRecordLiteral),
+            }
+            values.push(pair[1].clone());
+        }
+        let rtype = Rc::new(RecordType {
+            name: name.clone(),
+            fields,
+            sum: None,
+            literal: true,
+        });
+        Ok(LispType::Record(Rc::new(Record { rtype, values })))
     }
 
-    pub fn parse(tokens: Vec<Token>) -> Rc<ConsList> {
+    /// Parses data up to and including the token accepted by `is_close`.
+    fn parse_items(
+        tokens: &mut std::slice::Iter<Token>,
+        is_close: fn(&Token) -> bool,
+    ) -> Result<Vec<LispType>, ParserError> {
+        let mut items = Vec::new();
+
+        while let Some(token) = tokens.next() {
+            if is_close(token) {
+                return Ok(items);
+            }
+            if let Some(value) = Self::parse_datum(token, tokens)? {
+                items.push(value);
+            }
+        }
+
+        Err(ParserError::UnexpectedEof)
+    }
+
+    /// Parses a whole program into the list of its top-level forms.
+    pub fn parse(tokens: Vec<Token>) -> Result<Rc<ConsList>, ParserError> {
         let mut token_iter = tokens.iter();
-        Self::parse_list(&mut token_iter)
+        let mut forms = Vec::new();
+
+        while let Some(token) = token_iter.next() {
+            if let Some(value) = Self::parse_datum(token, &mut token_iter)? {
+                forms.push(value);
+            }
+        }
+
+        Ok(Rc::new(ConsList::from(forms)))
+    }
+
+    /// Lexes and parses `src`, returning the list of its top-level forms.
+    pub fn read(src: &str) -> Result<Rc<ConsList>, ParserError> {
+        let mut lexer = Lexer::new(src);
+        let mut tokens = Vec::new();
+        while let Some(token) = lexer.next_token().map_err(ParserError::Lexer)? {
+            tokens.push(token);
+        }
+        Self::parse(tokens)
     }
 }
@@ -0,0 +1,334 @@
+use crate::{
+    conslist::ConsList,
+    evaluator::{Evaluator, EvaluatorError},
+    generic::{Generic, Method, ANY},
+    lisptype::{Lambda, LispType, Repr},
+    record::Record,
+    type_enums::{BinOp, BinPred, SpecForms},
+};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The built-in operations a record type can implement with `extend-type`,
+/// and the number of arguments each takes. `>`, `<=` and `>=` are derived
+/// from `<`, and `!=` from `==`.
+const OPERATIONS: [(&str, usize); 9] = [
+    ("+", 2),
+    ("-", 2),
+    ("*", 2),
+    ("/", 2),
+    ("%", 2),
+    ("<", 2),
+    ("==", 2),
+    ("show", 1),
+    ("seq", 1),
+];
+
+/// Text written for records whose type implements `show`, by address.
+pub type ShownRecords = HashMap<*const Record, String>;
+
+/// What the `show` and `seq` implementations return for the records within
+/// some values, for code that writes or iterates them without an evaluator.
+#[derive(Default)]
+pub struct RecordViews {
+    pub shown: ShownRecords,
+    /// The elements of records whose type implements `seq`, by address.
+    pub items: HashMap<*const Record, LispType>,
+}
+
+impl Evaluator {
+    /// Handles `(extend-type type (operation (params...) body...)...)`,
+    /// which implements built-in operations for the record type `type`:
+    /// the arithmetic operators, `<` for comparisons, `==` for equality,
+    /// `show` for printing and `seq` for iteration, which returns the
+    /// elements as a list or vector. Every builtin that takes a sequence,
+    /// from `map` and `length` to `member`, `string-join` and the `~{` of
+    /// `format`, iterates over those elements. Each implementation is a method of a
+    /// generic function dispatching on its first argument, so it can use
+    /// `call-next-method`. The evaluator consults these only when an operand
+    /// is a record, keeping built-in values fast. When only the second
+    /// operand of a binary operation has an implementation, as in
+    /// `(+ 1 money)`, that one runs, still with the operands in order.
+    ///
+    /// Like `==` on numbers and strings, an implementation of `==` is the
+    /// equality of the `==` and `!=` operators only. `equal?`, and with it
+    /// `member`, `assoc`, `case`, hash map keys and sets, compare records
+    /// field by field, as they must agree with how values are hashed.
+    pub(crate) fn eval_extend_type(
+        &mut self,
+        args: &[LispType],
+    ) -> Result<LispType, EvaluatorError> {
+        let malformed = || {
+            EvaluatorError::InvalidArguments(
+                "extend-type requires a type name and (operation (params...) body...) specs"
+                    .to_string(),
+            )
+        };
+        let Some((type_name, specs)) = args.split_first() else {
+            return Err(malformed());
+        };
+        let type_name = Self::type_name_of(SpecForms::EXTENDTYPE, type_name)?;
+
+        for spec in specs {
+            let items: Vec<LispType> = match spec {
+                LispType::Cons(spec) if spec.is_proper() => spec.iter().cloned().collect(),
+                _ => return Err(malformed()),
+            };
+            let [operation, LispType::Cons(params), body @ ..] = &items[..] else {
+                return Err(malformed());
+            };
+            if body.is_empty() || !params.is_proper() {
+                return Err(malformed());
+            }
+            let operation = match operation {
+                LispType::BinOp(op) => op.to_string(),
+                LispType::BinPred(pred) => pred.to_string(),
+                LispType::Symbol(name) => name.as_str(),
+                _ => "",
+            };
+            let Some(&(operation, arity)) = OPERATIONS.iter().find(|(op, _)| *op == operation)
+            else {
+                let names: Vec<&str> = OPERATIONS.iter().map(|(op, _)| *op).collect();
+                return Err(EvaluatorError::InvalidArguments(format!(
+                    "extend-type: {} is not an operation; expected one of {}",
+                    items[0].show(),
+                    names.join(" ")
+                )));
+            };
+            if params.len() != arity {
+                return Err(EvaluatorError::InvalidArguments(format!(
+                    "extend-type: {} takes {} parameter{}",
+                    operation,
+                    arity,
+                    if arity == 1 { "" } else { "s" }
+                )));
+            }
+            for param in params.iter() {
+                Self::check_pattern(param)?;
+            }
+
+            let mut specializers = vec![type_name.clone()];
+            specializers.resize(arity, ANY.to_string());
+            let generic = self
+                .protocols
+                .entry(operation.to_string())
+                .or_insert_with(|| Rc::new(Generic::new(operation.to_string(), arity)));
+            generic.add_method(Method {
+                specializers,
+                lambda: Lambda {
+                    params: params.iter().cloned().collect(),
+                    rest: None,
+                    body: body.to_vec(),
+                    env: self.env.clone(),
+                },
+            });
+        }
+        Ok(LispType::Symbol(type_name))
+    }
+
+    /// Runs the implementation of a built-in operation for the types of
+    /// `args`, or returns `None` when there is none. A binary operation
+    /// falls back to the implementation for its second operand.
+    fn call_protocol(
+        &mut self,
+        operation: &str,
+        args: Vec<LispType>,
+    ) -> Result<Option<LispType>, EvaluatorError> {
+        let Some(generic) = self.protocols.get(operation).cloned() else {
+            return Ok(None);
+        };
+        let mut methods = generic.applicable(&args);
+        if let ([a, b], true) = (&args[..], methods.is_empty()) {
+            methods = generic.applicable(&[b.clone(), a.clone()]);
+        }
+        if methods.is_empty() {
+            return Ok(None);
+        }
+        self.call_method(&generic, methods, args).map(Some)
+    }
+
+    /// Applies an arithmetic operator, using the `extend-type`
+    /// implementation when an operand is a record.
+    pub(crate) fn apply_binop(
+        &mut self,
+        op: &BinOp,
+        a: LispType,
+        b: LispType,
+    ) -> Result<LispType, EvaluatorError> {
+        if is_record(&a) || is_record(&b) {
+            if let Some(result) = self.call_protocol(op.to_string(), vec![a.clone(), b.clone()])? {
+                return Ok(result);
+            }
+        }
+        Self::apply_bo(op.clone(), a, b).and_then(|opt| {
+            opt.ok_or(EvaluatorError::Other(
+                "BinOp application failed".to_string(),
+            ))
+        })
+    }
+
+    /// Applies a comparison, using the `extend-type` implementation of `<`
+    /// or `==` when an operand is a record.
+    pub(crate) fn apply_binpred(
+        &mut self,
+        pred: &BinPred,
+        a: LispType,
+        b: LispType,
+    ) -> Result<LispType, EvaluatorError> {
+        if is_record(&a) || is_record(&b) {
+            // `a > b` is `b < a`, `a <= b` is `(not (< b a))` and so on.
+            let (operation, swap, negate) = match pred {
+                BinPred::LT => ("<", false, false),
+                BinPred::GT => ("<", true, false),
+                BinPred::LTE => ("<", true, true),
+                BinPred::GTE => ("<", false, true),
+                BinPred::EQ => ("==", false, false),
+                BinPred::NOEQ => ("==", false, true),
+            };
+            let args = match swap {
+                true => vec![b.clone(), a.clone()],
+                false => vec![a.clone(), b.clone()],
+            };
+            if let Some(result) = self.call_protocol(operation, args)? {
+                return Ok(LispType::Bool(result.is_truthy() != negate));
+            }
+        }
+        Self::apply_bp(pred.clone(), &a, &b).and_then(|opt| {
+            opt.map(LispType::Bool).ok_or(EvaluatorError::Other(
+                "BinPred application failed".to_string(),
+            ))
+        })
+    }
+
+    /// Writes `value` like `show`, or like `display` when `readable` is
+    /// false, except that records whose type implements `show` are written
+    /// as the string their implementation returns.
+    pub fn show_value(
+        &mut self,
+        value: &LispType,
+        readable: bool,
+    ) -> Result<String, EvaluatorError> {
+        let shown = self.shown_records(std::slice::from_ref(value))?;
+        Ok(value.repr_string(Repr {
+            readable,
+            records: Some(&shown),
+        }))
+    }
+
+    /// The text of every record within `values` whose type implements
+    /// `show`, for writing them with `Repr::records`.
+    pub(crate) fn shown_records(
+        &mut self,
+        values: &[LispType],
+    ) -> Result<ShownRecords, EvaluatorError> {
+        Ok(self.record_views(values, false)?.shown)
+    }
+
+    /// The text of every record within `values` whose type implements
+    /// `show` and, when `with_items` is true, the elements of every one
+    /// whose type implements `seq`. Elements are searched as well.
+    pub(crate) fn record_views(
+        &mut self,
+        values: &[LispType],
+        with_items: bool,
+    ) -> Result<RecordViews, EvaluatorError> {
+        let mut views = RecordViews::default();
+        let with_items = with_items && self.protocols.contains_key("seq");
+        if with_items || self.protocols.contains_key("show") {
+            for value in values {
+                self.collect_views(value, with_items, &mut views)?;
+            }
+        }
+        Ok(views)
+    }
+
+    fn collect_views(
+        &mut self,
+        value: &LispType,
+        with_items: bool,
+        views: &mut RecordViews,
+    ) -> Result<(), EvaluatorError> {
+        match value {
+            LispType::Record(record) => {
+                let key = Rc::as_ptr(record);
+                if views.shown.contains_key(&key) || views.items.contains_key(&key) {
+                    return Ok(());
+                }
+                match self.call_protocol("show", vec![value.clone()])? {
+                    Some(LispType::String(text)) => {
+                        views.shown.insert(key, text);
+                    }
+                    Some(other) => {
+                        return Err(EvaluatorError::TypeMismatch(format!(
+                            "show of a {} must return a string, got {}",
+                            record.rtype.name,
+                            other.show()
+                        )))
+                    }
+                    None => {
+                        for field in &record.values {
+                            self.collect_views(field, with_items, views)?;
+                        }
+                    }
+                }
+                if with_items {
+                    if let Some(items) = self.call_protocol("seq", vec![value.clone()])? {
+                        views.items.insert(key, items.clone());
+                        self.collect_views(&items, with_items, views)?;
+                    }
+                }
+            }
+            LispType::Cons(list) => {
+                let mut current: &ConsList = list;
+                loop {
+                    match current {
+                        ConsList::Cons(head, tail) => {
+                            self.collect_views(head, with_items, views)?;
+                            current = tail;
+                        }
+                        ConsList::Pair(head, tail) => {
+                            self.collect_views(head, with_items, views)?;
+                            self.collect_views(tail, with_items, views)?;
+                            break;
+                        }
+                        ConsList::Nil => break,
+                    }
+                }
+            }
+            LispType::Vector(vector) => {
+                let items = vector.items.borrow().clone();
+                for item in &items {
+                    self.collect_views(item, with_items, views)?;
+                }
+            }
+            LispType::HashMap(map) => {
+                for (key, value) in map.iter() {
+                    self.collect_views(key, with_items, views)?;
+                    self.collect_views(value, with_items, views)?;
+                }
+            }
+            LispType::Set(set) => {
+                for item in set.iter() {
+                    self.collect_views(item, with_items, views)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The elements of a record whose type implements `seq`, as the list or
+    /// vector its implementation returns; other values are returned as is.
+    pub(crate) fn as_sequence(&mut self, value: LispType) -> Result<LispType, EvaluatorError> {
+        if is_record(&value) {
+            if let Some(seq) = self.call_protocol("seq", vec![value.clone()])? {
+                return Ok(seq);
+            }
+        }
+        Ok(value)
+    }
+}
+
+fn is_record(value: &LispType) -> bool {
+    matches!(value, LispType::Record(_))
+}
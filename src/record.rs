@@ -0,0 +1,134 @@
+use crate::{evaluator::EvaluatorError, lisptype::LispType};
+use std::rc::Rc;
+
+/// A record type made by `defstruct`, `define-record-type` or as a variant
+/// of a `deftype`. Record types are nominal: two definitions with the same
+/// name make distinct types.
+#[derive(Debug)]
+pub struct RecordType {
+    pub name: String,
+    pub fields: Vec<String>,
+    /// The sum type this is a variant of, for the variants of a `deftype`.
+    pub sum: Option<Rc<SumType>>,
+    /// Whether this is the placeholder type of a record read from a
+    /// `#S(...)` literal, which names its fields in any order. Expansion
+    /// replaces such records, see `Evaluator::resolve_literals`.
+    pub literal: bool,
+}
+
+/// A sum type made by `deftype`: a closed set of variants, each of which is
+/// a record type.
+#[derive(Debug)]
+pub struct SumType {
+    pub name: String,
+    pub variants: Vec<String>,
+}
+
+/// An instance of a record type. Records are immutable: updaters return a
+/// modified copy.
+#[derive(Debug)]
+pub struct Record {
+    pub rtype: Rc<RecordType>,
+    pub values: Vec<LispType>,
+}
+
+/// What a generated record procedure does.
+#[derive(Debug)]
+pub enum RecordOp {
+    /// Builds a record from one argument per listed field index; the other
+    /// fields are `nil`.
+    Construct(Rc<RecordType>, Vec<usize>),
+    Predicate(Rc<RecordType>),
+    Get(Rc<RecordType>, usize),
+    /// `(update record value)` returns a copy with the field replaced.
+    Update(Rc<RecordType>, usize),
+    /// Whether a value is a record of any variant of the sum type.
+    SumPredicate(Rc<SumType>),
+}
+
+/// A constructor, predicate, accessor or updater of a record type.
+#[derive(Debug)]
+pub struct RecordProc {
+    pub name: String,
+    pub op: RecordOp,
+}
+
+impl RecordType {
+    /// The index of the field `name`.
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|field| field == name)
+    }
+}
+
+impl RecordProc {
+    pub fn apply(&self, args: Vec<LispType>) -> Result<LispType, EvaluatorError> {
+        let arity = match &self.op {
+            RecordOp::Construct(_, fields) => fields.len(),
+            RecordOp::Predicate(_) | RecordOp::Get(..) | RecordOp::SumPredicate(_) => 1,
+            RecordOp::Update(..) => 2,
+        };
+        if args.len() != arity {
+            return Err(EvaluatorError::InvalidArguments(format!(
+                "{} expects {} argument{}, got {}",
+                self.name,
+                arity,
+                if arity == 1 { "" } else { "s" },
+                args.len()
+            )));
+        }
+
+        let mut args = args.into_iter();
+        match &self.op {
+            RecordOp::Construct(rtype, fields) => {
+                let mut values = vec![LispType::nil(); rtype.fields.len()];
+                for (&index, value) in fields.iter().zip(args) {
+                    values[index] = value;
+                }
+                Ok(LispType::Record(Rc::new(Record {
+                    rtype: rtype.clone(),
+                    values,
+                })))
+            }
+            RecordOp::Predicate(rtype) => Ok(LispType::Bool(matches!(
+                args.next(),
+                Some(LispType::Record(record)) if Rc::ptr_eq(&record.rtype, rtype)
+            ))),
+            RecordOp::SumPredicate(sum) => Ok(LispType::Bool(matches!(
+                args.next(),
+                Some(LispType::Record(record))
+                    if record.rtype.sum.as_ref().is_some_and(|s| Rc::ptr_eq(s, sum))
+            ))),
+            RecordOp::Get(rtype, index) => {
+                let record =
+                    self.expect_record(rtype, args.next().expect("arity checked above"))?;
+                Ok(record.values[*index].clone())
+            }
+            RecordOp::Update(rtype, index) => {
+                let record =
+                    self.expect_record(rtype, args.next().expect("arity checked above"))?;
+                let mut values = record.values.clone();
+                values[*index] = args.next().expect("arity checked above");
+                Ok(LispType::Record(Rc::new(Record {
+                    rtype: record.rtype.clone(),
+                    values,
+                })))
+            }
+        }
+    }
+
+    fn expect_record(
+        &self,
+        rtype: &Rc<RecordType>,
+        value: LispType,
+    ) -> Result<Rc<Record>, EvaluatorError> {
+        match value {
+            LispType::Record(record) if Rc::ptr_eq(&record.rtype, rtype) => Ok(record),
+            other => Err(EvaluatorError::TypeMismatch(format!(
+                "{} expects a {}, got {}",
+                self.name,
+                rtype.name,
+                other.show()
+            ))),
+        }
+    }
+}
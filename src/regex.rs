@@ -0,0 +1,667 @@
+//! A small regular expression engine.
+//!
+//! Patterns are compiled to a program for a Pike VM, which simulates all
+//! alternatives in lockstep: matching takes time linear in the input and
+//! never backtracks, while still tracking capture groups and honouring
+//! leftmost-first (Perl-style) priorities.
+//!
+//! Supported syntax: literals, `.`, classes (`[a-z]`, `[^...]`), the escapes
+//! `\d \w \s \D \W \S \b \B \n \t \r`, anchors `^` and `$`, capturing `(...)`
+//! and non-capturing `(?:...)` groups, alternation `|`, and the quantifiers
+//! `* + ? {n} {n,} {n,m}`, each with a lazy `?` variant.
+
+use std::fmt;
+
+/// Largest count accepted in `{n,m}`, which keeps compiled programs small.
+const MAX_REPEAT: u32 = 1000;
+const MAX_PROGRAM_LEN: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexError {
+    /// Char index in the pattern where the problem was found.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for RegexError {}
+
+/// Byte ranges of a match: index 0 is the whole match, then one entry per
+/// capture group, `None` when the group did not take part.
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+#[derive(Debug)]
+pub struct Regex {
+    pattern: String,
+    program: Vec<Inst>,
+    groups: usize,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, RegexError> {
+        let mut parser = RegexParser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let node = parser.parse_alt()?;
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unmatched )"));
+        }
+
+        let mut compiler = Compiler {
+            program: Vec::new(),
+        };
+        compiler.push(Inst::Save(0));
+        compiler.emit(&node)?;
+        compiler.push(Inst::Save(1));
+        compiler.push(Inst::Match);
+        if compiler.program.len() > MAX_PROGRAM_LEN {
+            return Err(RegexError {
+                position: 0,
+                message: "pattern too large".to_string(),
+            });
+        }
+
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            program: compiler.program,
+            groups: parser.groups,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Number of capture groups, not counting the whole match.
+    pub fn groups(&self) -> usize {
+        self.groups
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.captures_at(text, 0).is_some()
+    }
+
+    /// Finds the leftmost match starting at or after byte offset `start`.
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Captures> {
+        let slot_count = 2 * (self.groups + 1);
+        let mut current = ThreadList::new(self.program.len());
+        let mut next = ThreadList::new(self.program.len());
+        let mut matched = None;
+        let mut pos = start;
+
+        loop {
+            if matched.is_none() {
+                self.add_thread(&mut current, 0, vec![None; slot_count], text, pos);
+            }
+            if current.threads.is_empty() && matched.is_some() {
+                break;
+            }
+
+            let ch = text[pos..].chars().next();
+            let next_pos = pos + ch.map_or(0, char::len_utf8);
+            for (pc, slots) in current.threads.drain(..) {
+                let advance = match &self.program[pc] {
+                    Inst::Char(c) => ch == Some(*c),
+                    Inst::Any => ch.is_some_and(|c| c != '\n'),
+                    Inst::Class(class) => ch.is_some_and(|c| class.matches(c)),
+                    Inst::Match => {
+                        // Threads after this one have lower priority.
+                        matched = Some(slots);
+                        break;
+                    }
+                    _ => unreachable!("epsilon instructions are followed in add_thread"),
+                };
+                if advance {
+                    self.add_thread(&mut next, pc + 1, slots, text, next_pos);
+                }
+            }
+
+            if ch.is_none() {
+                break;
+            }
+            pos = next_pos;
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        matched.map(|slots| {
+            slots
+                .chunks(2)
+                .map(|pair| match (pair[0], pair[1]) {
+                    (Some(start), Some(end)) => Some((start, end)),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    /// Returns the captures of every non-overlapping match, left to right.
+    pub fn captures_all(&self, text: &str) -> Vec<Captures> {
+        let mut result = Vec::new();
+        let mut start = 0;
+        while start <= text.len() {
+            let Some(captures) = self.captures_at(text, start) else {
+                break;
+            };
+            let (match_start, match_end) = captures[0].expect("group 0 always matches");
+            result.push(captures);
+            // After an empty match, step over one char to make progress.
+            start = if match_end > match_start {
+                match_end
+            } else {
+                match text[match_end..].chars().next() {
+                    Some(ch) => match_end + ch.len_utf8(),
+                    None => break,
+                }
+            };
+        }
+        result
+    }
+
+    /// Adds the thread at `pc` to `list`, following jumps, splits, saves and
+    /// assertions so that only char-consuming instructions are queued.
+    fn add_thread(
+        &self,
+        list: &mut ThreadList,
+        pc: usize,
+        slots: Vec<Option<usize>>,
+        text: &str,
+        pos: usize,
+    ) {
+        let mut stack = vec![(pc, slots)];
+        while let Some((pc, mut slots)) = stack.pop() {
+            if list.visited[pc] {
+                continue;
+            }
+            list.visited[pc] = true;
+
+            match &self.program[pc] {
+                Inst::Jmp(target) => stack.push((*target, slots)),
+                Inst::Split(preferred, other) => {
+                    stack.push((*other, slots.clone()));
+                    stack.push((*preferred, slots));
+                }
+                Inst::Save(slot) => {
+                    slots[*slot] = Some(pos);
+                    stack.push((pc + 1, slots));
+                }
+                Inst::Assert(assertion) => {
+                    if assertion.holds(text, pos) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                _ => list.threads.push((pc, slots)),
+            }
+        }
+    }
+}
+
+struct ThreadList {
+    threads: Vec<(usize, Vec<Option<usize>>)>,
+    visited: Vec<bool>,
+}
+
+impl ThreadList {
+    fn new(len: usize) -> Self {
+        ThreadList {
+            threads: Vec::new(),
+            visited: vec![false; len],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.threads.clear();
+        self.visited.iter_mut().for_each(|v| *v = false);
+    }
+}
+
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    /// Try the first target before the second.
+    Split(usize, usize),
+    Jmp(usize),
+    Save(usize),
+    Assert(Assertion),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+impl Assertion {
+    fn holds(self, text: &str, pos: usize) -> bool {
+        let before = text[..pos].chars().next_back().is_some_and(is_word_char);
+        let after = text[pos..].chars().next().is_some_and(is_word_char);
+        match self {
+            Assertion::Start => pos == 0,
+            Assertion::End => pos == text.len(),
+            Assertion::WordBoundary => before != after,
+            Assertion::NotWordBoundary => before == after,
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Range(lo, hi) => *lo <= c && c <= *hi,
+            ClassItem::Digit(negated) => c.is_ascii_digit() != *negated,
+            ClassItem::Word(negated) => is_word_char(c) != *negated,
+            ClassItem::Space(negated) => c.is_whitespace() != *negated,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        self.items.iter().any(|item| item.matches(c)) != self.negated
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+struct RegexParser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl RegexParser {
+    fn error(&self, message: &str) -> RegexError {
+        RegexError {
+            position: self.pos,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alt(branches)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            items.push(self.parse_repeat()?);
+        }
+        Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap(),
+            _ => Node::Concat(items),
+        })
+    }
+
+    fn parse_repeat(&mut self) -> Result<Node, RegexError> {
+        let mut node = self.parse_atom()?;
+        loop {
+            let start = self.pos;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.parse_counts()? {
+                    Some(counts) => counts,
+                    None => break,
+                },
+                _ => break,
+            };
+            if self.pos == start {
+                self.pos += 1;
+            }
+            let greedy = !self.eat('?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+        Ok(node)
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}`. Anything else leaves the position
+    /// untouched and returns `None`, so the brace is read as a literal.
+    fn parse_counts(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let start = self.pos;
+        self.pos += 1;
+        let min = self.parse_number();
+        // `{n,}` is open-ended, `{n}` means exactly n.
+        let max = if self.eat(',') {
+            self.parse_number()
+        } else {
+            min
+        };
+
+        match min {
+            Some(min) if self.eat('}') => {
+                if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+                    return Err(self.error("repetition count too large"));
+                }
+                if max.is_some_and(|max| max < min) {
+                    return Err(self.error("repetition range is reversed"));
+                }
+                Ok(Some((min, max)))
+            }
+            _ => {
+                self.pos = start;
+                Ok(None)
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        if digits.is_empty() {
+            None
+        } else {
+            // Overlong counts saturate and are rejected by the caller.
+            Some(digits.parse().unwrap_or(u32::MAX))
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("unexpected end of pattern"))?;
+        self.pos += 1;
+        match c {
+            '(' => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return Err(self.error("unsupported group syntax"));
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let inner = self.parse_alt()?;
+                if !self.eat(')') {
+                    return Err(self.error("missing )"));
+                }
+                Ok(Node::Group(Box::new(inner), index))
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Assert(Assertion::Start)),
+            '$' => Ok(Node::Assert(Assertion::End)),
+            '*' | '+' | '?' => {
+                self.pos -= 1;
+                Err(self.error("nothing to repeat"))
+            }
+            '\\' => self.parse_escape(),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, RegexError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("pattern ends with a backslash"))?;
+        self.pos += 1;
+        Ok(match c {
+            'b' => Node::Assert(Assertion::WordBoundary),
+            'B' => Node::Assert(Assertion::NotWordBoundary),
+            _ => match self.class_escape(c)? {
+                ClassItem::Range(c, _) => Node::Char(c),
+                item => Node::Class(Class {
+                    items: vec![item],
+                    negated: false,
+                }),
+            },
+        })
+    }
+
+    /// Interprets the char after a backslash, inside or outside a class.
+    fn class_escape(&self, c: char) -> Result<ClassItem, RegexError> {
+        Ok(match c {
+            'd' | 'D' => ClassItem::Digit(c == 'D'),
+            'w' | 'W' => ClassItem::Word(c == 'W'),
+            's' | 'S' => ClassItem::Space(c == 'S'),
+            'n' => ClassItem::Range('\n', '\n'),
+            't' => ClassItem::Range('\t', '\t'),
+            'r' => ClassItem::Range('\r', '\r'),
+            c if c.is_alphanumeric() => {
+                return Err(RegexError {
+                    position: self.pos - 1,
+                    message: format!("unknown escape \\{}", c),
+                })
+            }
+            c => ClassItem::Range(c, c),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Node, RegexError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+
+        loop {
+            let c = self.peek().ok_or_else(|| self.error("missing ]"))?;
+            self.pos += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let item = if c == '\\' {
+                let escaped = self.peek().ok_or_else(|| self.error("missing ]"))?;
+                self.pos += 1;
+                self.class_escape(escaped)?
+            } else {
+                ClassItem::Range(c, c)
+            };
+
+            // A '-' between two single chars forms a range.
+            let item = match item {
+                ClassItem::Range(lo, _)
+                    if self.peek() == Some('-')
+                        && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') =>
+                {
+                    self.pos += 1;
+                    let hi = self.peek().unwrap();
+                    self.pos += 1;
+                    let hi = if hi == '\\' {
+                        match self.peek().map(|c| self.class_escape(c)) {
+                            Some(Ok(ClassItem::Range(hi, _))) => {
+                                self.pos += 1;
+                                hi
+                            }
+                            _ => return Err(self.error("invalid range end")),
+                        }
+                    } else {
+                        hi
+                    };
+                    if hi < lo {
+                        return Err(self.error("class range is reversed"));
+                    }
+                    ClassItem::Range(lo, hi)
+                }
+                item => item,
+            };
+            items.push(item);
+        }
+
+        Ok(Node::Class(Class { items, negated }))
+    }
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.program.push(inst);
+        self.program.len() - 1
+    }
+
+    fn emit(&mut self, node: &Node) -> Result<(), RegexError> {
+        if self.program.len() > MAX_PROGRAM_LEN {
+            return Err(RegexError {
+                position: 0,
+                message: "pattern too large".to_string(),
+            });
+        }
+
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.push(Inst::Char(*c));
+            }
+            Node::Any => {
+                self.push(Inst::Any);
+            }
+            Node::Class(class) => {
+                self.push(Inst::Class(class.clone()));
+            }
+            Node::Assert(assertion) => {
+                self.push(Inst::Assert(*assertion));
+            }
+            Node::Group(inner, None) => self.emit(inner)?,
+            Node::Group(inner, Some(index)) => {
+                self.push(Inst::Save(2 * index));
+                self.emit(inner)?;
+                self.push(Inst::Save(2 * index + 1));
+            }
+            Node::Concat(items) => {
+                for item in items {
+                    self.emit(item)?;
+                }
+            }
+            Node::Alt(branches) => {
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.push(Inst::Split(0, 0));
+                        self.emit(branch)?;
+                        jumps.push(self.push(Inst::Jmp(0)));
+                        let next = self.program.len();
+                        self.program[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.emit(branch)?;
+                    }
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.emit(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.push(Inst::Split(0, 0));
+                        self.emit(node)?;
+                        self.push(Inst::Jmp(split));
+                        let end = self.program.len();
+                        self.program[split] = self.split(*greedy, split + 1, end);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0)));
+                            self.emit(node)?;
+                        }
+                        let end = self.program.len();
+                        for split in splits {
+                            self.program[split] = self.split(*greedy, split + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn split(&self, greedy: bool, body: usize, skip: usize) -> Inst {
+        if greedy {
+            Inst::Split(body, skip)
+        } else {
+            Inst::Split(skip, body)
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use crate::{
+    conslist::ConsList,
+    env::Env,
+    evaluator::EvaluatorError,
+    lisptype::{LispMap, LispType, LispVector},
+    type_enums::SpecForms,
+};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const ELLIPSIS: &str = "...";
+
+/// The rules of a `(syntax-rules (literals...) (pattern template)...)` macro.
+///
+/// Expansion is hygienic by renaming: every symbol a template introduces,
+/// as opposed to one a pattern variable substitutes, is renamed to
+/// `name#N@S` with a number `N` unique to the expansion and the number `S`
+/// the evaluator gave to the scope the macro was defined in. A binding made
+/// by the template therefore cannot capture a variable of the macro's user,
+/// and a binding of the user cannot capture a symbol of the template: an
+/// introduced symbol that the expansion does not bind refers to `name` in
+/// the macro's scope (see [`base_name`] and [`macro_scope`]).
+#[derive(Debug)]
+pub struct SyntaxRules {
+    /// Symbols that match only themselves in patterns.
+    literals: Vec<String>,
+    /// `(pattern template)` pairs, tried in order. Only the part of the
+    /// pattern after the macro keyword is kept.
+    rules: Vec<(LispType, LispType)>,
+    /// The scope the macro was defined in.
+    pub env: Rc<Env>,
+}
+
+/// What a pattern variable matched: a form, or one match per repetition
+/// when the variable appears under an ellipsis.
+#[derive(Clone)]
+enum Matched {
+    One(LispType),
+    Many(Vec<Matched>),
+}
+
+type Bindings = HashMap<String, Matched>;
+
+/// The name a renamed symbol was written with: `tmp` for `tmp#3@0`. Other
+/// symbols, including the `#:g1` ones of `gensym`, are returned unchanged.
+pub fn base_name(name: &str) -> &str {
+    match name.split_once('#') {
+        Some((base, _)) if !base.is_empty() => base,
+        _ => name,
+    }
+}
+
+/// The number of the scope whose macro renamed a symbol: `0` for `tmp#3@0`.
+pub fn macro_scope(name: &str) -> Option<usize> {
+    match name.split_once('#') {
+        Some((base, stamp)) if !base.is_empty() => stamp.split_once('@')?.1.parse().ok(),
+        _ => None,
+    }
+}
+
+fn rename(name: &str, stamp: &str) -> String {
+    format!("{}#{}", name, stamp)
+}
+
+fn is_ellipsis(form: &LispType) -> bool {
+    matches!(form, LispType::Symbol(s) if s == ELLIPSIS)
+}
+
+/// The elements of a possibly dotted list and its final cdr (`nil` for a
+/// proper list).
+fn split_list(list: &ConsList) -> (Vec<LispType>, LispType) {
+    let mut items = Vec::new();
+    let mut current = list;
+    loop {
+        match current {
+            ConsList::Cons(head, rest) => {
+                items.push(head.clone());
+                current = rest;
+            }
+            ConsList::Pair(head, tail) => {
+                items.push(head.clone());
+                return (items, tail.clone());
+            }
+            ConsList::Nil => return (items, LispType::nil()),
+        }
+    }
+}
+
+fn malformed(message: String) -> EvaluatorError {
+    EvaluatorError::InvalidArguments(format!("syntax-rules: {}", message))
+}
+
+impl SyntaxRules {
+    /// Builds the rules from the unevaluated arguments of `syntax-rules`.
+    pub fn new(
+        literals: &LispType,
+        rules: &[LispType],
+        env: Rc<Env>,
+    ) -> Result<Self, EvaluatorError> {
+        let literals = match literals {
+            LispType::Cons(list) if list.is_proper() => list
+                .iter()
+                .map(|literal| match literal {
+                    LispType::Symbol(s) => Ok(s.clone()),
+                    other => Err(malformed(format!(
+                        "literals must be symbols, got {}",
+                        other.show()
+                    ))),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            other => {
+                return Err(malformed(format!(
+                    "expected a list of literals, got {}",
+                    other.show()
+                )))
+            }
+        };
+
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let parts: Vec<&LispType> = match rule {
+                    LispType::Cons(list) if list.is_proper() => list.iter().collect(),
+                    _ => Vec::new(),
+                };
+                match parts[..] {
+                    [LispType::Cons(pattern), template] if !pattern.is_empty() => {
+                        let args = pattern.cdr().expect("checked non-empty above");
+                        check_pattern(&args)?;
+                        Ok((args, template.clone()))
+                    }
+                    _ => Err(malformed(format!(
+                        "rules must be ((keyword pattern...) template), got {}",
+                        rule.show()
+                    ))),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(SyntaxRules {
+            literals,
+            rules,
+            env,
+        })
+    }
+
+    /// Expands the macro call `form` with the first rule that matches it.
+    /// `stamp` must be unique to this expansion and `scope` is the number of
+    /// the macro's scope; both go into the renamed symbols.
+    pub fn expand(
+        &self,
+        form: &LispType,
+        stamp: usize,
+        scope: usize,
+    ) -> Result<LispType, EvaluatorError> {
+        let stamp = format!("{}@{}", stamp, scope);
+        let args = match form {
+            LispType::Cons(list) => list.cdr(),
+            _ => None,
+        };
+        if let Some(args) = args {
+            for (pattern, template) in &self.rules {
+                let mut bindings = Bindings::new();
+                if self.match_pattern(pattern, &args, &mut bindings) {
+                    return self.expand_template(template, &bindings, &stamp, false);
+                }
+            }
+        }
+        Err(EvaluatorError::InvalidArguments(format!(
+            "no syntax-rules pattern matches {}",
+            form.show()
+        )))
+    }
+
+    fn match_pattern(&self, pattern: &LispType, form: &LispType, bindings: &mut Bindings) -> bool {
+        match pattern {
+            LispType::Symbol(name) if self.literals.contains(name) => {
+                matches!(form, LispType::Symbol(s) if base_name(s) == name)
+            }
+            LispType::Symbol(name) if name == "_" => true,
+            LispType::Symbol(name) => {
+                bindings.insert(name.clone(), Matched::One(form.clone()));
+                true
+            }
+            LispType::Cons(pattern) => {
+                let LispType::Cons(form) = form else {
+                    return false;
+                };
+                let (items, tail) = split_list(pattern);
+                let (form_items, form_tail) = split_list(form);
+                self.match_sequence(&items, &tail, &form_items, &form_tail, bindings)
+            }
+            LispType::Vector(pattern) => {
+                let LispType::Vector(form) = form else {
+                    return false;
+                };
+                let nil = LispType::nil();
+                let items = pattern.items.borrow().clone();
+                let form_items = form.items.borrow().clone();
+                self.match_sequence(&items, &nil, &form_items, &nil, bindings)
+            }
+            _ => pattern == form,
+        }
+    }
+
+    /// Matches the elements and tail of a list pattern, where one element
+    /// may be followed by `...` to match any number of forms.
+    fn match_sequence(
+        &self,
+        items: &[LispType],
+        tail: &LispType,
+        forms: &[LispType],
+        form_tail: &LispType,
+        bindings: &mut Bindings,
+    ) -> bool {
+        let Some(ellipsis) = items.iter().position(is_ellipsis) else {
+            if tail.is_nil() {
+                return forms.len() == items.len()
+                    && form_tail.is_nil()
+                    && self.match_all(items, forms, bindings);
+            }
+            if forms.len() < items.len() {
+                return false;
+            }
+            let (head, rest) = forms.split_at(items.len());
+            let rest = ConsList::from(rest.to_vec()).append(form_tail.clone());
+            return self.match_all(items, head, bindings)
+                && self.match_pattern(tail, &rest, bindings);
+        };
+
+        let (before, repeated, after) = (
+            &items[..ellipsis - 1],
+            &items[ellipsis - 1],
+            &items[ellipsis + 1..],
+        );
+        if forms.len() < before.len() + after.len() {
+            return false;
+        }
+        let after_start = forms.len() - after.len();
+        if !self.match_all(before, &forms[..before.len()], bindings)
+            || !self.match_all(after, &forms[after_start..], bindings)
+        {
+            return false;
+        }
+
+        let mut repetitions = Vec::new();
+        for form in &forms[before.len()..after_start] {
+            let mut inner = Bindings::new();
+            if !self.match_pattern(repeated, form, &mut inner) {
+                return false;
+            }
+            repetitions.push(inner);
+        }
+        let mut vars = Vec::new();
+        self.pattern_vars(repeated, &mut vars);
+        for var in vars {
+            let matches = repetitions
+                .iter_mut()
+                .map(|inner| inner.remove(&var).expect("every repetition binds it"))
+                .collect();
+            bindings.insert(var, Matched::Many(matches));
+        }
+
+        if tail.is_nil() {
+            form_tail.is_nil()
+        } else {
+            self.match_pattern(tail, form_tail, bindings)
+        }
+    }
+
+    fn match_all(
+        &self,
+        patterns: &[LispType],
+        forms: &[LispType],
+        bindings: &mut Bindings,
+    ) -> bool {
+        patterns
+            .iter()
+            .zip(forms)
+            .all(|(pattern, form)| self.match_pattern(pattern, form, bindings))
+    }
+
+    /// The pattern variables of `pattern`, in order.
+    fn pattern_vars(&self, pattern: &LispType, vars: &mut Vec<String>) {
+        match pattern {
+            LispType::Symbol(name)
+                if name != "_" && name != ELLIPSIS && !self.literals.contains(name) =>
+            {
+                vars.push(name.clone())
+            }
+            LispType::Cons(list) if !list.is_empty() => {
+                let (items, tail) = split_list(list);
+                for item in items.iter().chain([&tail]) {
+                    self.pattern_vars(item, vars);
+                }
+            }
+            LispType::Vector(vector) => {
+                for item in vector.items.borrow().iter() {
+                    self.pattern_vars(item, vars);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Substitutes the pattern variables of `template` and renames the
+    /// symbols it introduces, except in quoted data.
+    fn expand_template(
+        &self,
+        template: &LispType,
+        bindings: &Bindings,
+        stamp: &str,
+        quoted: bool,
+    ) -> Result<LispType, EvaluatorError> {
+        match template {
+            LispType::Symbol(name) => match bindings.get(name) {
+                Some(Matched::One(form)) => Ok(form.clone()),
+                Some(Matched::Many(_)) => Err(malformed(format!(
+                    "pattern variable {} must be followed by ... in the template",
+                    name
+                ))),
+                None if quoted => Ok(template.clone()),
+                None => Ok(LispType::Symbol(rename(name, stamp))),
+            },
+            LispType::Cons(list) if !list.is_empty() => {
+                let quoted = match list.car() {
+                    Some(LispType::SpecForm(SpecForms::QUOTE | SpecForms::QUASIQUOTE)) => true,
+                    Some(LispType::SpecForm(SpecForms::UNQUOTE | SpecForms::UNQUOTESPLICING)) => {
+                        false
+                    }
+                    _ => quoted,
+                };
+                let (items, tail) = split_list(list);
+                let items = self.expand_items(&items, bindings, stamp, quoted)?;
+                let tail = self.expand_template(&tail, bindings, stamp, quoted)?;
+                Ok(ConsList::from(items).append(tail))
+            }
+            LispType::Vector(vector) => {
+                let items = vector.items.borrow().clone();
+                let items = self.expand_items(&items, bindings, stamp, quoted)?;
+                Ok(LispType::Vector(Rc::new(LispVector::new(items, false))))
+            }
+            LispType::HashMap(map) => {
+                let mut result = LispMap::default();
+                for (key, value) in map.iter() {
+                    result.insert(
+                        self.expand_template(key, bindings, stamp, quoted)?,
+                        self.expand_template(value, bindings, stamp, quoted)?,
+                    );
+                }
+                Ok(LispType::HashMap(Rc::new(result)))
+            }
+            _ => Ok(template.clone()),
+        }
+    }
+
+    /// Expands the elements of a list or vector template. An element
+    /// followed by `...` is expanded once per repetition of the pattern
+    /// variables under an ellipsis that it contains.
+    fn expand_items(
+        &self,
+        items: &[LispType],
+        bindings: &Bindings,
+        stamp: &str,
+        quoted: bool,
+    ) -> Result<Vec<LispType>, EvaluatorError> {
+        let mut result = Vec::new();
+        let mut i = 0;
+        while i < items.len() {
+            let item = &items[i];
+            if is_ellipsis(item) {
+                return Err(malformed("... must follow a template".to_string()));
+            }
+            if !items.get(i + 1).is_some_and(is_ellipsis) {
+                result.push(self.expand_template(item, bindings, stamp, quoted)?);
+                i += 1;
+                continue;
+            }
+
+            let mut vars = Vec::new();
+            self.pattern_vars(item, &mut vars);
+            let repeated: Vec<(String, &Vec<Matched>)> = vars
+                .into_iter()
+                .filter_map(|var| match bindings.get(&var) {
+                    Some(Matched::Many(matches)) => Some((var, matches)),
+                    _ => None,
+                })
+                .collect();
+            let Some((_, first)) = repeated.first() else {
+                return Err(malformed(format!(
+                    "{} ... contains no pattern variable matched under an ellipsis",
+                    item.show()
+                )));
+            };
+            let count = first.len();
+            if repeated.iter().any(|(_, matches)| matches.len() != count) {
+                return Err(malformed(format!(
+                    "pattern variables in {} ... matched different numbers of forms",
+                    item.show()
+                )));
+            }
+            for k in 0..count {
+                let mut inner = bindings.clone();
+                for (var, matches) in &repeated {
+                    inner.insert(var.clone(), matches[k].clone());
+                }
+                result.push(self.expand_template(item, &inner, stamp, quoted)?);
+            }
+            i += 2;
+        }
+        Ok(result)
+    }
+}
+
+/// Checks where the ellipses of a pattern are: at most one per list, and
+/// never first.
+fn check_pattern(pattern: &LispType) -> Result<(), EvaluatorError> {
+    let items = match pattern {
+        LispType::Cons(list) if !list.is_empty() => {
+            let (items, tail) = split_list(list);
+            check_pattern(&tail)?;
+            items
+        }
+        LispType::Vector(vector) => vector.items.borrow().clone(),
+        _ => return Ok(()),
+    };
+    let ellipses: Vec<usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| is_ellipsis(item))
+        .map(|(i, _)| i)
+        .collect();
+    if ellipses.len() > 1 || ellipses.first() == Some(&0) {
+        return Err(malformed(format!(
+            "misplaced ... in pattern {}",
+            pattern.show()
+        )));
+    }
+    items.iter().try_for_each(check_pattern)
+}
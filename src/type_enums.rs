@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BinOp {
     ADD,
     SUB,
@@ -8,7 +8,7 @@ pub enum BinOp {
     SCONCAT,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BinPred {
     GT,
     GTE,
@@ -18,16 +18,16 @@ pub enum BinPred {
     NOEQ,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SpecForms {
     DEF,
     SET,
     GET,
     QUOTE,
+    QUASIQUOTE,
+    UNQUOTE,
+    UNQUOTESPLICING,
     TYPEOF,
-    CONS,
-    CAR,
-    CDR,
     COND,
     PRINT,
     READ,
@@ -37,8 +37,136 @@ pub enum SpecForms {
     MACRO,
     MACROEXPAND,
     DO,
+    LET,
+    LETSTAR,
+    LETREC,
+    WHILE,
+    LOOP,
+    RECUR,
+    DOTIMES,
+    DOLIST,
+    IF,
+    WHEN,
+    UNLESS,
+    AND,
+    OR,
+    CASE,
+    MATCH,
+    DEFINESYNTAX,
+    SYNTAXRULES,
+    DEFSTRUCT,
+    DEFINERECORDTYPE,
+    DEFTYPE,
+    DEFGENERIC,
+    DEFMETHOD,
+    EXTENDTYPE,
+    MODULE,
+    EXPORT,
+    IMPORT,
+}
+
+/// Native functions: unlike special forms, all their arguments are evaluated.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    CONS,
+    CAR,
+    CDR,
+    LIST,
+    LISTP,
+    PAIRP,
+    EQP,
+    EQVP,
+    EQUALP,
+    HASHMAP,
+    HASHGET,
+    HASHSET,
+    HASHREMOVE,
+    HASHCONTAINSP,
+    HASHCOUNT,
+    HASHKEYS,
+    HASHVALUES,
+    HASHMERGE,
+    HASHTOLIST,
+    VECTOR,
+    MAKEVECTOR,
+    VECTORP,
+    VECTORREF,
+    VECTORSET,
+    VECTORLENGTH,
+    VECTORSLICE,
+    VECTORTOLIST,
+    LISTTOVECTOR,
+    LENGTH,
+    SET,
+    SETP,
+    SETADD,
+    SETREMOVE,
+    MEMBERP,
+    UNION,
+    INTERSECTION,
+    DIFFERENCE,
+    SUBSETP,
+    SETTOLIST,
+    CHARP,
+    CHARTOINTEGER,
+    INTEGERTOCHAR,
+    CHARUPCASE,
+    CHARDOWNCASE,
+    CHARALPHABETICP,
+    CHARNUMERICP,
+    CHARWHITESPACEP,
+    CHARUPPERCASEP,
+    CHARLOWERCASEP,
+    STRINGREF,
+    STRINGLENGTH,
+    SUBSTRING,
+    STRINGINDEX,
+    STRINGSPLIT,
+    STRINGJOIN,
+    STRINGTRIM,
+    STRINGUPCASE,
+    STRINGDOWNCASE,
+    STRINGREPLACE,
+    STRINGCONTAINSP,
+    STRINGSTARTSWITHP,
+    STRINGENDSWITHP,
+    STRINGTONUMBER,
+    NUMBERTOSTRING,
+    STRINGTOSYMBOL,
+    SYMBOLTOSTRING,
+    STRINGTOLIST,
+    LISTTOSTRING,
+    FORMAT,
+    REGEX,
+    REGEXP,
+    REGEXMATCH,
+    REGEXFINDALL,
+    REGEXREPLACE,
+    REGEXSPLIT,
+    MAP,
+    FOREACH,
+    FILTER,
+    REMOVE,
+    REDUCE,
+    FOLDLEFT,
+    FOLDRIGHT,
+    APPLY,
+    APPEND,
+    REVERSE,
+    NTH,
+    LAST,
+    TAKE,
+    DROP,
+    ZIP,
+    RANGE,
+    ASSOC,
+    MEMBER,
+    SORT,
+    NOT,
+    GENSYM,
 }
 
+#[allow(clippy::should_implement_trait)]
 impl BinOp {
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
@@ -64,6 +192,7 @@ impl BinOp {
     }
 }
 
+#[allow(clippy::should_implement_trait)]
 impl BinPred {
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
@@ -89,17 +218,18 @@ impl BinPred {
     }
 }
 
+#[allow(clippy::should_implement_trait)]
 impl SpecForms {
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
             "def" => Some(Self::DEF),
             "set!" => Some(Self::SET),
             "get" => Some(Self::GET),
-            "'" => Some(Self::QUOTE),
+            "quote" => Some(Self::QUOTE),
+            "quasiquote" => Some(Self::QUASIQUOTE),
+            "unquote" => Some(Self::UNQUOTE),
+            "unquote-splicing" => Some(Self::UNQUOTESPLICING),
             "typeof" => Some(Self::TYPEOF),
-            "cons" => Some(Self::CONS),
-            "car" => Some(Self::CAR),
-            "cdr" => Some(Self::CDR),
             "cond" => Some(Self::COND),
             "print" => Some(Self::PRINT),
             "read" => Some(Self::READ),
@@ -109,6 +239,32 @@ impl SpecForms {
             "macro" => Some(Self::MACRO),
             "macroexpand" => Some(Self::MACROEXPAND),
             "do" => Some(Self::DO),
+            "let" => Some(Self::LET),
+            "let*" => Some(Self::LETSTAR),
+            "letrec" => Some(Self::LETREC),
+            "while" => Some(Self::WHILE),
+            "loop" => Some(Self::LOOP),
+            "recur" => Some(Self::RECUR),
+            "dotimes" => Some(Self::DOTIMES),
+            "dolist" => Some(Self::DOLIST),
+            "if" => Some(Self::IF),
+            "when" => Some(Self::WHEN),
+            "unless" => Some(Self::UNLESS),
+            "and" => Some(Self::AND),
+            "or" => Some(Self::OR),
+            "case" => Some(Self::CASE),
+            "match" => Some(Self::MATCH),
+            "define-syntax" => Some(Self::DEFINESYNTAX),
+            "syntax-rules" => Some(Self::SYNTAXRULES),
+            "defstruct" => Some(Self::DEFSTRUCT),
+            "define-record-type" => Some(Self::DEFINERECORDTYPE),
+            "deftype" => Some(Self::DEFTYPE),
+            "defgeneric" => Some(Self::DEFGENERIC),
+            "defmethod" => Some(Self::DEFMETHOD),
+            "extend-type" => Some(Self::EXTENDTYPE),
+            "module" => Some(Self::MODULE),
+            "export" => Some(Self::EXPORT),
+            "import" => Some(Self::IMPORT),
             _ => None,
         }
     }
@@ -119,10 +275,10 @@ impl SpecForms {
             Self::SET => "set!",
             Self::GET => "get",
             Self::QUOTE => "quote",
+            Self::QUASIQUOTE => "quasiquote",
+            Self::UNQUOTE => "unquote",
+            Self::UNQUOTESPLICING => "unquote-splicing",
             Self::TYPEOF => "typeof",
-            Self::CONS => "cons",
-            Self::CAR => "car",
-            Self::CDR => "cdr",
             Self::COND => "cond",
             Self::PRINT => "print",
             Self::READ => "read",
@@ -132,6 +288,238 @@ impl SpecForms {
             Self::MACRO => "macro",
             Self::MACROEXPAND => "macroexpand",
             Self::DO => "do",
+            Self::LET => "let",
+            Self::LETSTAR => "let*",
+            Self::LETREC => "letrec",
+            Self::WHILE => "while",
+            Self::LOOP => "loop",
+            Self::RECUR => "recur",
+            Self::DOTIMES => "dotimes",
+            Self::DOLIST => "dolist",
+            Self::IF => "if",
+            Self::WHEN => "when",
+            Self::UNLESS => "unless",
+            Self::AND => "and",
+            Self::OR => "or",
+            Self::CASE => "case",
+            Self::MATCH => "match",
+            Self::DEFINESYNTAX => "define-syntax",
+            Self::SYNTAXRULES => "syntax-rules",
+            Self::DEFSTRUCT => "defstruct",
+            Self::DEFINERECORDTYPE => "define-record-type",
+            Self::DEFTYPE => "deftype",
+            Self::DEFGENERIC => "defgeneric",
+            Self::DEFMETHOD => "defmethod",
+            Self::EXTENDTYPE => "extend-type",
+            Self::MODULE => "module",
+            Self::EXPORT => "export",
+            Self::IMPORT => "import",
+        }
+    }
+}
+
+#[allow(clippy::should_implement_trait)]
+impl Builtin {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "cons" => Some(Self::CONS),
+            "car" => Some(Self::CAR),
+            "cdr" => Some(Self::CDR),
+            "list" => Some(Self::LIST),
+            "list?" => Some(Self::LISTP),
+            "pair?" => Some(Self::PAIRP),
+            "eq?" => Some(Self::EQP),
+            "eqv?" => Some(Self::EQVP),
+            "equal?" => Some(Self::EQUALP),
+            "hash-map" => Some(Self::HASHMAP),
+            "hash-get" => Some(Self::HASHGET),
+            "hash-set" => Some(Self::HASHSET),
+            "hash-remove" => Some(Self::HASHREMOVE),
+            "hash-contains?" => Some(Self::HASHCONTAINSP),
+            "hash-count" => Some(Self::HASHCOUNT),
+            "hash-keys" => Some(Self::HASHKEYS),
+            "hash-values" => Some(Self::HASHVALUES),
+            "hash-merge" => Some(Self::HASHMERGE),
+            "hash->list" => Some(Self::HASHTOLIST),
+            "vector" => Some(Self::VECTOR),
+            "make-vector" => Some(Self::MAKEVECTOR),
+            "vector?" => Some(Self::VECTORP),
+            "vector-ref" => Some(Self::VECTORREF),
+            "vector-set!" => Some(Self::VECTORSET),
+            "vector-length" => Some(Self::VECTORLENGTH),
+            "vector-slice" => Some(Self::VECTORSLICE),
+            "vector->list" => Some(Self::VECTORTOLIST),
+            "list->vector" => Some(Self::LISTTOVECTOR),
+            "length" => Some(Self::LENGTH),
+            "set" => Some(Self::SET),
+            "set?" => Some(Self::SETP),
+            "set-add" => Some(Self::SETADD),
+            "set-remove" => Some(Self::SETREMOVE),
+            "member?" => Some(Self::MEMBERP),
+            "union" => Some(Self::UNION),
+            "intersection" => Some(Self::INTERSECTION),
+            "difference" => Some(Self::DIFFERENCE),
+            "subset?" => Some(Self::SUBSETP),
+            "set->list" => Some(Self::SETTOLIST),
+            "char?" => Some(Self::CHARP),
+            "char->integer" => Some(Self::CHARTOINTEGER),
+            "integer->char" => Some(Self::INTEGERTOCHAR),
+            "char-upcase" => Some(Self::CHARUPCASE),
+            "char-downcase" => Some(Self::CHARDOWNCASE),
+            "char-alphabetic?" => Some(Self::CHARALPHABETICP),
+            "char-numeric?" => Some(Self::CHARNUMERICP),
+            "char-whitespace?" => Some(Self::CHARWHITESPACEP),
+            "char-upper-case?" => Some(Self::CHARUPPERCASEP),
+            "char-lower-case?" => Some(Self::CHARLOWERCASEP),
+            "string-ref" => Some(Self::STRINGREF),
+            "string-length" => Some(Self::STRINGLENGTH),
+            "substring" => Some(Self::SUBSTRING),
+            "string-index" => Some(Self::STRINGINDEX),
+            "string-split" => Some(Self::STRINGSPLIT),
+            "string-join" => Some(Self::STRINGJOIN),
+            "string-trim" => Some(Self::STRINGTRIM),
+            "string-upcase" => Some(Self::STRINGUPCASE),
+            "string-downcase" => Some(Self::STRINGDOWNCASE),
+            "string-replace" => Some(Self::STRINGREPLACE),
+            "string-contains?" => Some(Self::STRINGCONTAINSP),
+            "string-starts-with?" => Some(Self::STRINGSTARTSWITHP),
+            "string-ends-with?" => Some(Self::STRINGENDSWITHP),
+            "string->number" => Some(Self::STRINGTONUMBER),
+            "number->string" => Some(Self::NUMBERTOSTRING),
+            "string->symbol" => Some(Self::STRINGTOSYMBOL),
+            "symbol->string" => Some(Self::SYMBOLTOSTRING),
+            "string->list" => Some(Self::STRINGTOLIST),
+            "list->string" => Some(Self::LISTTOSTRING),
+            "format" => Some(Self::FORMAT),
+            "regex" => Some(Self::REGEX),
+            "regex?" => Some(Self::REGEXP),
+            "regex-match" => Some(Self::REGEXMATCH),
+            "regex-find-all" => Some(Self::REGEXFINDALL),
+            "regex-replace" => Some(Self::REGEXREPLACE),
+            "regex-split" => Some(Self::REGEXSPLIT),
+            "map" => Some(Self::MAP),
+            "for-each" => Some(Self::FOREACH),
+            "filter" => Some(Self::FILTER),
+            "remove" => Some(Self::REMOVE),
+            "reduce" => Some(Self::REDUCE),
+            "fold-left" => Some(Self::FOLDLEFT),
+            "fold-right" => Some(Self::FOLDRIGHT),
+            "apply" => Some(Self::APPLY),
+            "append" => Some(Self::APPEND),
+            "reverse" => Some(Self::REVERSE),
+            "nth" => Some(Self::NTH),
+            "last" => Some(Self::LAST),
+            "take" => Some(Self::TAKE),
+            "drop" => Some(Self::DROP),
+            "zip" => Some(Self::ZIP),
+            "range" => Some(Self::RANGE),
+            "assoc" => Some(Self::ASSOC),
+            "member" => Some(Self::MEMBER),
+            "sort" => Some(Self::SORT),
+            "not" => Some(Self::NOT),
+            "gensym" => Some(Self::GENSYM),
+            _ => None,
+        }
+    }
+
+    pub fn to_string(&self) -> &'static str {
+        match self {
+            Self::CONS => "cons",
+            Self::CAR => "car",
+            Self::CDR => "cdr",
+            Self::LIST => "list",
+            Self::LISTP => "list?",
+            Self::PAIRP => "pair?",
+            Self::EQP => "eq?",
+            Self::EQVP => "eqv?",
+            Self::EQUALP => "equal?",
+            Self::HASHMAP => "hash-map",
+            Self::HASHGET => "hash-get",
+            Self::HASHSET => "hash-set",
+            Self::HASHREMOVE => "hash-remove",
+            Self::HASHCONTAINSP => "hash-contains?",
+            Self::HASHCOUNT => "hash-count",
+            Self::HASHKEYS => "hash-keys",
+            Self::HASHVALUES => "hash-values",
+            Self::HASHMERGE => "hash-merge",
+            Self::HASHTOLIST => "hash->list",
+            Self::VECTOR => "vector",
+            Self::MAKEVECTOR => "make-vector",
+            Self::VECTORP => "vector?",
+            Self::VECTORREF => "vector-ref",
+            Self::VECTORSET => "vector-set!",
+            Self::VECTORLENGTH => "vector-length",
+            Self::VECTORSLICE => "vector-slice",
+            Self::VECTORTOLIST => "vector->list",
+            Self::LISTTOVECTOR => "list->vector",
+            Self::LENGTH => "length",
+            Self::SET => "set",
+            Self::SETP => "set?",
+            Self::SETADD => "set-add",
+            Self::SETREMOVE => "set-remove",
+            Self::MEMBERP => "member?",
+            Self::UNION => "union",
+            Self::INTERSECTION => "intersection",
+            Self::DIFFERENCE => "difference",
+            Self::SUBSETP => "subset?",
+            Self::SETTOLIST => "set->list",
+            Self::CHARP => "char?",
+            Self::CHARTOINTEGER => "char->integer",
+            Self::INTEGERTOCHAR => "integer->char",
+            Self::CHARUPCASE => "char-upcase",
+            Self::CHARDOWNCASE => "char-downcase",
+            Self::CHARALPHABETICP => "char-alphabetic?",
+            Self::CHARNUMERICP => "char-numeric?",
+            Self::CHARWHITESPACEP => "char-whitespace?",
+            Self::CHARUPPERCASEP => "char-upper-case?",
+            Self::CHARLOWERCASEP => "char-lower-case?",
+            Self::STRINGREF => "string-ref",
+            Self::STRINGLENGTH => "string-length",
+            Self::SUBSTRING => "substring",
+            Self::STRINGINDEX => "string-index",
+            Self::STRINGSPLIT => "string-split",
+            Self::STRINGJOIN => "string-join",
+            Self::STRINGTRIM => "string-trim",
+            Self::STRINGUPCASE => "string-upcase",
+            Self::STRINGDOWNCASE => "string-downcase",
+            Self::STRINGREPLACE => "string-replace",
+            Self::STRINGCONTAINSP => "string-contains?",
+            Self::STRINGSTARTSWITHP => "string-starts-with?",
+            Self::STRINGENDSWITHP => "string-ends-with?",
+            Self::STRINGTONUMBER => "string->number",
+            Self::NUMBERTOSTRING => "number->string",
+            Self::STRINGTOSYMBOL => "string->symbol",
+            Self::SYMBOLTOSTRING => "symbol->string",
+            Self::STRINGTOLIST => "string->list",
+            Self::LISTTOSTRING => "list->string",
+            Self::FORMAT => "format",
+            Self::REGEX => "regex",
+            Self::REGEXP => "regex?",
+            Self::REGEXMATCH => "regex-match",
+            Self::REGEXFINDALL => "regex-find-all",
+            Self::REGEXREPLACE => "regex-replace",
+            Self::REGEXSPLIT => "regex-split",
+            Self::MAP => "map",
+            Self::FOREACH => "for-each",
+            Self::FILTER => "filter",
+            Self::REMOVE => "remove",
+            Self::REDUCE => "reduce",
+            Self::FOLDLEFT => "fold-left",
+            Self::FOLDRIGHT => "fold-right",
+            Self::APPLY => "apply",
+            Self::APPEND => "append",
+            Self::REVERSE => "reverse",
+            Self::NTH => "nth",
+            Self::LAST => "last",
+            Self::TAKE => "take",
+            Self::DROP => "drop",
+            Self::ZIP => "zip",
+            Self::RANGE => "range",
+            Self::ASSOC => "assoc",
+            Self::MEMBER => "member",
+            Self::SORT => "sort",
+            Self::NOT => "not",
+            Self::GENSYM => "gensym",
         }
     }
 }
@@ -1,7 +1,7 @@
-(def i 10)
+(def fact
+  (lambda (n)
+    (let lp ((i n) (r 1))
+      (cond (< i 1) r
+            (lp (- i 1) (* r i))))))
 
-(def cmd-1 ('(set! i (- i 1))))
-
-(eval cmd-1)
-
-(def func ('(cond (< i 1) (print "end") (do (set! r (* r i)) (set! i (- i 1)) (eval func) ))))
+(print (fact 10))